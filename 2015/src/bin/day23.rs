@@ -1,13 +1,15 @@
-use std::{convert::TryFrom, str::FromStr};
+use std::fmt;
+use std::str::FromStr;
 
 use anyhow::{bail, Context, Error, Result};
 use aoc2015::dispatch;
+use machine::{parse_program, split, Flow, Instruction, Machine, Registers};
 
 fn main() -> Result<()> {
     dispatch(part1, part2)
 }
 
-#[derive(Debug, Hash, PartialEq, Eq, Copy, Clone)]
+#[derive(Debug, Hash, PartialEq, Eq, PartialOrd, Ord, Copy, Clone)]
 enum Register {
     A,
     B,
@@ -16,12 +18,19 @@ enum Register {
 impl FromStr for Register {
     type Err = Error;
     fn from_str(value: &str) -> Result<Self> {
-        if value.starts_with('a') {
-            Ok(Register::A)
-        } else if value.starts_with('b') {
-            Ok(Register::B)
-        } else {
-            bail!("no reg match: {}", value)
+        match value {
+            "a" => Ok(Register::A),
+            "b" => Ok(Register::B),
+            _ => bail!("no reg match: {}", value),
+        }
+    }
+}
+
+impl fmt::Display for Register {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Register::A => write!(f, "a"),
+            Register::B => write!(f, "b"),
         }
     }
 }
@@ -36,99 +45,59 @@ enum Op {
     JumpIfOne(Register, i64),
 }
 
-impl TryFrom<&str> for Op {
-    type Error = Error;
+impl FromStr for Op {
+    type Err = Error;
 
-    fn try_from(value: &str) -> Result<Self, Self::Error> {
-        if let Some(rest) = value.strip_prefix("hlf ") {
-            return Ok(Op::Half(rest.parse().context("no register")?));
-        }
-        if let Some(rest) = value.strip_prefix("tpl ") {
-            return Ok(Op::Triple(rest.parse().context("no register")?));
-        }
-        if let Some(rest) = value.strip_prefix("inc ") {
-            return Ok(Op::Inc(rest.parse().context("no register")?));
-        }
-        if let Some(rest) = value.strip_prefix("jmp ") {
-            return Ok(Op::Jump(rest.parse().context("no offset")?));
-        }
-        if let Some(rest) = value.strip_prefix("jio ") {
-            let (reg_raw, offset_raw) = rest.split_once(' ').context("no split")?;
-            return Ok(Op::JumpIfOne(
-                reg_raw.parse().context("bad reg")?,
-                offset_raw.parse().context("bad offset")?,
-            ));
-        }
-        if let Some(rest) = value.strip_prefix("jie ") {
-            let (reg_raw, offset_raw) = rest.split_once(' ').context("no split")?;
-            return Ok(Op::JumpIfEven(
-                reg_raw.parse().context("bad reg")?,
-                offset_raw.parse().context("bad offset")?,
-            ));
-        }
-        bail!("no match: {}", value)
+    fn from_str(value: &str) -> Result<Self> {
+        Ok(match split(value) {
+            ("hlf", args) if args.len() == 1 => Op::Half(args[0].parse()?),
+            ("tpl", args) if args.len() == 1 => Op::Triple(args[0].parse()?),
+            ("inc", args) if args.len() == 1 => Op::Inc(args[0].parse()?),
+            ("jmp", args) if args.len() == 1 => Op::Jump(args[0].parse().context("bad offset")?),
+            ("jio", args) if args.len() == 2 => {
+                Op::JumpIfOne(args[0].parse()?, args[1].parse().context("bad offset")?)
+            }
+            ("jie", args) if args.len() == 2 => {
+                Op::JumpIfEven(args[0].parse()?, args[1].parse().context("bad offset")?)
+            }
+            _ => bail!("no match: {}", value),
+        })
     }
 }
 
-#[derive(Debug, Default)]
-struct Registers {
-    a: i64,
-    b: i64,
-}
-
-impl Registers {
-    fn get(&self, register: Register) -> i64 {
-        match register {
-            Register::A => self.a,
-            Register::B => self.b,
-        }
-    }
-
-    fn set(&mut self, register: Register, value: i64) {
-        match register {
-            Register::A => self.a = value,
-            Register::B => self.b = value,
+impl Instruction for Op {
+    type State = Registers<Register>;
+
+    fn execute(&self, registers: &mut Self::State) -> Flow {
+        match *self {
+            Op::Half(reg) => registers.update(&reg, |v| v / 2),
+            Op::Triple(reg) => registers.update(&reg, |v| v * 3),
+            Op::Inc(reg) => registers.update(&reg, |v| v + 1),
+            Op::Jump(offset) => return Flow::Jump(offset),
+            Op::JumpIfEven(reg, offset) if registers.get(&reg) % 2 == 0 => {
+                return Flow::Jump(offset)
+            }
+            Op::JumpIfOne(reg, offset) if registers.get(&reg) == 1 => return Flow::Jump(offset),
+            Op::JumpIfEven(..) | Op::JumpIfOne(..) => {}
         }
+        Flow::Next
     }
 }
 
-fn run(ops: &[Op], registers: Registers) -> i64 {
-    let mut pc = 0;
-    let mut registers = registers;
-    while let Some(op) = ops.get(pc) {
-        // dbg!(pc, op, &registers);
-        match op {
-            Op::Half(reg) => registers.set(*reg, registers.get(*reg) / 2),
-            Op::Triple(reg) => registers.set(*reg, registers.get(*reg) * 3),
-            Op::Inc(reg) => registers.set(*reg, registers.get(*reg) + 1),
-            Op::Jump(offset) => pc += *offset as usize - 1,
-            Op::JumpIfEven(reg, offset) => {
-                if registers.get(*reg) % 2 == 0 {
-                    pc += *offset as usize - 1
-                }
-            }
-            Op::JumpIfOne(reg, offset) => {
-                if registers.get(*reg) == 1 {
-                    pc += *offset as usize - 1
-                }
-            }
-        }
-        pc += 1;
-    }
-    registers.get(Register::B)
+fn run(input: &str, a: i64) -> Result<Registers<Register>> {
+    let mut registers = Registers::default();
+    registers.set(&Register::A, a);
+    let mut machine = Machine::new(parse_program::<Op>(input)?, registers);
+    machine.run();
+    Ok(machine.state)
 }
 
 fn part1(input: &str) -> Result<i64> {
-    let ops: Vec<_> = input.split('\n').map(Op::try_from).collect::<Result<_>>()?;
-    let registers = Registers::default();
-    Ok(run(&ops, registers))
+    Ok(run(input, 0)?.get(&Register::B))
 }
 
 fn part2(input: &str) -> Result<i64> {
-    let ops: Vec<_> = input.split('\n').map(Op::try_from).collect::<Result<_>>()?;
-    let mut registers = Registers::default();
-    registers.set(Register::A, 1);
-    Ok(run(&ops, registers))
+    Ok(run(input, 1)?.get(&Register::B))
 }
 
 #[cfg(test)]
@@ -145,4 +114,10 @@ inc a";
         assert_eq!(part1(INPUT)?, 0);
         Ok(())
     }
+
+    #[test]
+    fn test_registers() -> Result<()> {
+        assert_eq!(run(INPUT, 0)?.get(&Register::A), 2);
+        Ok(())
+    }
 }
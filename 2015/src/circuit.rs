@@ -0,0 +1,403 @@
+// a circuit of 16 bit logic gates. parsed into a dag and evaluated in
+// topological order, with support for overriding the value on any wire
+use anyhow::{bail, Context, Error, Result};
+use std::collections::{HashMap, HashSet, VecDeque};
+use std::fmt::{self, Write};
+use std::str::FromStr;
+
+pub type Wire = String;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Source {
+    Value(u16),
+    Wire(Wire),
+}
+
+impl Source {
+    fn wire(&self) -> Option<&Wire> {
+        match self {
+            Source::Value(_) => None,
+            Source::Wire(wire) => Some(wire),
+        }
+    }
+}
+
+impl From<&str> for Source {
+    fn from(s: &str) -> Source {
+        if let Ok(n) = s.parse() {
+            Source::Value(n)
+        } else {
+            Source::Wire(s.into())
+        }
+    }
+}
+
+impl fmt::Display for Source {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Source::Value(value) => write!(f, "{}", value),
+            Source::Wire(wire) => write!(f, "{}", wire),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Gate {
+    Direct(Source),
+    And(Source, Source),
+    Or(Source, Source),
+    Not(Source),
+    Lshift(Source, Source),
+    Rshift(Source, Source),
+}
+
+impl Gate {
+    fn inputs(&self) -> Vec<&Source> {
+        use Gate::*;
+        match self {
+            Direct(s) | Not(s) => vec![s],
+            And(s1, s2) | Or(s1, s2) | Lshift(s1, s2) | Rshift(s1, s2) => vec![s1, s2],
+        }
+    }
+
+    fn name(&self) -> &'static str {
+        use Gate::*;
+        match self {
+            Direct(_) => "",
+            And(..) => "AND",
+            Or(..) => "OR",
+            Not(_) => "NOT",
+            Lshift(..) => "LSHIFT",
+            Rshift(..) => "RSHIFT",
+        }
+    }
+
+    // all inputs must already have values
+    fn apply(&self, values: &HashMap<Wire, u16>) -> u16 {
+        use Gate::*;
+        let value = |source: &Source| match source {
+            Source::Value(v) => *v,
+            Source::Wire(wire) => values[wire],
+        };
+        match self {
+            Direct(s) => value(s),
+            And(s1, s2) => value(s1) & value(s2),
+            Or(s1, s2) => value(s1) | value(s2),
+            Lshift(s1, s2) => value(s1).checked_shl(value(s2) as u32).unwrap_or(0),
+            Rshift(s1, s2) => value(s1).checked_shr(value(s2) as u32).unwrap_or(0),
+            Not(s) => !value(s),
+        }
+    }
+}
+
+impl fmt::Display for Gate {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        use Gate::*;
+        match self {
+            Direct(s) => write!(f, "{}", s),
+            Not(s) => write!(f, "NOT {}", s),
+            And(s1, s2) | Or(s1, s2) | Lshift(s1, s2) | Rshift(s1, s2) => {
+                write!(f, "{} {} {}", s1, self.name(), s2)
+            }
+        }
+    }
+}
+
+fn parse_line(s: &str) -> Result<(Gate, Wire)> {
+    let (source, target) = s.split_once(" -> ").context("no arrow")?;
+    let gate = if let Some((left, right)) = source.split_once(" AND ") {
+        Gate::And(left.into(), right.into())
+    } else if let Some((left, right)) = source.split_once(" OR ") {
+        Gate::Or(left.into(), right.into())
+    } else if let Some((left, right)) = source.split_once(" LSHIFT ") {
+        Gate::Lshift(left.into(), right.into())
+    } else if let Some((left, right)) = source.split_once(" RSHIFT ") {
+        Gate::Rshift(left.into(), right.into())
+    } else if let Some(value) = source.strip_prefix("NOT ") {
+        Gate::Not(value.into())
+    } else if source.contains(' ') {
+        bail!("unknown gate: `{}`", source)
+    } else {
+        Gate::Direct(source.into())
+    };
+    Ok((gate, target.into()))
+}
+
+#[derive(Debug)]
+pub struct Circuit {
+    gates: HashMap<Wire, Gate>,
+    // wires in topological order
+    order: Vec<Wire>,
+    dependents: HashMap<Wire, Vec<Wire>>,
+    overrides: HashMap<Wire, u16>,
+    values: HashMap<Wire, u16>,
+}
+
+impl Circuit {
+    pub fn new(gates: Vec<(Gate, Wire)>) -> Result<Self> {
+        let mut by_wire = HashMap::new();
+        for (gate, wire) in gates {
+            if by_wire.insert(wire.clone(), gate).is_some() {
+                bail!("wire `{}` has more than one driver", wire);
+            }
+        }
+
+        let mut dependents: HashMap<Wire, Vec<Wire>> = HashMap::new();
+        let mut waiting_on = HashMap::new();
+        let mut wires: Vec<_> = by_wire.keys().collect();
+        wires.sort();
+        for wire in wires {
+            let mut inputs: Vec<_> = by_wire[wire]
+                .inputs()
+                .into_iter()
+                .filter_map(Source::wire)
+                .collect();
+            inputs.dedup();
+            for &input in &inputs {
+                if !by_wire.contains_key(input) {
+                    bail!("undefined wire `{}` (used by `{}`)", input, wire);
+                }
+                dependents
+                    .entry(input.clone())
+                    .or_default()
+                    .push(wire.clone());
+            }
+            waiting_on.insert(wire.clone(), inputs.len());
+        }
+
+        let mut ready: Vec<_> = waiting_on
+            .iter()
+            .filter(|(_, &count)| count == 0)
+            .map(|(wire, _)| wire.clone())
+            .collect();
+        ready.sort();
+        let mut ready: VecDeque<_> = ready.into();
+        let mut order = vec![];
+        while let Some(wire) = ready.pop_front() {
+            for dependent in dependents.get(&wire).into_iter().flatten() {
+                let count = waiting_on.get_mut(dependent).unwrap();
+                *count -= 1;
+                if *count == 0 {
+                    ready.push_back(dependent.clone());
+                }
+            }
+            order.push(wire);
+        }
+        if order.len() < by_wire.len() {
+            let mut cycle: Vec<_> = waiting_on
+                .into_iter()
+                .filter(|(_, count)| *count > 0)
+                .map(|(wire, _)| wire)
+                .collect();
+            cycle.sort();
+            bail!("cycle involving wires: {}", cycle.join(", "));
+        }
+
+        let mut circuit = Circuit {
+            gates: by_wire,
+            order,
+            dependents,
+            overrides: HashMap::new(),
+            values: HashMap::new(),
+        };
+        let all = circuit.order.clone();
+        circuit.evaluate(&all);
+        Ok(circuit)
+    }
+
+    // `wires` must be in topological order
+    fn evaluate(&mut self, wires: &[Wire]) {
+        for wire in wires {
+            let value = match self.overrides.get(wire) {
+                Some(&value) => value,
+                None => self.gates[wire].apply(&self.values),
+            };
+            self.values.insert(wire.clone(), value);
+        }
+    }
+
+    pub fn get(&self, wire: &str) -> Result<u16> {
+        self.values
+            .get(wire)
+            .copied()
+            .with_context(|| format!("undefined wire `{}`", wire))
+    }
+
+    // everything downstream of `wire`, including itself, in topological order
+    fn downstream(&self, wire: &str) -> Vec<Wire> {
+        let mut affected = HashSet::new();
+        let mut stack = vec![wire.to_string()];
+        while let Some(wire) = stack.pop() {
+            if affected.insert(wire.clone()) {
+                stack.extend(self.dependents.get(&wire).into_iter().flatten().cloned());
+            }
+        }
+        self.order
+            .iter()
+            .filter(|wire| affected.contains(*wire))
+            .cloned()
+            .collect()
+    }
+
+    // forces `wire` to `value`, ignoring its gate, and re-evaluates the wires
+    // that depend on it. returns how many wires were re-evaluated
+    pub fn set_override(&mut self, wire: &str, value: u16) -> Result<usize> {
+        if !self.gates.contains_key(wire) {
+            bail!("undefined wire `{}`", wire);
+        }
+        self.overrides.insert(wire.into(), value);
+        let affected = self.downstream(wire);
+        self.evaluate(&affected);
+        Ok(affected.len())
+    }
+
+    pub fn clear_override(&mut self, wire: &str) -> Result<usize> {
+        if self.overrides.remove(wire).is_none() {
+            bail!("wire `{}` isn't overridden", wire);
+        }
+        let affected = self.downstream(wire);
+        self.evaluate(&affected);
+        Ok(affected.len())
+    }
+
+    // graphviz dot, with gates as boxes between the wires
+    pub fn to_dot(&self) -> String {
+        let mut dot = String::new();
+        writeln!(dot, "digraph circuit {{").unwrap();
+        for wire in &self.order {
+            let gate = &self.gates[wire];
+            let label = match self.overrides.get(wire) {
+                Some(value) => format!("{} = {} (override)", wire, value),
+                None => format!("{} = {}", wire, self.values[wire]),
+            };
+            writeln!(dot, "  \"{}\" [label=\"{}\"];", wire, label).unwrap();
+            let target = if let Gate::Direct(_) = gate {
+                wire.clone()
+            } else {
+                let node = format!("{}_gate", wire);
+                writeln!(
+                    dot,
+                    "  \"{}\" [shape=box, label=\"{}\"];",
+                    node,
+                    gate.name()
+                )
+                .unwrap();
+                writeln!(dot, "  \"{}\" -> \"{}\";", node, wire).unwrap();
+                node
+            };
+            for (index, input) in gate.inputs().into_iter().enumerate() {
+                let input = match input {
+                    Source::Wire(input) => input.clone(),
+                    Source::Value(value) => {
+                        let node = format!("{}_{}", wire, index);
+                        writeln!(
+                            dot,
+                            "  \"{}\" [shape=plaintext, label=\"{}\"];",
+                            node, value
+                        )
+                        .unwrap();
+                        node
+                    }
+                };
+                writeln!(dot, "  \"{}\" -> \"{}\";", input, target).unwrap();
+            }
+        }
+        dot.push_str("}\n");
+        dot
+    }
+}
+
+impl FromStr for Circuit {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        let gates = s
+            .lines()
+            .enumerate()
+            .filter(|(_, line)| !line.trim().is_empty())
+            .map(|(index, line)| {
+                parse_line(line.trim()).with_context(|| format!("line {}", index + 1))
+            })
+            .collect::<Result<_>>()?;
+        Circuit::new(gates)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const INPUT: &str = "123 -> x
+456 -> y
+x AND y -> d
+x OR y -> e
+x LSHIFT 2 -> f
+y RSHIFT 2 -> g
+NOT x -> h
+NOT y -> i";
+
+    #[test]
+    fn test_evaluate() -> Result<()> {
+        let circuit: Circuit = INPUT.parse()?;
+        for (wire, expected) in &[
+            ("d", 72),
+            ("e", 507),
+            ("f", 492),
+            ("g", 114),
+            ("h", 65412),
+            ("i", 65079),
+            ("x", 123),
+            ("y", 456),
+        ] {
+            assert_eq!(circuit.get(wire)?, *expected);
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn test_errors() {
+        let err = |input: &str| format!("{:#}", input.parse::<Circuit>().unwrap_err());
+        assert_eq!(
+            err("a -> b\nb -> c\nc -> a\n1 -> d"),
+            "cycle involving wires: a, b, c"
+        );
+        assert_eq!(err("x AND q -> d"), "undefined wire `x` (used by `d`)");
+        assert_eq!(err("1 -> a\n2 -> a"), "wire `a` has more than one driver");
+        assert_eq!(
+            err("1 -> a\n1 XOR a -> b"),
+            "line 2: unknown gate: `1 XOR a`"
+        );
+    }
+
+    #[test]
+    fn test_override() -> Result<()> {
+        let mut circuit: Circuit = INPUT.parse()?;
+        assert_eq!(circuit.set_override("y", 1)?, 5);
+        assert_eq!(circuit.get("d")?, 1);
+        assert_eq!(circuit.get("e")?, 123);
+        assert_eq!(circuit.get("f")?, 492);
+        assert_eq!(circuit.clear_override("y")?, 5);
+        assert_eq!(circuit.get("d")?, 72);
+        assert!(circuit.set_override("nope", 1).is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_dot() -> Result<()> {
+        let circuit: Circuit = "1 -> x\nNOT x -> y".parse()?;
+        assert_eq!(
+            circuit.to_dot(),
+            "digraph circuit {
+  \"x\" [label=\"x = 1\"];
+  \"x_0\" [shape=plaintext, label=\"1\"];
+  \"x_0\" -> \"x\";
+  \"y\" [label=\"y = 65534\"];
+  \"y_gate\" [shape=box, label=\"NOT\"];
+  \"y_gate\" -> \"y\";
+  \"x\" -> \"y_gate\";
+}
+"
+        );
+        Ok(())
+    }
+}
@@ -1,3 +1,4 @@
+pub mod circuit;
 pub mod coor;
 pub mod shared;
 pub use crate::shared::dispatch;
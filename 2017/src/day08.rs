@@ -1,6 +1,7 @@
-use std::collections::{HashMap};
 use std::cmp;
 use std::str::{self, FromStr};
+use failure::Error;
+use machine::{self, parse_program, Flow, Machine, Registers};
 use shared::AppResult;
 use nom::{IResult, alpha, digit, space};
 
@@ -86,9 +87,9 @@ named!(program <Program>,
     )
 );
 
-fn condition(left: i32, op: ConditionOp, right: i32) -> bool {
+fn condition(left: i64, op: &ConditionOp, right: i64) -> bool {
     use self::ConditionOp::*;
-    match op {
+    match *op {
         Eq => left == right,
         Gt => left > right,
         Gte => left >= right,
@@ -98,43 +99,51 @@ fn condition(left: i32, op: ConditionOp, right: i32) -> bool {
     }
 }
 
-pub fn process(input: &str) -> AppResult<(u32, u32)> {
-    let mut registers = HashMap::new();
-    let mut maxduring = 0;
 
-    for line in input.split('\n') {
-        // println!("::{}::", line);
-        let instruction = match program(line.as_bytes()) {
-            IResult::Done(_, instruction) => instruction,
+impl FromStr for Program {
+    type Err = Error;
+
+    fn from_str(s: &str) -> AppResult<Self> {
+        match program(s.as_bytes()) {
+            IResult::Done(_, instruction) => Ok(instruction),
             err => bail!("Failed to parse instruction: {:?}", err),
-        };
-        let &condition_register_value = registers
-            .get(&instruction.condition_register)
-            .unwrap_or(&0);
+        }
+    }
+}
+
+
+impl machine::Instruction for Program {
+    type State = Registers<String>;
+
+    fn execute(&self, registers: &mut Self::State) -> Flow {
         if condition(
-                condition_register_value,
-                instruction.condition_op,
-                instruction.condition_value,
+                registers.get(&self.condition_register),
+                &self.condition_op,
+                self.condition_value as i64,
             ) {
-
-            let mut register_value = registers
-                .entry(instruction.register)
-                .or_insert(0);
-            match instruction.operation {
-                Operation::Increment => {*register_value += instruction.value}
-                Operation::Decrement => {*register_value -= instruction.value}
+            let value = self.value as i64;
+            match self.operation {
+                Operation::Increment => registers.update(&self.register, |v| v + value),
+                Operation::Decrement => registers.update(&self.register, |v| v - value),
             }
         }
-        maxduring = match registers.values().max() {
-            Some(&n) => cmp::max(maxduring, n),
-            None => maxduring,
-        };
+        Flow::Next
     }
-    let maxend = match registers.values().max() {
-        Some(&n) => n as u32,
+}
+
+
+pub fn process(input: &str) -> AppResult<(u32, u32)> {
+    let mut machine = Machine::new(parse_program::<Program>(input)?, Registers::default());
+    let mut maxduring = 0;
+    machine.run_with(|tick| {
+        maxduring = cmp::max(maxduring, tick.state.max().unwrap_or(0));
+    });
+    let maxend = match machine.state.max() {
+        Some(n) => n,
         None => bail!("no registers"),
     };
-    Ok((maxend, maxduring as u32))
+    maxduring = cmp::max(maxduring, maxend);
+    Ok((maxend as u32, maxduring as u32))
 }
 
 
@@ -2,6 +2,7 @@
 
 extern crate clap;
 #[macro_use] extern crate failure;
+extern crate machine;
 // extern crate itertools;
 #[macro_use] extern crate nom;
 
@@ -54,7 +54,7 @@ fn size(points: &Vec<Point>) -> i64 {
     let (minx, maxx, miny, maxy) = bounding_box(points);
     (maxx - minx) * (maxy - miny)
 }
-fn bounding_box(points: &Vec<Point>) -> (i64, i64, i64, i64) {
+fn bounding_box(points: &[Point]) -> (i64, i64, i64, i64) {
     let maxx = points.iter().map(|p| p.px).max().unwrap();
     let minx = points.iter().map(|p| p.px).min().unwrap();
     let maxy = points.iter().map(|p| p.py).max().unwrap();
@@ -74,7 +74,8 @@ fn mv_back(points: &mut Vec<Point>) {
     }
 }
 
-fn part1(input: &str) -> Result<i64> {
+// moves the points until their bounding box stops shrinking
+fn converge(input: &str) -> (Vec<Point>, i64) {
     let mut coors: Vec<Point> = input
         .split('\n')
         .filter_map(|row| row.parse().ok())
@@ -90,23 +91,34 @@ fn part1(input: &str) -> Result<i64> {
         bb2 = size(&coors);
     }
     mv_back(&mut coors);
-    let taken: HashSet<_> = coors.iter().map(|p| (p.px, p.py)).collect();
-    let (minx, maxx, miny, maxy) = bounding_box(&coors);
+    (coors, steps)
+}
+
+fn render(points: &[Point]) -> String {
+    let taken: HashSet<_> = points.iter().map(|p| (p.px, p.py)).collect();
+    let (minx, maxx, miny, maxy) = bounding_box(points);
+    let mut output = String::new();
     for y in miny..=maxy {
         for x in minx..=maxx {
             if taken.contains(&(x, y)) {
-                print!("#");
+                output.push('#');
             } else {
-                print!(".");
+                output.push('.');
             }
         }
-        println!("");
+        output.push('\n');
     }
-    Ok(steps)
+    output
+}
+
+fn part1(input: &str) -> Result<String> {
+    let (points, _) = converge(input);
+    Ok(ocr::read(&render(&points))?)
 }
 
 fn part2(input: &str) -> Result<i64> {
-    part1(input)
+    let (_, steps) = converge(input);
+    Ok(steps)
 }
 
 #[cfg(test)]
@@ -161,7 +173,25 @@ position=<-3,  6> velocity=< 2, -1>";
     }
 
     #[test]
-    fn test_part1() -> Result<()> {
-        Ok(assert_eq!(part1(INPUT)?, 3))
+    fn test_part2() -> Result<()> {
+        Ok(assert_eq!(part2(INPUT)?, 3))
+    }
+
+    #[test]
+    fn test_render() {
+        let (points, _) = converge(INPUT);
+        assert_eq!(
+            render(&points),
+            "\
+#...#..###
+#...#...#.
+#...#...#.
+#####...#.
+#...#...#.
+#...#...#.
+#...#...#.
+#...#..###
+"
+        );
     }
 }
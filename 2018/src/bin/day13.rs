@@ -1,6 +1,8 @@
 use aoc2018::{dispatch, Result};
-use failure::err_msg;
-use std::collections::HashMap;
+use failure::{bail, err_msg, format_err, Error};
+use std::collections::{HashMap, HashSet};
+use std::fmt;
+use std::str::FromStr;
 
 fn main() {
     dispatch(&part1, &part2)
@@ -15,7 +17,20 @@ enum Track {
     Intersection,
 }
 
-#[derive(Debug, Clone, Copy)]
+impl Track {
+    fn symbol(self) -> char {
+        use self::Track::*;
+        match self {
+            Horizontal => '-',
+            Vertical => '|',
+            TopLeft => '\\',
+            TopRight => '/',
+            Intersection => '+',
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
 enum TrainDirection {
     Up,
     Down,
@@ -23,34 +38,118 @@ enum TrainDirection {
     Right,
 }
 
+impl TrainDirection {
+    fn symbol(self) -> char {
+        use self::TrainDirection::*;
+        match self {
+            Up => '^',
+            Down => 'v',
+            Left => '<',
+            Right => '>',
+        }
+    }
+
+    fn turn(self, turn: Turn) -> TrainDirection {
+        use self::TrainDirection::*;
+        match turn {
+            Turn::Straight => self,
+            Turn::Left => match self {
+                Up => Left,
+                Down => Right,
+                Left => Down,
+                Right => Up,
+            },
+            Turn::Right => match self {
+                Up => Right,
+                Down => Left,
+                Left => Up,
+                Right => Down,
+            },
+        }
+    }
+}
+
 #[derive(Debug, Clone, Copy)]
 struct Input {
     track: Track,
     train: Option<TrainDirection>,
 }
 
-#[derive(Debug, Clone, Copy)]
-enum IntersectionStrategy {
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Turn {
     Left,
     Straight,
     Right,
 }
 
+// decides which way a train goes at an intersection, given the train's id and
+// how many intersections it has already crossed
+trait IntersectionStrategy {
+    fn turn(&self, train: usize, crossed: usize) -> Turn;
+}
+
+impl<F: Fn(usize, usize) -> Turn> IntersectionStrategy for F {
+    fn turn(&self, train: usize, crossed: usize) -> Turn {
+        self(train, crossed)
+    }
+}
+
+// every train goes through the same sequence of turns, repeating
+struct Cycle(Vec<Turn>);
+
+impl Cycle {
+    fn new(turns: Vec<Turn>) -> Result<Self> {
+        if turns.is_empty() {
+            bail!("a cycle of turns can't be empty");
+        }
+        Ok(Cycle(turns))
+    }
+
+    fn left_straight_right() -> Self {
+        Cycle(vec![Turn::Left, Turn::Straight, Turn::Right])
+    }
+}
+
+// one letter per turn, e.g. `LSR`
+impl FromStr for Cycle {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        Cycle::new(
+            s.chars()
+                .map(|c| match c {
+                    'L' => Ok(Turn::Left),
+                    'S' => Ok(Turn::Straight),
+                    'R' => Ok(Turn::Right),
+                    _ => Err(format_err!("invalid turn: `{}`", c)),
+                })
+                .collect::<Result<_>>()?,
+        )
+    }
+}
+
+impl IntersectionStrategy for Cycle {
+    fn turn(&self, _train: usize, crossed: usize) -> Turn {
+        self.0[crossed % self.0.len()]
+    }
+}
+
 #[derive(Debug, Clone, Copy)]
 struct Train {
+    id: usize,
     direction: TrainDirection,
-    intersection_strategy: IntersectionStrategy,
+    crossed: usize,
 }
 
 impl Train {
-    fn new(direction: TrainDirection) -> Self {
-        use self::IntersectionStrategy::*;
-        let intersection_strategy = Left;
+    fn new(id: usize, direction: TrainDirection) -> Self {
         Train {
+            id,
             direction,
-            intersection_strategy,
+            crossed: 0,
         }
     }
+
     fn step(&self, pos: (usize, usize)) -> (usize, usize) {
         use self::TrainDirection::*;
         match self.direction {
@@ -61,35 +160,7 @@ impl Train {
         }
     }
 
-    fn intersection_turn(&mut self) -> TrainDirection {
-        use self::TrainDirection::*;
-        match self.intersection_strategy {
-            IntersectionStrategy::Straight => {
-                self.intersection_strategy = IntersectionStrategy::Right;
-                self.direction
-            }
-            IntersectionStrategy::Left => {
-                self.intersection_strategy = IntersectionStrategy::Straight;
-                match self.direction {
-                    Up => Left,
-                    Down => Right,
-                    Left => Down,
-                    Right => Up,
-                }
-            }
-            IntersectionStrategy::Right => {
-                self.intersection_strategy = IntersectionStrategy::Left;
-                match self.direction {
-                    Up => Right,
-                    Down => Left,
-                    Left => Up,
-                    Right => Down,
-                }
-            }
-        }
-    }
-
-    fn turn(&mut self, track: &Track) {
+    fn turn(&mut self, track: &Track, strategy: &impl IntersectionStrategy) {
         use self::Track::*;
         use self::TrainDirection::*;
         match track {
@@ -107,7 +178,11 @@ impl Train {
                 Down => self.direction = Left,
                 Left => self.direction = Down,
             },
-            Intersection => self.direction = self.intersection_turn(),
+            Intersection => {
+                let turn = strategy.turn(self.id, self.crossed);
+                self.direction = self.direction.turn(turn);
+                self.crossed += 1;
+            }
         }
     }
 }
@@ -168,62 +243,134 @@ fn parse(c: &char) -> Result<Input> {
 
 type Coor = (usize, usize);
 
-fn parse_input(input: &str) -> (HashMap<Coor, Track>, HashMap<Coor, Train>) {
-    let mut map = HashMap::new();
-    let mut trains = HashMap::new();
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct Collision {
+    tick: usize,
+    pos: Coor,
+    // ids of the moving train and the one it hit
+    trains: Vec<usize>,
+}
+
+// crashed trains are removed straight away, so the others keep going
+struct Simulator<S> {
+    map: HashMap<Coor, Track>,
+    trains: HashMap<Coor, Train>,
+    strategy: S,
+    ticks: usize,
+    collisions: Vec<Collision>,
+}
+
+impl<S: IntersectionStrategy> Simulator<S> {
+    fn new(input: &str, strategy: S) -> Result<Self> {
+        let mut map = HashMap::new();
+        let mut trains = HashMap::new();
 
-    for (y, row) in input.split('\n').enumerate() {
-        for (x, c) in row.chars().enumerate() {
-            if let Ok(parsed) = parse(&c) {
+        for (y, row) in input.split('\n').enumerate() {
+            for (x, c) in row.chars().enumerate() {
+                if c == ' ' {
+                    continue;
+                }
+                let parsed =
+                    parse(&c).map_err(|_| format_err!("invalid track `{}` at {},{}", c, x, y))?;
                 map.insert((x, y), parsed.track);
                 if let Some(direction) = parsed.train {
-                    trains.insert((x, y), Train::new(direction));
+                    trains.insert((x, y), Train::new(trains.len(), direction));
                 }
             }
         }
+        Ok(Simulator {
+            map,
+            trains,
+            strategy,
+            ticks: 0,
+            collisions: vec![],
+        })
+    }
+
+    // moves every train once, in reading order, and returns the collisions
+    // during the tick
+    fn tick(&mut self) -> Result<&[Collision]> {
+        let first_collision = self.collisions.len();
+        let mut order: Vec<_> = self.trains.iter().map(|(&pos, t)| (pos, t.id)).collect();
+        order.sort_by_key(|&((x, y), _)| (y, x));
+        for (pos, id) in order {
+            match self.trains.get(&pos) {
+                Some(train) if train.id == id => {}
+                _ => continue, // crashed
+            }
+            let mut train = self.trains.remove(&pos).unwrap();
+            let next_pos = train.step(pos);
+            if let Some(other) = self.trains.remove(&next_pos) {
+                self.collisions.push(Collision {
+                    tick: self.ticks,
+                    pos: next_pos,
+                    trains: vec![train.id, other.id],
+                });
+                continue;
+            }
+            let track = self
+                .map
+                .get(&next_pos)
+                .ok_or_else(|| format_err!("train {} ran off the track at {:?}", id, next_pos))?;
+            train.turn(track, &self.strategy);
+            self.trains.insert(next_pos, train);
+        }
+        self.ticks += 1;
+        Ok(&self.collisions[first_collision..])
+    }
+
+    fn collisions(&self) -> &[Collision] {
+        &self.collisions
+    }
+}
+
+// the track with the trains on it, in the input's notation, and an `X` where
+// trains crashed during the last tick
+impl<S> fmt::Display for Simulator<S> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let crashes: HashSet<Coor> = self
+            .collisions
+            .iter()
+            .filter(|c| c.tick + 1 == self.ticks)
+            .map(|c| c.pos)
+            .collect();
+        let max_x = self.map.keys().map(|c| c.0).max().unwrap_or(0);
+        let max_y = self.map.keys().map(|c| c.1).max().unwrap_or(0);
+        for y in 0..=max_y {
+            let mut row = String::new();
+            for x in 0..=max_x {
+                row.push(if crashes.contains(&(x, y)) {
+                    'X'
+                } else if let Some(train) = self.trains.get(&(x, y)) {
+                    train.direction.symbol()
+                } else {
+                    self.map.get(&(x, y)).map_or(' ', |t| t.symbol())
+                });
+            }
+            writeln!(f, "{}", row.trim_end())?;
+        }
+        Ok(())
     }
-    (map, trains)
 }
 
 fn part1(input: &str) -> Result<String> {
-    let (map, mut trains) = parse_input(input);
+    let mut simulator = Simulator::new(input, Cycle::left_straight_right())?;
     loop {
-        let mut order: Vec<_> = trains.keys().map(|&t| t.clone()).collect();
-        order.sort_by_key(|&(x, y)| (y, x));
-        for pos in order {
-            let mut train = trains.remove(&pos).expect("coor missing for remove");
-            let next_pos = train.step(pos);
-            if trains.contains_key(&next_pos) {
-                return Ok(format!("{:?}", next_pos));
-            }
-            let track = map.get(&next_pos).expect("next coor missing");
-            train.turn(track);
-            trains.insert(next_pos, train);
+        simulator.tick()?;
+        if let Some(collision) = simulator.collisions().first() {
+            return Ok(format!("{:?}", collision.pos));
         }
     }
 }
 
 fn part2(input: &str) -> Result<String> {
-    let (map, mut trains) = parse_input(input);
+    let mut simulator = Simulator::new(input, Cycle::left_straight_right())?;
     loop {
-        let mut order: Vec<_> = trains.keys().map(|&t| t.clone()).collect();
-        if order.len() == 1 {
-            return Ok(format!("{:?}", order[0]));
-        }
-        order.sort_by_key(|&(x, y)| (y, x));
-        for pos in order {
-            let mut remove = false;
-            if let Some(mut train) = trains.remove(&pos) {
-                let next_pos = train.step(pos);
-                if trains.contains_key(&next_pos) {
-                    trains.remove(&next_pos);
-                    remove = true;
-                }
-                let track = map.get(&next_pos).expect("next coor missing");
-                train.turn(track);
-                if !remove {
-                    trains.insert(next_pos, train);
-                }
+        match simulator.trains.len() {
+            0 => bail!("no trains left"),
+            1 => return Ok(format!("{:?}", simulator.trains.keys().next().unwrap())),
+            _ => {
+                simulator.tick()?;
             }
         }
     }
@@ -240,6 +387,14 @@ mod tests {
 \-+-/  \-+--/
   \------/   ";
 
+    const EXAMPLE2: &str = r"/>-<\
+|   |
+| /<+-\
+| | | v
+\>+</ |
+  |   ^
+  \<->/";
+
     #[test]
     fn test_part1() -> Result<()> {
         Ok(assert_eq!(part1(INPUT)?, "(7, 3)"))
@@ -247,17 +402,80 @@ mod tests {
 
     #[test]
     fn test_part2() -> Result<()> {
-        Ok(assert_eq!(
-            part2(
-                r"/>-<\
-|   |
-| /<+-\
-| | | v
-\>+</ |
-  |   ^
-  \<->/"
-            )?,
-            "(6, 4)"
-        ))
+        Ok(assert_eq!(part2(EXAMPLE2)?, "(6, 4)"))
+    }
+
+    #[test]
+    fn test_render() -> Result<()> {
+        let mut simulator = Simulator::new(INPUT, Cycle::left_straight_right())?;
+        let trimmed: Vec<_> = INPUT.lines().map(str::trim_end).collect();
+        assert_eq!(simulator.to_string(), trimmed.join("\n") + "\n");
+        simulator.tick()?;
+        assert_eq!(
+            simulator.to_string(),
+            r"/-->\
+|   |  /----\
+| /-+--+-\  |
+| | |  | |  |
+\-+-/  \->--/
+  \------/
+"
+        );
+        while simulator.collisions().is_empty() {
+            simulator.tick()?;
+        }
+        assert_eq!(
+            simulator.to_string(),
+            r"/---\
+|   |  /----\
+| /-+--+-\  |
+| | |  X |  |
+\-+-/  \-+--/
+  \------/
+"
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_collisions() -> Result<()> {
+        let mut simulator = Simulator::new(EXAMPLE2, Cycle::left_straight_right())?;
+        assert_eq!(simulator.tick()?.len(), 3);
+        while simulator.trains.len() > 1 {
+            simulator.tick()?;
+        }
+        let collision = |tick, pos, trains: &[usize]| Collision {
+            tick,
+            pos,
+            trains: trains.to_vec(),
+        };
+        assert_eq!(
+            simulator.collisions(),
+            &[
+                collision(0, (2, 0), &[1, 0]),
+                collision(0, (2, 4), &[5, 4]),
+                collision(0, (6, 4), &[6, 3]),
+                collision(2, (2, 4), &[7, 2]),
+            ][..]
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_strategy() -> Result<()> {
+        // going straight on, the two loops' trains never meet
+        let mut simulator = Simulator::new(INPUT, |_, _| Turn::Straight)?;
+        for _ in 0..100 {
+            assert!(simulator.tick()?.is_empty());
+        }
+        assert!(Simulator::new("-->?", |_, _| Turn::Straight).is_err());
+
+        assert!(Cycle::new(vec![]).is_err());
+        let cycle = Cycle::new(vec![Turn::Right, Turn::Left])?;
+        assert_eq!(cycle.turn(0, 3), Turn::Left);
+        assert_eq!("RSL".parse::<Cycle>()?.turn(0, 2), Turn::Left);
+        assert!("".parse::<Cycle>().is_err());
+        assert!("LX".parse::<Cycle>().is_err());
+        Ok(())
     }
 }
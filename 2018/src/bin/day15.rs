@@ -1,6 +1,7 @@
 use aoc2018::{dispatch, Result};
 use failure::Error;
 use std::collections::{HashMap, HashSet, VecDeque};
+use std::fmt;
 use std::mem;
 use std::str::FromStr;
 
@@ -15,7 +16,6 @@ enum UnitType {
 }
 
 impl UnitType {
-    #[allow(dead_code)]
     fn symbol(&self) -> char {
         match *self {
             UnitType::Elf => 'E',
@@ -61,10 +61,86 @@ enum RoundResult {
     ElfDied,
 }
 
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Event {
+    Move {
+        unit_type: UnitType,
+        from: Coor,
+        to: Coor,
+    },
+    Attack {
+        unit_type: UnitType,
+        at: Coor,
+        target: Coor,
+        hit_points: i32,
+    },
+    Death {
+        unit_type: UnitType,
+        at: Coor,
+    },
+}
+
+impl fmt::Display for Event {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Event::Move {
+                unit_type,
+                from,
+                to,
+            } => write!(
+                f,
+                "{} at {},{} moves to {},{}",
+                unit_type.symbol(),
+                from.0,
+                from.1,
+                to.0,
+                to.1
+            ),
+            Event::Attack {
+                unit_type,
+                at,
+                target,
+                hit_points,
+            } => write!(
+                f,
+                "{} at {},{} attacks {},{} ({} HP left)",
+                unit_type.symbol(),
+                at.0,
+                at.1,
+                target.0,
+                target.1,
+                hit_points
+            ),
+            Event::Death { unit_type, at } => {
+                write!(f, "{} at {},{} dies", unit_type.symbol(), at.0, at.1)
+            }
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct Outcome {
+    // `GameOver` or `ElfDied`
+    result: RoundResult,
+    full_rounds: i32,
+    hit_points: i32,
+}
+
+impl Outcome {
+    fn score(&self) -> i32 {
+        self.full_rounds * self.hit_points
+    }
+}
+
+#[derive(Clone)]
 struct Game {
     units: HashMap<Coor, Unit>,
     terrain: HashMap<Coor, Terrain>,
     elf_power: i32,
+    // end the battle as soon as any elf dies
+    stop_on_elf_death: bool,
+    // the events of each round played, including a final partial one
+    log: Vec<Vec<Event>>,
 }
 
 impl Game {
@@ -72,7 +148,38 @@ impl Game {
         self.units.values().map(|&u| u.hit_points).sum()
     }
 
+    fn record(&mut self, event: Event) {
+        self.log
+            .last_mut()
+            .expect("events are recorded during a round")
+            .push(event);
+    }
+
+    fn battle(&mut self) -> Outcome {
+        let mut full_rounds = 0;
+        loop {
+            match self.round() {
+                RoundResult::Continue => full_rounds += 1,
+                result => {
+                    return Outcome {
+                        result,
+                        full_rounds,
+                        hit_points: self.remaining_hit_points(),
+                    }
+                }
+            }
+        }
+    }
+
     fn round(&mut self) -> RoundResult {
+        self.log.push(vec![]);
+        // with only one side left (or none at all) no unit has a target
+        let mut sides = self.units.values().map(|u| u.unit_type);
+        match sides.next() {
+            Some(side) if sides.all(|s| s == side) => return RoundResult::GameOver,
+            None => return RoundResult::GameOver,
+            Some(_) => {}
+        }
         let mut order: Vec<(usize, Coor)> = self.units.iter().map(|(c, u)| (u.id, *c)).collect();
         order.sort_by_key(|&(_, (x, y))| (y, x));
         for (id, coor) in order {
@@ -92,16 +199,36 @@ impl Game {
             }
 
             let neighbour_units = self.neighbour_units(&coor, &unit_type);
-            let coor = if neighbour_units.len() == 0 {
-                self.choose_and_move(&coor)
+            let coor = if neighbour_units.is_empty() {
+                let to = self.choose_and_move(&coor);
+                if to != coor {
+                    self.record(Event::Move {
+                        unit_type,
+                        from: coor,
+                        to,
+                    });
+                }
+                to
             } else {
                 coor
             };
 
             let neighbour_units = self.neighbour_units(&coor, &unit_type);
-            if neighbour_units.len() > 0 {
-                if let Some(killed) = self.choose_and_attack(&neighbour_units[..]) {
-                    if killed.unit_type == UnitType::Elf {
+            if !neighbour_units.is_empty() {
+                let (target, killed) = self.choose_and_attack(&neighbour_units[..]);
+                self.record(Event::Attack {
+                    unit_type,
+                    at: coor,
+                    target,
+                    hit_points: killed
+                        .map_or_else(|| self.units[&target].hit_points, |unit| unit.hit_points),
+                });
+                if let Some(killed) = killed {
+                    self.record(Event::Death {
+                        unit_type: killed.unit_type,
+                        at: target,
+                    });
+                    if killed.unit_type == UnitType::Elf && self.stop_on_elf_death {
                         return RoundResult::ElfDied;
                     }
                 }
@@ -122,21 +249,22 @@ impl Game {
         neighbour_units
     }
 
-    fn choose_and_attack(&mut self, neighbour_units: &[Coor]) -> Option<Unit> {
+    // the coordinate attacked, and the unit if it was killed
+    fn choose_and_attack(&mut self, neighbour_units: &[Coor]) -> (Coor, Option<Unit>) {
         let lowest_hit_points = neighbour_units
             .iter()
             .map(|c| self.units.get(c).unwrap().hit_points)
             .min()
             .unwrap();
 
-        let chosen_coor = neighbour_units
+        let chosen_coor = *neighbour_units
             .iter()
             .map(|c| (self.units.get(c).unwrap(), c))
             .filter(|&(u, _)| u.hit_points == lowest_hit_points)
             .map(|(_, c)| c)
             .min_by_key(|&(x, y)| (y, x))
             .unwrap();
-        let chosen_unit = self.units.get_mut(chosen_coor).unwrap();
+        let chosen_unit = self.units.get_mut(&chosen_coor).unwrap();
         let power = match chosen_unit.unit_type {
             // nb. this is the target, so reverse
             UnitType::Goblin => self.elf_power,
@@ -144,9 +272,9 @@ impl Game {
         };
         chosen_unit.hit_points -= power;
         if chosen_unit.hit_points <= 0 {
-            self.units.remove(chosen_coor)
+            (chosen_coor, self.units.remove(&chosen_coor))
         } else {
-            None
+            (chosen_coor, None)
         }
     }
 
@@ -227,11 +355,8 @@ impl Game {
                 }
 
                 distances.entry(*neighbour).or_insert_with(|| {
-                    if queue.push_back(*neighbour) == () {
-                        current_distance + 1
-                    } else {
-                        panic!("haxx")
-                    }
+                    queue.push_back(*neighbour);
+                    current_distance + 1
                 });
             }
         }
@@ -312,41 +437,35 @@ impl Game {
         let (x, y) = *coor;
         [(x, y - 1), (x - 1, y), (x + 1, y), (x, y + 1)]
     }
+}
 
-    #[allow(dead_code)]
-    fn print(&self) {
+// the board in the puzzle's format, with each row's units and their hit
+// points after it
+impl fmt::Display for Game {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         use self::Terrain::*;
-        use self::UnitType::*;
         let max = self.terrain.keys().max().unwrap();
         for y in 0..=max.1 {
+            let mut unit_text = vec![];
             for x in 0..=max.0 {
                 let coor = &(x, y);
                 if let Some(unit) = self.units.get(coor) {
-                    match unit.unit_type {
-                        Goblin => print!("G"),
-                        Elf => print!("E"),
-                    }
+                    write!(f, "{}", unit.unit_type.symbol())?;
+                    unit_text.push(format!("{}({})", unit.unit_type.symbol(), unit.hit_points));
                 } else {
                     match self.terrain.get(coor) {
-                        Some(Open) => print!("."),
-                        Some(Wall) => print!("#"),
-                        None => unreachable!(),
+                        Some(Open) => write!(f, ".")?,
+                        Some(Wall) => write!(f, "#")?,
+                        None => {}
                     }
                 }
             }
-            let mut units_in_row: Vec<_> = self.units.iter().filter(|&(c, _)| c.1 == y).collect();
-            units_in_row.sort_by_key(|&(c, _)| c.0);
-            let mut unit_text = vec![];
-            for (_, &unit) in units_in_row {
-                unit_text.push(format!("{}({})", unit.unit_type.symbol(), unit.hit_points))
-            }
             if !unit_text.is_empty() {
-                println!("   {}", unit_text.join(", "));
-            } else {
-                println!("");
+                write!(f, "   {}", unit_text.join(", "))?;
             }
+            writeln!(f)?;
         }
-        println!("");
+        Ok(())
     }
 }
 
@@ -373,6 +492,8 @@ impl FromStr for Game {
             terrain,
             units,
             elf_power: 3,
+            stop_on_elf_death: false,
+            log: vec![],
         })
     }
 }
@@ -403,42 +524,49 @@ impl Input {
     }
 }
 
+// the lowest value from `low` upwards for which `passes` holds, assuming that
+// once it holds it keeps holding for every higher value
+fn lowest_passing(low: i32, mut passes: impl FnMut(i32) -> bool) -> i32 {
+    let mut high = low.max(1);
+    while !passes(high) {
+        high *= 2;
+    }
+    let mut low = low;
+    while low < high {
+        let mid = low + (high - low) / 2;
+        if passes(mid) {
+            high = mid;
+        } else {
+            low = mid + 1;
+        }
+    }
+    high
+}
+
+// the lowest elf attack power that wins without losing a single elf, and the
+// outcome of that battle
+fn lowest_elf_power(game: &Game) -> (i32, Outcome) {
+    let battle = |elf_power| {
+        let mut game = game.clone();
+        game.elf_power = elf_power;
+        game.stop_on_elf_death = true;
+        game.battle()
+    };
+    let elf_power = lowest_passing(4, |elf_power| {
+        battle(elf_power).result == RoundResult::GameOver
+    });
+    (elf_power, battle(elf_power))
+}
+
 fn part1(input: &str) -> Result<i32> {
     let mut game: Game = input.parse()?;
-    // game.print();
-    let mut round = 0;
-    while game.round() == RoundResult::Continue {
-        round += 1;
-        // println!("After {} rounds:", round);
-        // game.print();
-    }
-    // game.print();
-    // println!("{} * {}", game.remaining_hit_points(), round);
-    Ok(game.remaining_hit_points() * round)
+    Ok(game.battle().score())
 }
 
 fn part2(input: &str) -> Result<i32> {
-    let mut game: Game;
-    let mut round;
-    let mut elf_power = 3;
-    loop {
-        round = 0;
-        elf_power += 1;
-        game = input.parse()?;
-        game.elf_power = elf_power;
-        if loop {
-            match game.round() {
-                RoundResult::Continue => {
-                    round += 1;
-                }
-                RoundResult::GameOver => break true,
-                RoundResult::ElfDied => break false,
-            }
-        } {
-            break;
-        }
-    }
-    Ok(game.remaining_hit_points() * round)
+    let game: Game = input.parse()?;
+    let (_, outcome) = lowest_elf_power(&game);
+    Ok(outcome.score())
 }
 
 #[cfg(test)]
@@ -449,4 +577,132 @@ mod tests {
     fn test_part1() -> Result<()> {
         Ok(assert_eq!(part1("")?, 0))
     }
+
+    const EXAMPLE: &str = "\
+#######
+#.G...#
+#...EG#
+#.#.#G#
+#..G#E#
+#.....#
+#######";
+
+    const EXAMPLES: [(&str, i32, i32); 5] = [
+        (
+            "#######\n#G..#E#\n#E#E.E#\n#G.##.#\n#...#E#\n#...E.#\n#######",
+            36334,
+            0,
+        ),
+        (
+            "#######\n#E..EG#\n#.#G.E#\n#E.##E#\n#G..#.#\n#..E#.#\n#######",
+            39514,
+            31284,
+        ),
+        (
+            "#######\n#E.G#.#\n#.#G..#\n#G.#.G#\n#G..#.#\n#...E.#\n#######",
+            27755,
+            3478,
+        ),
+        (
+            "#######\n#.E...#\n#.#..G#\n#.###.#\n#E#G#G#\n#...#G#\n#######",
+            28944,
+            6474,
+        ),
+        (
+            "#########\n#G......#\n#.E.#...#\n#..##..G#\n#...##..#\n\
+             #...#...#\n#.G...G.#\n#.....G.#\n#########",
+            18740,
+            1140,
+        ),
+    ];
+
+    #[test]
+    fn test_examples() -> Result<()> {
+        assert_eq!(part1(EXAMPLE)?, 27730);
+        assert_eq!(part2(EXAMPLE)?, 4988);
+        for &(input, score, with_boost) in EXAMPLES.iter() {
+            assert_eq!(part1(input)?, score);
+            if with_boost > 0 {
+                assert_eq!(part2(input)?, with_boost);
+            }
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn test_render() -> Result<()> {
+        let mut game: Game = EXAMPLE.parse()?;
+        game.round();
+        assert_eq!(
+            game.to_string(),
+            "\
+#######
+#..G..#   G(200)
+#...EG#   E(197), G(197)
+#.#G#G#   G(200), G(197)
+#...#E#   E(197)
+#.....#
+#######
+"
+        );
+        let outcome = game.battle();
+        assert_eq!(outcome.full_rounds, 46);
+        assert_eq!(
+            game.to_string(),
+            "\
+#######
+#G....#   G(200)
+#.G...#   G(131)
+#.#.#G#   G(59)
+#...#.#
+#....G#   G(200)
+#######
+"
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_log() -> Result<()> {
+        let mut game: Game = EXAMPLE.parse()?;
+        game.round();
+        let events: Vec<String> = game.log[0].iter().map(|e| e.to_string()).collect();
+        assert_eq!(
+            events,
+            vec![
+                "G at 2,1 moves to 3,1",
+                "E at 4,2 attacks 5,2 (197 HP left)",
+                "G at 5,2 attacks 4,2 (197 HP left)",
+                "G at 5,3 attacks 5,4 (197 HP left)",
+                "G at 3,4 moves to 3,3",
+                "E at 5,4 attacks 5,3 (197 HP left)",
+            ]
+        );
+        game.battle();
+        let deaths: Vec<String> = game
+            .log
+            .iter()
+            .flatten()
+            .filter(|e| matches!(e, Event::Death { .. }))
+            .map(|e| e.to_string())
+            .collect();
+        assert_eq!(deaths, vec!["E at 4,2 dies", "E at 5,4 dies"]);
+        Ok(())
+    }
+
+    #[test]
+    fn test_lowest_passing() {
+        assert_eq!(lowest_passing(4, |n| n >= 4), 4);
+        assert_eq!(lowest_passing(4, |n| n >= 15), 15);
+        assert_eq!(lowest_passing(0, |n| n >= 1000), 1000);
+    }
+
+    #[test]
+    fn test_lowest_elf_power() -> Result<()> {
+        let (elf_power, outcome) = lowest_elf_power(&EXAMPLE.parse()?);
+        assert_eq!(elf_power, 15);
+        assert_eq!(outcome.full_rounds, 29);
+        assert_eq!(outcome.hit_points, 172);
+        Ok(())
+    }
 }
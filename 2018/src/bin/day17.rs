@@ -1,10 +1,15 @@
 use aoc2018::{dispatch, Result};
+use failure::bail;
 use lazy_static::lazy_static;
 use regex::{CaptureMatches, Captures, Regex};
 use std::cmp;
 use std::collections::HashMap;
 use std::fmt;
+use std::fs::{self, File};
+use std::io::Write;
 use std::ops;
+use std::path::Path;
+use std::result;
 
 #[derive(Clone, Copy, PartialEq, Eq, Hash)]
 struct Coor {
@@ -17,6 +22,10 @@ impl Coor {
         Coor { x, y }
     }
 
+    fn above(&self) -> Option<Coor> {
+        Some(Coor::new(self.x, self.y.checked_sub(1)?))
+    }
+
     fn below(&self) -> Coor {
         Coor::new(self.x, self.y + 1)
     }
@@ -116,8 +125,11 @@ impl<'r, 't> Iterator for InputWalker<'r, 't> {
 enum Terrain {
     Clay,
     SettledWater,
+    FlowingWater,
 }
 
+const SPRING: Coor = Coor { x: 500, y: 0 };
+
 #[derive(Debug, Clone, PartialEq, Eq)]
 struct Map {
     terrain: HashMap<Coor, Terrain>,
@@ -125,17 +137,18 @@ struct Map {
     max_x: u32,
     min_y: u32,
     max_y: u32,
-    flowing_water: HashMap<Coor, usize>,
+    // cells water still has to fall from. an explicit stack rather than
+    // recursion, as real inputs are deep enough to overflow the call stack
+    sources: Vec<Coor>,
 }
 
 impl Map {
-    fn new(input: &str) -> Self {
+    fn new(input: &str) -> Result<Self> {
         let mut terrain = HashMap::new();
         let mut min_x = std::u32::MAX;
         let mut max_x = 0;
         let mut min_y = std::u32::MAX;
         let mut max_y = 0;
-        let flowing_water = HashMap::new();
         for scan in InputWalker::new(input) {
             match scan {
                 Scan::Horizontal(scan) => {
@@ -158,187 +171,187 @@ impl Map {
                 }
             }
         }
-        Map {
+        if terrain.is_empty() {
+            bail!("no clay in the scan");
+        }
+        if min_x == 0 {
+            bail!("clay at x=0 leaves no room for water to flow around it");
+        }
+        Ok(Map {
             terrain,
             min_x,
             max_x,
             min_y,
             max_y,
-            flowing_water,
-        }
+            sources: vec![SPRING],
+        })
     }
 
-    fn is_open(&self, coor: &Coor) -> bool {
-        !(self.terrain.contains_key(&coor))
+    // clay or settled water, which water can spread across
+    fn is_solid(&self, coor: &Coor) -> bool {
+        matches!(
+            self.terrain.get(coor),
+            Some(Terrain::Clay) | Some(Terrain::SettledWater)
+        )
     }
 
-    fn move_down(&mut self, from: &Coor) {
-        let distance = self.flowing_water.remove(from).expect("move_down missing");
-        self.flowing_water.insert(from.below(), distance + 1);
+    fn flow(&mut self, coor: Coor) {
+        self.terrain.entry(coor).or_insert(Terrain::FlowingWater);
     }
 
-    #[allow(dead_code)]
-    fn print(&self) {
-        // let mut ycount = 0;
-        for y in (self.min_y)..=(self.max_y + 1) {
-            // ycount += 1;
-            // if ycount > 100 {
-            //     break;
-            // }
-            // let mut xcount = 0;
-            for x in (self.min_x - 3)..=(self.max_x + 3) {
-                // xcount += 1;
-                // if xcount > 100 {
-                //     break;
-                // }
-                let coor = Coor::new(x, y);
-                let c = match self.terrain.get(&coor) {
-                    Some(Terrain::SettledWater) => '~',
-                    Some(Terrain::Clay) => '#',
-                    None => {
-                        if self.flowing_water.contains_key(&coor) {
-                            '|'
-                        } else {
-                            '.'
+    // lets water fall from the next source, and spread over whatever it lands
+    // on. returns false once there's nothing left to do
+    fn step(&mut self) -> bool {
+        let source = match self.sources.pop() {
+            Some(source) => source,
+            None => return false,
+        };
+        if self.is_solid(&source) {
+            return true;
+        }
+        self.flow(source);
+        let mut pos = source;
+        loop {
+            let below = pos.below();
+            if below.y > self.max_y {
+                return true;
+            }
+            match self.terrain.get(&below) {
+                None => {
+                    self.flow(below);
+                    pos = below;
+                }
+                // joins a stream that's already been followed
+                Some(Terrain::FlowingWater) => return true,
+                Some(_) => break,
+            }
+        }
+        let left = self.spread(pos, |c| c.left(1));
+        let right = self.spread(pos, |c| c.right(1));
+        match (left, right) {
+            (Ok(left), Ok(right)) => {
+                // the row fills up, so whatever fed it now lands a row higher
+                for x in left.x..=right.x {
+                    let coor = Coor::new(x, pos.y);
+                    self.terrain.insert(coor, Terrain::SettledWater);
+                    if let Some(above) = coor.above() {
+                        if self.terrain.get(&above) == Some(&Terrain::FlowingWater) {
+                            self.sources.push(above);
                         }
                     }
-                };
-                print!("{}", c);
+                }
+            }
+            (left, right) => {
+                for edge in [left, right].iter() {
+                    if let Err(edge) = edge {
+                        self.sources.push(*edge);
+                    }
+                }
             }
-            println!("");
         }
-        println!("");
+        true
     }
 
-    fn inside_bb(&self, coor: &Coor) -> bool {
-        coor.y >= self.min_y && coor.y <= self.max_y
+    // spreads water sideways from `from` until it reaches a wall, giving
+    // the last cell before it, or an edge it spills over
+    fn spread(&mut self, from: Coor, next: impl Fn(&Coor) -> Coor) -> result::Result<Coor, Coor> {
+        let mut pos = from;
+        loop {
+            self.flow(pos);
+            if !self.is_solid(&pos.below()) {
+                return Err(pos);
+            }
+            let next_pos = next(&pos);
+            if self.is_solid(&next_pos) {
+                return Ok(pos);
+            }
+            pos = next_pos;
+        }
     }
 
-    fn water_count(&self) -> usize {
-        self.terrain
-            .iter()
-            .filter(|&(c, &t)| t == Terrain::SettledWater && self.inside_bb(c))
-            .count()
-            + self
-                .flowing_water
-                .iter()
-                .filter(|&(c, _)| self.inside_bb(c))
-                .count()
+    fn fill(&mut self) {
+        while self.step() {}
     }
 
-    fn settled_water_count(&self) -> usize {
+    fn count(&self, f: impl Fn(Terrain) -> bool) -> usize {
         self.terrain
             .iter()
-            .filter(|&(c, &t)| t == Terrain::SettledWater && self.inside_bb(c))
+            .filter(|&(c, &t)| f(t) && c.y >= self.min_y && c.y <= self.max_y)
             .count()
     }
 
-    fn contained(&self, coor: &Coor) -> Option<Vec<Coor>> {
-        let mut res = vec![];
-        let mut offset = 0;
-        loop {
-            let left = coor.left(offset);
-            if !self.is_open(&left) {
-                break;
-            }
-            if self.is_open(&left.below()) {
-                return None;
-            }
-            res.push(left);
-            offset += 1;
-        }
-        let mut offset = 0;
-        loop {
-            let right = coor.right(offset);
-            if !self.is_open(&right) {
-                break;
-            }
-            if self.is_open(&right.below()) {
-                return None;
-            }
-            res.push(right);
-            offset += 1;
-        }
-        Some(res)
+    fn water_count(&self) -> usize {
+        self.count(|t| t != Terrain::Clay)
     }
 
-    fn round(&mut self) -> bool {
-        // spring
-        self.flowing_water.insert(Coor::new(500, 0), 0);
-        let mut order: Vec<(Coor, usize)> = self
-            .flowing_water
-            .iter()
-            .map(|(&c, &d)| (c.clone(), d))
-            .collect();
-        order.sort_by_key(|&(_, d)| d);
-        order.reverse();
-        let mut moved = false;
-        for (coor, distance) in order {
-            if self.is_open(&coor.below()) {
-                if coor.below().y <= self.max_y {
-                    if !self.flowing_water.contains_key(&coor.below()) {
-                        self.move_down(&coor);
-                        moved = true;
-                    }
-                }
-            } else {
-                if let Some(contained) = self.contained(&coor) {
-                    moved = true;
+    fn settled_water_count(&self) -> usize {
+        self.count(|t| t == Terrain::SettledWater)
+    }
 
-                    self.flowing_water.remove(&coor);
-                    for contained_coor in contained {
-                        self.terrain.insert(contained_coor, Terrain::SettledWater);
-                    }
-                }
-                let mut flowed = false;
-                if self.is_open(&coor.left(1)) {
-                    let new_coor = coor.left(1);
-                    if !self.flowing_water.contains_key(&new_coor) {
-                        self.flowing_water.insert(new_coor, distance + 1);
-                        flowed = true;
-                    }
-                }
-                if self.is_open(&coor.right(1)) {
-                    let new_coor = coor.right(1);
-                    if !self.flowing_water.contains_key(&new_coor) {
-                        self.flowing_water.insert(new_coor, distance + 1);
-                        flowed = true;
+    fn write_to(&self, path: &Path) -> Result<()> {
+        let mut file = File::create(path)?;
+        write!(file, "{}", self)?;
+        Ok(())
+    }
+}
+
+// the puzzle's picture: from the spring down to the lowest clay, and one
+// column either side of it, where water can spill
+impl fmt::Display for Map {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        for y in 0..=self.max_y {
+            let row: String = (self.min_x - 1..=self.max_x + 1)
+                .map(|x| {
+                    let coor = Coor::new(x, y);
+                    match self.terrain.get(&coor) {
+                        _ if coor == SPRING => '+',
+                        Some(Terrain::Clay) => '#',
+                        Some(Terrain::SettledWater) => '~',
+                        Some(Terrain::FlowingWater) => '|',
+                        None => '.',
                     }
-                }
-                if flowed {
-                    moved = true;
-                    self.flowing_water.remove(&coor);
-                }
-            }
+                })
+                .collect();
+            writeln!(f, "{}", row)?;
         }
-
-        moved
+        Ok(())
     }
 }
 
-fn part1(input: &str) -> Result<usize> {
-    let mut map = Map::new(input);
-    loop {
-        if !map.round() {
-            break;
-        };
-    }
-    Ok(map.water_count())
+fn simulate(input: &str) -> Result<Map> {
+    let mut map = Map::new(input)?;
+    map.fill();
+    Ok(map)
 }
 
-fn part2(input: &str) -> Result<usize> {
-    let mut map = Map::new(input);
-    let mut water_count = map.water_count();
+// writes the final picture to `path`. not part of solving, but handy for
+// looking at a run by hand
+#[cfg_attr(not(test), allow(dead_code))]
+fn render(input: &str, path: &Path) -> Result<()> {
+    simulate(input)?.write_to(path)
+}
+
+// writes the picture after every step to numbered files in `dir`
+#[cfg_attr(not(test), allow(dead_code))]
+fn render_frames(input: &str, dir: &Path) -> Result<usize> {
+    let mut map = Map::new(input)?;
+    fs::create_dir_all(dir)?;
+    let mut frame = 0;
     loop {
-        if !map.round() {
-            break;
-        };
-        let new_water_count = map.water_count();
-        if new_water_count > water_count {
-            water_count = new_water_count
+        map.write_to(&dir.join(format!("{:06}.txt", frame)))?;
+        if !map.step() {
+            return Ok(frame + 1);
         }
+        frame += 1;
     }
-    Ok(map.settled_water_count())
+}
+
+fn part1(input: &str) -> Result<usize> {
+    Ok(simulate(input)?.water_count())
+}
+
+fn part2(input: &str) -> Result<usize> {
+    Ok(simulate(input)?.settled_water_count())
 }
 
 fn main() {
@@ -348,23 +361,27 @@ fn main() {
 #[cfg(test)]
 mod tests {
     use super::*;
+    use std::env;
 
-    #[test]
-    fn test_part1() -> Result<()> {
-        Ok(assert_eq!(
-            part1(
-                "x=495, y=2..7
+    const EXAMPLE: &str = "x=495, y=2..7
 y=7, x=495..501
 x=501, y=3..7
 x=498, y=2..4
 x=506, y=1..2
 x=498, y=10..13
 x=504, y=10..13
-y=13, x=498..504"
-            )?,
-            57
-        ))
+y=13, x=498..504";
+
+    #[test]
+    fn test_part1() -> Result<()> {
+        Ok(assert_eq!(part1(EXAMPLE)?, 57))
+    }
+
+    #[test]
+    fn test_part2() -> Result<()> {
+        Ok(assert_eq!(part2(EXAMPLE)?, 29))
     }
+
     #[test]
     fn test_part1_split() -> Result<()> {
         Ok(assert_eq!(
@@ -374,7 +391,76 @@ y=3, x=498..502
 y=0, x=505..505
 y=0, x=495..495"
             )?,
-            0
+            30
+        ))
+    }
+
+    #[test]
+    fn test_part1_settles_top_row() -> Result<()> {
+        // the spring's own row fills up, with nothing above it to feed
+        Ok(assert_eq!(
+            part1("x=499, y=0..1\nx=501, y=0..1\ny=1, x=500..500")?,
+            1
         ))
     }
+
+    #[test]
+    fn test_render() -> Result<()> {
+        let mut map = Map::new(EXAMPLE)?;
+        map.fill();
+        assert_eq!(
+            map.to_string(),
+            "......+.......
+......|.....#.
+.#..#||||...#.
+.#..#~~#|.....
+.#..#~~#|.....
+.#~~~~~#|.....
+.#~~~~~#|.....
+.#######|.....
+........|.....
+...|||||||||..
+...|#~~~~~#|..
+...|#~~~~~#|..
+...|#~~~~~#|..
+...|#######|..
+"
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_deep() -> Result<()> {
+        // a long drop into a tall, narrow well
+        let mut map =
+            Map::new("x=499, y=100000..200000\nx=501, y=100000..200000\ny=200001, x=499..501")?;
+        map.fill();
+        assert_eq!(map.settled_water_count(), 100001);
+        // and then spills over both sides, down past the bottom
+        assert_eq!(map.water_count(), 100001 + 2 * 100002);
+        Ok(())
+    }
+
+    #[test]
+    fn test_render_files() -> Result<()> {
+        let dir = env::temp_dir().join(format!("day17-{}", std::process::id()));
+        let frames = render_frames(EXAMPLE, &dir.join("frames"))?;
+        render(EXAMPLE, &dir.join("final.txt"))?;
+        let last = dir.join("frames").join(format!("{:06}.txt", frames - 1));
+        assert_eq!(fs::read_to_string(&last)?, simulate(EXAMPLE)?.to_string());
+        assert_eq!(
+            fs::read_to_string(dir.join("final.txt"))?,
+            simulate(EXAMPLE)?.to_string()
+        );
+        // the first frame is before any water has flowed
+        let first = fs::read_to_string(dir.join("frames").join("000000.txt"))?;
+        assert!(!first.contains('|'));
+        fs::remove_dir_all(&dir)?;
+        Ok(())
+    }
+
+    #[test]
+    fn test_invalid() {
+        assert!(Map::new("").is_err());
+    }
 }
@@ -1,123 +1,343 @@
 use aoc2018::{dispatch, Result};
+use failure::{bail, err_msg};
 use lazy_static::lazy_static;
 use regex::{CaptureMatches, Captures, Regex};
 use std::cmp::min;
 use std::collections::{HashMap, HashSet};
+use std::convert::TryFrom;
+use std::fmt;
 
 fn main() {
     dispatch(&part1, &part2)
 }
 
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Outcome {
+    // the side left standing, and how many units it has left
+    Won(UnitType, u32),
+    // a round where nobody died: the rest would go the same way forever. has
+    // the units left on both sides
+    Stalemate(u32),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct Attack {
+    attacker: UnitKey,
+    defender: UnitKey,
+    killed: u32,
+}
+
+// what happened in one round, for the puzzle's narration
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct Round {
+    // the groups alive at the start of the round, and their units
+    groups: Vec<(UnitKey, u32)>,
+    // the damage each attacker would deal to the defenders still untargeted
+    // when it chose
+    damage: Vec<(UnitKey, UnitKey, u64)>,
+    attacks: Vec<Attack>,
+}
+
+impl Round {
+    fn killed(&self) -> u32 {
+        self.attacks.iter().map(|a| a.killed).sum()
+    }
+}
+
+impl fmt::Display for Round {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        for side in [UnitType::ImmuneSystem, UnitType::Infection].iter() {
+            writeln!(f, "{}:", side)?;
+            let mut groups: Vec<_> = self.groups.iter().filter(|g| (g.0).0 == *side).collect();
+            groups.sort();
+            if groups.is_empty() {
+                writeln!(f, "No groups remain.")?;
+            }
+            for ((_, id), units) in groups {
+                writeln!(f, "Group {} contains {} units", id, units)?;
+            }
+        }
+        writeln!(f)?;
+        // the puzzle lists the infection first here
+        let mut damage = self.damage.clone();
+        damage.sort_by_key(|&(attacker, defender, _)| {
+            (attacker.0 != UnitType::Infection, attacker.1, defender.1)
+        });
+        for (attacker, defender, damage) in damage {
+            writeln!(
+                f,
+                "{} group {} would deal defending group {} {} damage",
+                attacker.0, attacker.1, defender.1, damage
+            )?;
+        }
+        writeln!(f)?;
+        for attack in &self.attacks {
+            writeln!(
+                f,
+                "{} group {} attacks defending group {}, killing {} units",
+                attack.attacker.0, attack.attacker.1, attack.defender.1, attack.killed
+            )?;
+        }
+        Ok(())
+    }
+}
+
 #[derive(Debug, Clone)]
 struct Reindeer {
     units: HashMap<UnitKey, Unit>,
+    log: Vec<Round>,
+    // the side whose attacks were boosted, and how much more boost is sure to
+    // leave every target choice and kill so far unchanged (`None` if nothing
+    // so far depends on the boost at all)
+    boosted: Option<UnitType>,
+    stable_for: Option<u64>,
 }
 
 impl Reindeer {
-    fn new(input: &str, boost: u32) -> Self {
-        let immune_system_start = input.find("Immune System:").unwrap();
-        let infection_start = input.find("Infection").unwrap();
+    fn new(input: &str) -> Result<Self> {
+        let immune_system_start = input
+            .find("Immune System:")
+            .ok_or_else(|| err_msg("no `Immune System:` army"))?;
+        let infection_start = input
+            .find("Infection:")
+            .ok_or_else(|| err_msg("no `Infection:` army"))?;
+        if infection_start < immune_system_start {
+            bail!("expected the immune system's army before the infection's");
+        }
 
         let mut units = HashMap::new();
-        for mut unit in UnitWalker::new(
+        for unit in UnitWalker::new(
             UnitType::ImmuneSystem,
             &input[immune_system_start..infection_start],
-        ) {
-            unit.damage += boost;
-            units.insert((unit.unit_type, unit.id), unit);
+        )
+        .chain(UnitWalker::new(
+            UnitType::Infection,
+            &input[infection_start..],
+        )) {
+            units.insert(unit.key(), unit);
+        }
+        Ok(Reindeer {
+            units,
+            log: vec![],
+            boosted: None,
+            stable_for: None,
+        })
+    }
+
+    fn boost(&mut self, side: UnitType, boost: u32) -> Result<()> {
+        for unit in self.units.values_mut().filter(|u| u.unit_type == side) {
+            unit.damage = unit
+                .damage
+                .checked_add(boost)
+                .ok_or_else(|| err_msg("boosted damage overflows"))?;
         }
+        self.boosted = Some(side);
+        Ok(())
+    }
+
+    // `more` boost is the first that might change something that happened
+    fn changes_after(&mut self, more: Option<u64>) {
+        if let Some(more) = more {
+            self.stable_for = Some(self.stable_for.map_or(more, |s| min(s, more)));
+        }
+    }
 
-        for unit in UnitWalker::new(UnitType::Infection, &input[infection_start..]) {
-            units.insert((unit.unit_type, unit.id), unit);
+    fn is_boosted(&self, unit: &Unit) -> bool {
+        self.boosted == Some(unit.unit_type)
+    }
+
+    // the least extra boost that could flip how `a` and `b` rank in effective
+    // power, ties included
+    fn rank_changes_after(&self, a: &Unit, b: &Unit) -> Option<u64> {
+        let slope = |u: &Unit| i128::from(u.units) * i128::from(self.is_boosted(u));
+        let (mut m, mut c) = (
+            slope(a) - slope(b),
+            i128::from(a.effective_power()) - i128::from(b.effective_power()),
+        );
+        if m < 0 {
+            m = -m;
+            c = -c;
+        }
+        match (m, c) {
+            (0, _) => None,
+            (_, c) if c > 0 => None,
+            (_, 0) => Some(1),
+            (m, c) => Some(((-c + m - 1) / m) as u64),
         }
-        Reindeer { units }
     }
 
-    fn target(&self) -> HashMap<UnitKey, UnitKey> {
+    // the least extra boost that could make `attacker` kill more than
+    // `killed` of `defender`'s units
+    fn kills_change_after(&self, attacker: &Unit, defender: &Unit, killed: u32) -> Option<u64> {
+        if !self.is_boosted(attacker) || killed == defender.units {
+            return None;
+        }
+        let per_damage = u64::from(attacker.factor(defender)) * u64::from(attacker.units);
+        let needed = (u64::from(killed) + 1) * u64::from(defender.hit_points);
+        Some(needed.div_ceil(per_damage) - u64::from(attacker.damage))
+    }
+
+    fn remaining(&self, side: UnitType) -> u32 {
+        self.units
+            .values()
+            .filter(|u| u.unit_type == side)
+            .map(|u| u.units)
+            .sum()
+    }
+
+    fn target(&mut self, damage: &mut Vec<(UnitKey, UnitKey, u64)>) -> HashMap<UnitKey, UnitKey> {
+        if self.boosted.is_some() {
+            let units: Vec<_> = self.units.values().cloned().collect();
+            for (i, a) in units.iter().enumerate() {
+                if self.is_boosted(a) && a.effective_power() == 0 {
+                    self.changes_after(Some(1));
+                }
+                for b in &units[i + 1..] {
+                    let more = self.rank_changes_after(a, b);
+                    self.changes_after(more);
+                }
+            }
+        }
         let mut target_map = HashMap::new();
         let mut target_set = TargetSet::new();
         let mut units = self.units.values().collect::<Vec<_>>();
         units.sort_by_key(|u| (u.effective_power(), u.initiative));
         units.reverse();
-        // println!("{:?}", self.units);
-        // println!("order: {:?}", units.iter().map(|u| (u.unit_type, u.id, u.effective_power(), u.initiative)).collect::<Vec<_>>());
         for unit in units.iter() {
             let mut units_left: Vec<_> = self
                 .units
                 .values()
                 .filter(|&u| u.unit_type != unit.unit_type)
-                .filter(|&u| u.units > 0)
                 .filter(|&u| unit.damage_to(u) > 0)
                 .filter(|&u| !target_set.contains_for_type(unit.unit_type, &u.id))
                 .collect();
             units_left.sort_by_key(|&u| (unit.damage_to(u), u.effective_power(), u.initiative));
             units_left.reverse();
-            // println!(
-            //     "{:?} {} left {:?}",
-            //     unit.unit_type,
-            //     unit.id,
-            //     units_left
-            //         .iter()
-            //         .map(|u| (u.id, unit.damage_to(u)))
-            //         .collect::<Vec<_>>()
-            // );
+            damage.extend(
+                units_left
+                    .iter()
+                    .map(|u| (unit.key(), u.key(), unit.damage_to(u))),
+            );
             if let Some(best) = units_left.first() {
-                target_map.insert((unit.unit_type, unit.id), (best.unit_type, best.id));
+                target_map.insert(unit.key(), best.key());
                 target_set.insert_for_type(unit.unit_type, best.id);
-                // println!("{:?} {} -> {}", unit.unit_type, unit.id, best.id);
             }
         }
 
         target_map
     }
 
-    fn fight(&mut self) -> u32 {
-        loop {
-            // println!("");
-            let (immune_system, infection): (Vec<_>, Vec<_>) =
-                self.units.values().partition(|u| match u.unit_type {
-                    UnitType::ImmuneSystem => true,
-                    UnitType::Infection => false,
+    fn round(&mut self) -> &Round {
+        let mut groups: Vec<_> = self.units.values().map(|u| (u.key(), u.units)).collect();
+        groups.sort();
+        let mut damage = vec![];
+        let target_map = self.target(&mut damage);
+        let mut unit_order: Vec<_> = self
+            .units
+            .values()
+            .map(|u| (u.initiative, u.key()))
+            .collect();
+        unit_order.sort_by_key(|t| t.0);
+        unit_order.reverse();
+
+        let mut attacks = vec![];
+        for (_, unit_key) in unit_order {
+            let unit = self.units.get(&unit_key).expect("attacker missing").clone();
+            if unit.units == 0 {
+                continue;
+            }
+            if let Some(target_key) = target_map.get(&unit_key) {
+                let target = self.units.get_mut(target_key).expect("target missing");
+                let before = target.clone();
+                let killed = target.attacked_by(&unit);
+                let more = self.kills_change_after(&unit, &before, killed);
+                self.changes_after(more);
+                attacks.push(Attack {
+                    attacker: unit_key,
+                    defender: *target_key,
+                    killed,
                 });
-            let immune_remaining = immune_system.iter().map(|u| u.units).sum::<u32>();
-            let infection_remaining = infection.iter().map(|u| u.units).sum::<u32>();
-            // println!("{}, {}", immune_remaining, infection_remaining);
-            if immune_remaining == 0 || infection_remaining == 0 {
-                break;
             }
-            let target_map = self.target();
-            let mut unit_order: Vec<_> = self
-                .units
-                .values()
-                .map(|u| (u.initiative, u.key()))
-                .collect();
-            unit_order.sort_by_key(|t| t.0);
-            unit_order.reverse();
+        }
+        self.units.retain(|_, u| u.units > 0);
+        self.log.push(Round {
+            groups,
+            damage,
+            attacks,
+        });
+        self.log.last().unwrap()
+    }
 
-            for (_, unit_key) in unit_order {
-                let unit = self.units.get(&unit_key).expect("attacker missing").clone();
-                if unit.units == 0 {
-                    continue;
-                }
-                if let Some(target_key) = target_map.get(&unit_key) {
-                    if let Some(entry) = self.units.get_mut(target_key) {
-                        (*entry).attacked_by(&unit);
-                    } else {
-                        panic!("target missing");
-                    }
-                }
+    // no boost to `side` can beat an enemy group immune to all its attacks
+    fn hurts_every_enemy(&self, side: UnitType) -> bool {
+        let (ours, theirs): (Vec<_>, Vec<_>) =
+            self.units.values().partition(|u| u.unit_type == side);
+        theirs
+            .iter()
+            .all(|enemy| ours.iter().any(|unit| unit.factor(enemy) > 0))
+    }
+
+    fn fight(&mut self) -> Outcome {
+        loop {
+            let immune_remaining = self.remaining(UnitType::ImmuneSystem);
+            let infection_remaining = self.remaining(UnitType::Infection);
+            if infection_remaining == 0 {
+                return Outcome::Won(UnitType::ImmuneSystem, immune_remaining);
+            }
+            if immune_remaining == 0 {
+                return Outcome::Won(UnitType::Infection, infection_remaining);
+            }
+            if self.round().killed() == 0 {
+                return Outcome::Stalemate(immune_remaining + infection_remaining);
             }
         }
-        self.units.values().map(|u| u.units).sum()
     }
+}
 
-    fn remaining(&self) -> UnitType {
-        self.units
-            .values()
-            .filter(|u| u.units > 0)
-            .next()
-            .unwrap()
-            .unit_type
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct BoostSearch {
+    // every boost tried, in order, with how its battle went
+    tried: Vec<(u32, Outcome)>,
+    boost: Option<u32>,
+}
+
+// how many battles to fight before giving up on finding a boost
+const MAX_BATTLES: usize = 100_000;
+
+// the smallest boost to `side`'s attacks that makes it win. stalemates mean
+// winning isn't monotonic in the boost, so boosts are tried in turn, skipping
+// straight past those that would fight exactly the same battle as the last
+// one: every target choice and kill stays the same until the boost reaches
+// the next point where some group's effective power overtakes another's or
+// a boosted attack kills one more unit. once a battle doesn't depend on the
+// boost at all, no bigger boost can end any differently
+fn minimum_boost(input: &str, side: UnitType) -> Result<BoostSearch> {
+    let reindeer = Reindeer::new(input)?;
+    let mut tried: Vec<(u32, Outcome)> = vec![];
+    if !reindeer.hurts_every_enemy(side) {
+        return Ok(BoostSearch { tried, boost: None });
+    }
+    let mut boost = 0;
+    loop {
+        if tried.len() == MAX_BATTLES {
+            bail!("no boost up to {} settles the battle", boost);
+        }
+        let mut boosted = reindeer.clone();
+        boosted.boost(side, boost)?;
+        let outcome = boosted.fight();
+        tried.push((boost, outcome));
+        if matches!(outcome, Outcome::Won(winner, _) if winner == side) {
+            return Ok(BoostSearch {
+                tried,
+                boost: Some(boost),
+            });
+        }
+        boost = match boosted.stable_for {
+            None => return Ok(BoostSearch { tried, boost: None }),
+            Some(more) => {
+                u32::try_from(u64::from(boost) + more).map_err(|_| err_msg("boost overflows"))?
+            }
+        };
     }
 }
 
@@ -149,12 +369,21 @@ impl TargetSet {
     }
 }
 
-#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
 enum UnitType {
     ImmuneSystem,
     Infection,
 }
 
+impl fmt::Display for UnitType {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            UnitType::ImmuneSystem => write!(f, "Immune System"),
+            UnitType::Infection => write!(f, "Infection"),
+        }
+    }
+}
+
 type UnitKey = (UnitType, usize);
 
 #[derive(Debug, Clone)]
@@ -171,27 +400,32 @@ struct Unit {
 }
 
 impl Unit {
-    fn effective_power(&self) -> u32 {
-        self.units * self.damage
+    fn effective_power(&self) -> u64 {
+        u64::from(self.units) * u64::from(self.damage)
     }
 
-    fn damage_to(&self, other: &Unit) -> u32 {
-        let factor = if other.immune.contains(&self.damage_type) {
+    // how many times its attack's damage this does to `other`
+    fn factor(&self, other: &Unit) -> u32 {
+        if other.immune.contains(&self.damage_type) {
             0
         } else if other.weak.contains(&self.damage_type) {
             2
         } else {
             1
-        };
-        factor * self.effective_power()
+        }
     }
 
-    fn attacked_by(&mut self, other: &Unit) {
+    fn damage_to(&self, other: &Unit) -> u64 {
+        u64::from(self.factor(other)) * self.effective_power()
+    }
+
+    // returns how many units were killed
+    fn attacked_by(&mut self, other: &Unit) -> u32 {
         let damage = other.damage_to(self);
-        let potential_killed_units = damage / self.hit_points;
-        let killed_units = min(potential_killed_units, self.units);
-        // println!("{:?} {} attacks {}: damage: {}, units: {}, hit points: {}, potential: {}, killed {}", other.unit_type, other.id, self.id, damage, self.units, self.hit_points, potential_killed_units, killed_units);
+        let potential_killed_units = damage / u64::from(self.hit_points);
+        let killed_units = min(potential_killed_units, u64::from(self.units)) as u32;
         self.units -= killed_units;
+        killed_units
     }
 
     fn key(&self) -> UnitKey {
@@ -276,21 +510,18 @@ impl<'r, 't> Iterator for UnitWalker<'r, 't> {
 }
 
 fn part1(input: &str) -> Result<u32> {
-    let mut reindeer = Reindeer::new(input, 0);
-    // println!("{:?}", reindeer);
-    Ok(reindeer.fight())
+    match Reindeer::new(input)?.fight() {
+        Outcome::Won(_, units) => Ok(units),
+        Outcome::Stalemate(units) => bail!("stalemate with {} units left", units),
+    }
 }
 
 fn part2(input: &str) -> Result<u32> {
-    let mut boost = 61;
-    Ok(loop {
-        let mut reindeer = Reindeer::new(input, boost);
-        let res = reindeer.fight();
-        if reindeer.remaining() == UnitType::ImmuneSystem {
-            break res;
-        }
-        boost += 1;
-    })
+    let search = minimum_boost(input, UnitType::ImmuneSystem)?;
+    match search.tried.last() {
+        Some(&(_, Outcome::Won(UnitType::ImmuneSystem, units))) => Ok(units),
+        _ => bail!("no boost makes the immune system win"),
+    }
 }
 
 #[cfg(test)]
@@ -305,17 +536,169 @@ Infection:
 801 units each with 4706 hit points (weak to radiation) with an attack that does 116 bludgeoning damage at initiative 1
 4485 units each with 2961 hit points (immune to radiation; weak to fire, cold) with an attack that does 12 slashing damage at initiative 4";
 
+    // each side is immune to the other's attacks
+    const STALEMATE: &str = "Immune System:
+10 units each with 10 hit points (immune to fire) with an attack that does 5 cold damage at initiative 2
+
+Infection:
+10 units each with 10 hit points (immune to cold) with an attack that does 5 fire damage at initiative 1";
+
     #[test]
     fn test_part1() -> Result<()> {
         Ok(assert_eq!(part1(INPUT)?, 5216))
     }
 
     #[test]
-    fn test_boost() {
-        let mut reindeer = Reindeer::new(INPUT, 1570);
-        // println!("{:?}", reindeer);
-        let res = reindeer.fight();
-        assert_eq!(reindeer.remaining(), UnitType::ImmuneSystem);
-        assert_eq!(res, 51);
+    fn test_boost() -> Result<()> {
+        let mut reindeer = Reindeer::new(INPUT)?;
+        reindeer.boost(UnitType::ImmuneSystem, 1570)?;
+        assert_eq!(reindeer.fight(), Outcome::Won(UnitType::ImmuneSystem, 51));
+        Ok(())
+    }
+
+    #[test]
+    fn test_log() -> Result<()> {
+        let mut reindeer = Reindeer::new(INPUT)?;
+        assert_eq!(
+            reindeer.round().to_string(),
+            "Immune System:
+Group 1 contains 17 units
+Group 2 contains 989 units
+Infection:
+Group 1 contains 801 units
+Group 2 contains 4485 units
+
+Infection group 1 would deal defending group 1 185832 damage
+Infection group 1 would deal defending group 2 185832 damage
+Infection group 2 would deal defending group 2 107640 damage
+Immune System group 1 would deal defending group 1 76619 damage
+Immune System group 1 would deal defending group 2 153238 damage
+Immune System group 2 would deal defending group 1 24725 damage
+
+Infection group 2 attacks defending group 2, killing 84 units
+Immune System group 2 attacks defending group 1, killing 4 units
+Immune System group 1 attacks defending group 2, killing 51 units
+Infection group 1 attacks defending group 1, killing 17 units
+"
+        );
+        assert_eq!(
+            reindeer.round().to_string(),
+            "Immune System:
+Group 2 contains 905 units
+Infection:
+Group 1 contains 797 units
+Group 2 contains 4434 units
+
+Infection group 1 would deal defending group 2 184904 damage
+Immune System group 2 would deal defending group 1 22625 damage
+Immune System group 2 would deal defending group 2 22625 damage
+
+Immune System group 2 attacks defending group 1, killing 4 units
+Infection group 1 attacks defending group 2, killing 144 units
+"
+        );
+        assert_eq!(reindeer.fight(), Outcome::Won(UnitType::Infection, 5216));
+        assert_eq!(reindeer.log.len(), 8);
+        assert!(reindeer.log[7].to_string().ends_with(
+            "Infection group 1 attacks defending group 2, killing 49 units
+"
+        ));
+        Ok(())
+    }
+
+    #[test]
+    fn test_stalemate() -> Result<()> {
+        let mut reindeer = Reindeer::new(STALEMATE)?;
+        assert_eq!(reindeer.fight(), Outcome::Stalemate(20));
+        assert_eq!(reindeer.log.len(), 1);
+        assert_eq!(
+            minimum_boost(STALEMATE, UnitType::ImmuneSystem)?.boost,
+            None
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_minimum_boost() -> Result<()> {
+        let search = minimum_boost(INPUT, UnitType::ImmuneSystem)?;
+        assert_eq!(search.boost, Some(1570));
+        // boosts that would fight the same battle as the one before are
+        // skipped, and only the last one tried wins
+        let boosts: Vec<_> = search.tried.iter().map(|&(boost, _)| boost).collect();
+        assert!(boosts.windows(2).all(|w| w[0] < w[1]));
+        assert!(search.tried.len() < 1571 * 9 / 10);
+        let (last, before) = search.tried.split_last().unwrap();
+        assert!(before
+            .iter()
+            .all(|&(_, outcome)| !matches!(outcome, Outcome::Won(UnitType::ImmuneSystem, _))));
+        assert_eq!(
+            search.tried[0],
+            (0, Outcome::Won(UnitType::Infection, 5216))
+        );
+        assert_eq!(*last, (1570, Outcome::Won(UnitType::ImmuneSystem, 51)));
+        // the infection doesn't need any help
+        let search = minimum_boost(INPUT, UnitType::Infection)?;
+        assert_eq!(
+            search.tried,
+            vec![(0, Outcome::Won(UnitType::Infection, 5216))]
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_immune_enemy() -> Result<()> {
+        // nothing the immune system does can hurt the first infection group,
+        // so no boost is even tried
+        let input = "Immune System:
+10 units each with 10 hit points with an attack that does 5 fire damage at initiative 2
+
+Infection:
+1000 units each with 1000000 hit points (immune to fire) with an attack that does 1 cold damage at initiative 1
+5 units each with 100 hit points with an attack that does 1 cold damage at initiative 3";
+        assert!(!Reindeer::new(input)?.hurts_every_enemy(UnitType::ImmuneSystem));
+        let search = minimum_boost(input, UnitType::ImmuneSystem)?;
+        assert_eq!(search.boost, None);
+        assert!(search.tried.is_empty());
+        Ok(())
+    }
+
+    #[test]
+    fn test_boost_skips() -> Result<()> {
+        // the infection always strikes first and kills the immune system's
+        // only unit, so only the infection's rank against it depends on the
+        // boost: level at 9, and behind from 10 on
+        let input = "Immune System:
+1 units each with 1 hit points with an attack that does 1 fire damage at initiative 1
+
+Infection:
+1 units each with 10 hit points with an attack that does 10 fire damage at initiative 2";
+        let search = minimum_boost(input, UnitType::ImmuneSystem)?;
+        assert_eq!(search.boost, None);
+        let boosts: Vec<_> = search.tried.iter().map(|&(boost, _)| boost).collect();
+        assert_eq!(boosts, vec![0, 9, 10]);
+        assert_eq!(search.tried[2], (10, Outcome::Won(UnitType::Infection, 1)));
+        Ok(())
+    }
+
+    #[test]
+    fn test_boost_large_armies() -> Result<()> {
+        // a one-shot boost would be in the hundreds of millions, and the
+        // effective powers would overflow a u32 long before then
+        let input = "Immune System:
+10000 units each with 1 hit points with an attack that does 1 fire damage at initiative 1
+
+Infection:
+5000 units each with 50000 hit points with an attack that does 1000 fire damage at initiative 2";
+        let search = minimum_boost(input, UnitType::ImmuneSystem)?;
+        assert_eq!(search.boost, None);
+        assert_eq!(search.tried.len(), 3);
+        assert!(part2(input).is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_invalid() {
+        assert!(Reindeer::new("Infection:").is_err());
+        assert!(part1("Immune System:\n").is_err());
     }
 }
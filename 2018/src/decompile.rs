@@ -0,0 +1,447 @@
+// turns an elfcode program into structured pseudo-code. writes to the ip
+// register become jumps; a comparison followed by `ip += t` becomes a
+// conditional, forward skips become `if` blocks and backward jumps become
+// loops. anything that doesn't fit that structure is left as a `goto`
+use crate::elfcode::{Op, OpType, Program, RegType};
+use crate::idioms;
+use std::collections::{HashMap, HashSet};
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Value {
+    Reg(usize),
+    Lit(RegType),
+}
+
+impl Value {
+    fn show(&self) -> String {
+        match self {
+            Value::Reg(register) => format!("r{}", register),
+            Value::Lit(value) => value.to_string(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Stmt {
+    Assign(usize, String),
+    Goto(RegType),
+    // jump to the target if the condition holds
+    If(Cond, RegType),
+    // a jump to a computed address
+    Jump(String),
+    Halt,
+    // the second half of a merged `If`
+    Nop,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct Cond {
+    register: usize,
+    negated: bool,
+}
+
+impl Cond {
+    fn negate(&self) -> Self {
+        Cond {
+            register: self.register,
+            negated: !self.negated,
+        }
+    }
+
+    fn show(&self) -> String {
+        if self.negated {
+            format!("!r{}", self.register)
+        } else {
+            format!("r{}", self.register)
+        }
+    }
+}
+
+fn expression(op_type: OpType, a: Value, b: Value) -> String {
+    use OpType::*;
+    let symbol = match op_type {
+        Addr | Addi => "+",
+        Mulr | Muli => "*",
+        Banr | Bani => "&",
+        Borr | Bori => "|",
+        Gtir | Gtri | Gtrr => ">",
+        Eqir | Eqri | Eqrr => "==",
+        Setr | Seti => return a.show(),
+    };
+    if let (Value::Lit(a), Value::Lit(b)) = (&a, &b) {
+        let value = match symbol {
+            "+" => a + b,
+            "*" => a * b,
+            "&" => a & b,
+            "|" => a | b,
+            ">" => (a > b) as RegType,
+            _ => (a == b) as RegType,
+        };
+        return value.to_string();
+    }
+    format!("{} {} {}", a.show(), symbol, b.show())
+}
+
+fn is_comparison(op_type: OpType) -> bool {
+    use OpType::*;
+    matches!(op_type, Gtir | Gtri | Gtrr | Eqir | Eqri | Eqrr)
+}
+
+struct Lowering<'a> {
+    ops: &'a [Op],
+    ip_register: Option<usize>,
+}
+
+impl<'a> Lowering<'a> {
+    fn value(&self, address: usize, operand: RegType, register: bool) -> Value {
+        if !register {
+            return Value::Lit(operand);
+        }
+        let operand = operand as usize;
+        if Some(operand) == self.ip_register {
+            Value::Lit(address as RegType)
+        } else {
+            Value::Reg(operand)
+        }
+    }
+
+    fn target(&self, value: RegType) -> Stmt {
+        let target = value + 1;
+        if target < 0 || target >= self.ops.len() as RegType {
+            Stmt::Halt
+        } else {
+            Stmt::Goto(target)
+        }
+    }
+
+    // `ip += t` right after `t = <comparison>`
+    fn condition(&self, address: usize, op: &Op) -> Option<usize> {
+        let ip = self.ip_register? as RegType;
+        if op.op_type != OpType::Addr || address == 0 {
+            return None;
+        }
+        let register = if op.a == ip {
+            op.b
+        } else if op.b == ip {
+            op.a
+        } else {
+            return None;
+        };
+        let previous = self.ops[address - 1];
+        if is_comparison(previous.op_type) && previous.c == register {
+            Some(register as usize)
+        } else {
+            None
+        }
+    }
+
+    fn lower(&self, address: usize) -> Stmt {
+        let op = &self.ops[address];
+        let (reads_a, reads_b) = op.op_type.reads_registers();
+        let a = self.value(address, op.a, reads_a);
+        let b = self.value(address, op.b, reads_b);
+        if Some(op.c as usize) != self.ip_register {
+            let target = op.c as usize;
+            return match (op.op_type, &a, &b) {
+                (OpType::Addr, Value::Reg(r), other) | (OpType::Addr, other, Value::Reg(r))
+                    if *r == target =>
+                {
+                    Stmt::Assign(target, format!("r{} += {}", target, other.show()))
+                }
+                (OpType::Addi, Value::Reg(r), other) if *r == target => {
+                    Stmt::Assign(target, format!("r{} += {}", target, other.show()))
+                }
+                _ => Stmt::Assign(
+                    target,
+                    format!("r{} = {}", target, expression(op.op_type, a, b)),
+                ),
+            };
+        }
+        if let Some(register) = self.condition(address, op) {
+            return Stmt::If(
+                Cond {
+                    register,
+                    negated: false,
+                },
+                address as RegType + 2,
+            );
+        }
+        let expr = expression(op.op_type, a, b);
+        match expr.parse::<RegType>() {
+            Ok(value) => self.target(value),
+            Err(_) => Stmt::Jump(format!("({}) + 1", expr)),
+        }
+    }
+}
+
+fn jump_target(stmt: &Stmt) -> Option<RegType> {
+    match stmt {
+        Stmt::Goto(target) | Stmt::If(_, target) => Some(*target),
+        _ => None,
+    }
+}
+
+struct Renderer {
+    stmts: Vec<Stmt>,
+    // (source, target) of every jump
+    jumps: Vec<(usize, usize)>,
+    // loop start -> loop end (the address of the backward jump)
+    loops: HashMap<usize, usize>,
+    idioms: HashSet<usize>,
+    lines: Vec<String>,
+}
+
+struct Loop {
+    start: usize,
+    exit: usize,
+}
+
+impl Renderer {
+    fn emit(&mut self, address: Option<usize>, depth: usize, text: String) {
+        let prefix = match address {
+            Some(address) => format!("{:>3}: ", address),
+            None => "     ".into(),
+        };
+        self.lines
+            .push(format!("{}{}{}", prefix, "    ".repeat(depth), text));
+    }
+
+    fn exit_after(&self, address: usize) -> usize {
+        let mut exit = address + 1;
+        while let Some(Stmt::Nop) = self.stmts.get(exit) {
+            exit += 1;
+        }
+        exit
+    }
+
+    // a forward jump from `from` to `to` can become a block if nothing
+    // outside jumps into it
+    fn is_block(&self, from: usize, to: usize, end: usize) -> bool {
+        to <= end
+            && self.jumps.iter().all(|&(source, target)| {
+                !(target > from + 1 && target < to) || (source > from && source < to)
+            })
+    }
+
+    fn jump_text(&self, target: usize, loops: &[Loop]) -> String {
+        match loops.last() {
+            Some(inner) if inner.start == target => "continue".into(),
+            Some(inner) if inner.exit == target => "break".into(),
+            _ => format!("goto {}", target),
+        }
+    }
+
+    fn render(&mut self, start: usize, end: usize, depth: usize, loops: &mut Vec<Loop>) {
+        let mut address = start;
+        while address < end {
+            if let Some(&last) = self.loops.get(&address) {
+                if last < end && loops.last().map(|l| l.start) != Some(address) {
+                    let exit = self.exit_after(last);
+                    let idiom = if self.idioms.contains(&address) {
+                        "  // closed form available"
+                    } else {
+                        ""
+                    };
+                    loops.push(Loop {
+                        start: address,
+                        exit,
+                    });
+                    match self.stmts[last].clone() {
+                        Stmt::If(cond, _) => {
+                            self.emit(None, depth, format!("do {{{}", idiom));
+                            self.render(address, last, depth + 1, loops);
+                            self.emit(Some(last), depth, format!("}} while {}", cond.show()));
+                        }
+                        _ => {
+                            self.emit(None, depth, format!("loop {{{}", idiom));
+                            self.render(address, last, depth + 1, loops);
+                            self.emit(Some(last), depth, "}".into());
+                        }
+                    }
+                    loops.pop();
+                    address = exit;
+                    continue;
+                }
+            }
+            match self.stmts[address].clone() {
+                Stmt::Nop => {}
+                Stmt::Assign(_, text) => self.emit(Some(address), depth, text),
+                Stmt::Halt => self.emit(Some(address), depth, "halt".into()),
+                Stmt::Jump(expr) => self.emit(Some(address), depth, format!("goto {}", expr)),
+                Stmt::Goto(target) => {
+                    let text = self.jump_text(target as usize, loops);
+                    self.emit(Some(address), depth, text);
+                }
+                Stmt::If(cond, target) => {
+                    let target = target as usize;
+                    let body = self.exit_after(address);
+                    if target > body && self.is_block(address, target, end) {
+                        let cond = cond.negate();
+                        self.emit(Some(address), depth, format!("if {} {{", cond.show()));
+                        self.render(body, target, depth + 1, loops);
+                        self.emit(None, depth, "}".into());
+                        address = target;
+                        continue;
+                    }
+                    let text = self.jump_text(target, loops);
+                    self.emit(Some(address), depth, format!("if {} {}", cond.show(), text));
+                }
+            }
+            address += 1;
+        }
+    }
+}
+
+fn lower(program: &Program) -> Vec<Stmt> {
+    let lowering = Lowering {
+        ops: &program.ops,
+        ip_register: program.ip_register,
+    };
+    let mut stmts: Vec<_> = (0..program.ops.len())
+        .map(|address| lowering.lower(address))
+        .collect();
+    let targets: HashSet<_> = stmts.iter().filter_map(jump_target).collect();
+
+    // `if c goto a + 2; goto b` is `if !c goto b`
+    for address in 0..stmts.len().saturating_sub(1) {
+        if let (Stmt::If(cond, skip), Stmt::Goto(target)) = (&stmts[address], &stmts[address + 1]) {
+            if *skip == address as RegType + 2 && !targets.contains(&(address as RegType + 1)) {
+                stmts[address] = Stmt::If(cond.negate(), *target);
+                stmts[address + 1] = Stmt::Nop;
+            }
+        }
+    }
+    stmts
+}
+
+pub fn decompile(program: &Program) -> String {
+    let stmts = lower(program);
+    let jumps: Vec<_> = stmts
+        .iter()
+        .enumerate()
+        .filter_map(|(source, stmt)| jump_target(stmt).map(|target| (source, target as usize)))
+        .collect();
+
+    // the outermost backward jump to each address makes a loop, as long as
+    // the loop is only entered at the top and the loops nest
+    let mut loops: HashMap<usize, usize> = HashMap::new();
+    for &(source, target) in jumps.iter().filter(|(s, t)| t <= s) {
+        let entry = loops.entry(target).or_insert(source);
+        *entry = (*entry).max(source);
+    }
+    loops.retain(|&start, &mut end| {
+        jumps.iter().all(|&(source, target)| {
+            !(target > start && target <= end) || (source >= start && source <= end)
+        })
+    });
+    let mut regions: Vec<_> = loops.iter().map(|(&s, &e)| (s, e)).collect();
+    regions.sort();
+    for &(start, end) in regions.iter() {
+        let crosses = regions
+            .iter()
+            .any(|&(s, e)| s > start && s <= end && e > end);
+        if crosses {
+            loops.remove(&start);
+        }
+    }
+
+    let mut renderer = Renderer {
+        stmts,
+        jumps,
+        loops,
+        idioms: idioms::find(program).iter().map(|m| m.start).collect(),
+        lines: vec![],
+    };
+    let len = renderer.stmts.len();
+    renderer.render(0, len, 0, &mut vec![]);
+    renderer.lines.join("\n")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Result;
+
+    #[test]
+    fn test_decompile() -> Result<()> {
+        let program = "#ip 5
+addi 5 16 5
+seti 1 0 1
+seti 1 0 4
+mulr 1 4 3
+eqrr 3 2 3
+addr 3 5 5
+addi 5 1 5
+addr 1 0 0
+addi 4 1 4
+gtrr 4 2 3
+addr 5 3 5
+seti 2 0 5
+addi 1 1 1
+gtrr 1 2 3
+addr 3 5 5
+seti 1 0 5
+mulr 5 5 5
+seti 10 0 2
+seti 0 0 0
+seti 0 0 5"
+            .parse()?;
+        assert_eq!(
+            decompile(&program),
+            "  0: goto 17
+  1: r1 = 1
+     do {
+  2:     r4 = 1
+         do {  // closed form available
+  3:         r3 = r1 * r4
+  4:         r3 = r3 == r2
+  5:         if r3 {
+  7:             r0 += r1
+             }
+  8:         r4 += 1
+  9:         r3 = r4 > r2
+ 10:     } while !r3
+ 12:     r1 += 1
+ 13:     r3 = r1 > r2
+ 14: } while !r3
+ 16: halt
+ 17: r2 = 10
+ 18: r0 = 0
+ 19: goto 1"
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_decompile_break() -> Result<()> {
+        let program = "#ip 4
+seti 1000 0 5
+seti 0 0 3
+addi 3 1 1
+muli 1 256 1
+gtrr 1 5 1
+addr 1 4 4
+addi 4 1 4
+seti 9 0 4
+addi 3 1 3
+seti 1 0 4
+setr 3 0 0"
+            .parse()?;
+        assert_eq!(
+            decompile(&program),
+            "  0: r5 = 1000
+  1: r3 = 0
+     loop {  // closed form available
+  2:     r1 = r3 + 1
+  3:     r1 = r1 * 256
+  4:     r1 = r1 > r5
+  5:     if r1 {
+  7:         break
+         }
+  8:     r3 += 1
+  9: }
+ 10: r0 = r3"
+        );
+        Ok(())
+    }
+}
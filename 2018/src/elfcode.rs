@@ -0,0 +1,543 @@
+use crate::idioms::{self, Idiom};
+use crate::Result;
+use failure::{bail, err_msg, format_err, Error};
+use lazy_static::lazy_static;
+use regex::Regex;
+use std::collections::{HashMap, HashSet};
+use std::fmt;
+use std::str::FromStr;
+
+pub type RegType = i64;
+pub type Registers = [RegType; 6];
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub enum OpType {
+    Addr,
+    Addi,
+    Mulr,
+    Muli,
+    Banr,
+    Bani,
+    Borr,
+    Bori,
+    Setr,
+    Seti,
+    Gtir,
+    Gtri,
+    Gtrr,
+    Eqir,
+    Eqri,
+    Eqrr,
+}
+
+impl OpType {
+    pub const ALL: [OpType; 16] = [
+        OpType::Addr,
+        OpType::Addi,
+        OpType::Mulr,
+        OpType::Muli,
+        OpType::Banr,
+        OpType::Bani,
+        OpType::Borr,
+        OpType::Bori,
+        OpType::Setr,
+        OpType::Seti,
+        OpType::Gtir,
+        OpType::Gtri,
+        OpType::Gtrr,
+        OpType::Eqir,
+        OpType::Eqri,
+        OpType::Eqrr,
+    ];
+
+    pub fn name(self) -> &'static str {
+        use self::OpType::*;
+        match self {
+            Addr => "addr",
+            Addi => "addi",
+            Mulr => "mulr",
+            Muli => "muli",
+            Banr => "banr",
+            Bani => "bani",
+            Borr => "borr",
+            Bori => "bori",
+            Setr => "setr",
+            Seti => "seti",
+            Gtir => "gtir",
+            Gtri => "gtri",
+            Gtrr => "gtrr",
+            Eqir => "eqir",
+            Eqri => "eqri",
+            Eqrr => "eqrr",
+        }
+    }
+
+    // whether operand a (resp. b) names a register
+    pub fn reads_registers(self) -> (bool, bool) {
+        use self::OpType::*;
+        match self {
+            Addr | Mulr | Banr | Borr | Gtrr | Eqrr => (true, true),
+            Addi | Muli | Bani | Bori | Gtri | Eqri => (true, false),
+            Gtir | Eqir => (false, true),
+            Setr => (true, false),
+            Seti => (false, false),
+        }
+    }
+
+    // applies the op to `registers`, or returns `None` if it refers to a
+    // register that doesn't exist
+    pub fn apply(
+        self,
+        registers: &mut [RegType],
+        a: RegType,
+        b: RegType,
+        c: RegType,
+    ) -> Option<()> {
+        use self::OpType::*;
+        let reg = |r: RegType| -> Option<RegType> {
+            if r < 0 {
+                return None;
+            }
+            registers.get(r as usize).copied()
+        };
+        let (reads_a, reads_b) = self.reads_registers();
+        let a = if reads_a { reg(a)? } else { a };
+        let b = if reads_b { reg(b)? } else { b };
+        let value = match self {
+            Addr | Addi => a + b,
+            Mulr | Muli => a * b,
+            Banr | Bani => a & b,
+            Borr | Bori => a | b,
+            Setr | Seti => a,
+            Gtir | Gtri | Gtrr => (a > b) as RegType,
+            Eqir | Eqri | Eqrr => (a == b) as RegType,
+        };
+        if c < 0 {
+            return None;
+        }
+        *registers.get_mut(c as usize)? = value;
+        Some(())
+    }
+}
+
+impl FromStr for OpType {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        OpType::ALL
+            .iter()
+            .find(|op_type| op_type.name() == s)
+            .copied()
+            .ok_or_else(|| format_err!("invalid op: {}", s))
+    }
+}
+
+impl fmt::Display for OpType {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.name())
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Op {
+    pub op_type: OpType,
+    pub a: RegType,
+    pub b: RegType,
+    pub c: RegType,
+}
+
+impl Op {
+    pub fn apply(&self, registers: &mut [RegType]) -> Option<()> {
+        self.op_type.apply(registers, self.a, self.b, self.c)
+    }
+}
+
+impl FromStr for Op {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        let parts: Vec<_> = s.split_whitespace().collect();
+        if parts.len() != 4 {
+            bail!("invalid instruction: {}", s);
+        }
+        let int = |part: &str| {
+            part.parse()
+                .map_err(|_| format_err!("invalid operand in {}: {}", s, part))
+        };
+        Ok(Op {
+            op_type: parts[0].parse()?,
+            a: int(parts[1])?,
+            b: int(parts[2])?,
+            c: int(parts[3])?,
+        })
+    }
+}
+
+impl fmt::Display for Op {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{} {} {} {}", self.op_type, self.a, self.b, self.c)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Program {
+    pub ip_register: Option<usize>,
+    pub ops: Vec<Op>,
+}
+
+impl FromStr for Program {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        let mut ip_register = None;
+        let mut ops = vec![];
+        for line in s.lines().map(|line| line.trim()).filter(|l| !l.is_empty()) {
+            if let Some(register) = line.strip_prefix("#ip") {
+                if ip_register.is_some() {
+                    bail!("duplicate #ip directive");
+                }
+                ip_register = Some(
+                    register
+                        .trim()
+                        .parse()
+                        .map_err(|_| format_err!("invalid #ip directive: {}", line))?,
+                );
+            } else {
+                ops.push(line.parse()?);
+            }
+        }
+        Ok(Program { ip_register, ops })
+    }
+}
+
+impl fmt::Display for Program {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        if let Some(register) = self.ip_register {
+            writeln!(f, "#ip {}", register)?;
+        }
+        for op in self.ops.iter() {
+            writeln!(f, "{}", op)?;
+        }
+        Ok(())
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct Cpu {
+    ip: RegType,
+    ip_register: Option<usize>,
+    pub registers: Registers,
+    program: Vec<Op>,
+    idioms: HashMap<RegType, (Idiom, RegType)>,
+}
+
+impl Cpu {
+    pub fn new(program: &Program, registers: Registers) -> Self {
+        Cpu {
+            ip: 0,
+            ip_register: program.ip_register,
+            registers,
+            program: program.ops.clone(),
+            idioms: HashMap::new(),
+        }
+    }
+
+    // replaces recognized loop idioms with their closed forms
+    pub fn optimize(&mut self) {
+        let program = Program {
+            ip_register: self.ip_register,
+            ops: self.program.clone(),
+        };
+        for found in idioms::find(&program) {
+            let exit = (found.start + found.len) as RegType;
+            self.idioms
+                .insert(found.start as RegType, (found.idiom, exit));
+        }
+    }
+
+    pub fn from_input(input: &str, registers: Registers) -> Result<Self> {
+        Ok(Self::new(&input.parse()?, registers))
+    }
+
+    pub fn ip(&self) -> RegType {
+        self.ip
+    }
+
+    pub fn jump(&mut self, ip: RegType) {
+        self.ip = ip;
+    }
+
+    pub fn current(&self) -> Option<&Op> {
+        if self.ip < 0 {
+            return None;
+        }
+        self.program.get(self.ip as usize)
+    }
+
+    fn write_ip_register(&mut self, value: RegType) -> Result<()> {
+        if let Some(register) = self.ip_register {
+            *self
+                .registers
+                .get_mut(register)
+                .ok_or_else(|| format_err!("invalid #ip register: {}", register))? = value;
+        }
+        Ok(())
+    }
+
+    // executes one instruction, returning false if the cpu has halted
+    pub fn step(&mut self) -> Result<bool> {
+        let op = match self.current() {
+            Some(&op) => op,
+            None => return Ok(false),
+        };
+        if let Some(&(idiom, exit)) = self.idioms.get(&self.ip) {
+            if idiom.apply(&mut self.registers).is_some() {
+                self.write_ip_register(exit - 1)?;
+                self.ip = exit;
+                return Ok(true);
+            }
+        }
+        self.write_ip_register(self.ip)?;
+        op.apply(&mut self.registers)
+            .ok_or_else(|| format_err!("invalid register in {} at {}", op, self.ip))?;
+        if let Some(register) = self.ip_register {
+            self.ip = self.registers[register];
+        }
+        self.ip += 1;
+        Ok(true)
+    }
+
+    // runs until the cpu halts, returning the number of instructions executed
+    pub fn run(&mut self) -> Result<usize> {
+        let mut steps = 0;
+        while self.step()? {
+            steps += 1;
+        }
+        Ok(steps)
+    }
+}
+
+pub type Sample4 = [RegType; 4];
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Sample {
+    pub before: Sample4,
+    pub instruction: Sample4,
+    pub after: Sample4,
+}
+
+impl Sample {
+    pub fn matching_ops(&self) -> Vec<OpType> {
+        let [_, a, b, c] = self.instruction;
+        OpType::ALL
+            .iter()
+            .copied()
+            .filter(|op_type| {
+                let mut registers = self.before;
+                op_type.apply(&mut registers, a, b, c).is_some() && registers == self.after
+            })
+            .collect()
+    }
+}
+
+fn parse_ints(s: &str) -> Result<Sample4> {
+    let ints: Vec<RegType> = s
+        .split(|c: char| c == ',' || c.is_whitespace())
+        .filter(|part| !part.is_empty())
+        .map(|part| {
+            part.parse()
+                .map_err(|_| format_err!("invalid int: {}", part))
+        })
+        .collect::<Result<_>>()?;
+    if ints.len() != 4 {
+        bail!("expected 4 values: {}", s);
+    }
+    Ok([ints[0], ints[1], ints[2], ints[3]])
+}
+
+// parses day 16 style input: before/after samples, followed by a program of
+// numeric instructions
+pub fn parse_samples(input: &str) -> Result<(Vec<Sample>, Vec<Sample4>)> {
+    lazy_static! {
+        static ref RE: Regex =
+            Regex::new(r"Before:\s*\[([^\]]*)\]\s*\n([-\d ]+)\n\s*After:\s*\[([^\]]*)\]").unwrap();
+    }
+    let mut samples = vec![];
+    let mut end = 0;
+    for caps in RE.captures_iter(input) {
+        samples.push(Sample {
+            before: parse_ints(&caps[1])?,
+            instruction: parse_ints(&caps[2])?,
+            after: parse_ints(&caps[3])?,
+        });
+        end = caps.get(0).map(|m| m.end()).unwrap_or(end);
+    }
+    let program = input[end..]
+        .lines()
+        .filter(|line| !line.trim().is_empty())
+        .map(parse_ints)
+        .collect::<Result<_>>()?;
+    Ok((samples, program))
+}
+
+// works out which opcode number is which op by intersecting the candidates of
+// each sample, then repeatedly fixing opcodes with a single candidate
+pub fn identify_opcodes(samples: &[Sample]) -> Result<HashMap<RegType, OpType>> {
+    let mut possible: HashMap<RegType, HashSet<OpType>> = HashMap::new();
+    for sample in samples.iter() {
+        let matches: HashSet<_> = sample.matching_ops().into_iter().collect();
+        let entry = possible
+            .entry(sample.instruction[0])
+            .or_insert_with(|| matches.clone());
+        *entry = entry.intersection(&matches).copied().collect();
+    }
+
+    let mut opcodes = HashMap::new();
+    while !possible.is_empty() {
+        let (&opcode, op_type) = possible
+            .iter()
+            .find(|(_, candidates)| candidates.len() == 1)
+            .map(|(opcode, candidates)| (opcode, *candidates.iter().next().unwrap()))
+            .ok_or_else(|| err_msg("samples don't identify opcodes uniquely"))?;
+        possible.remove(&opcode);
+        for candidates in possible.values_mut() {
+            candidates.remove(&op_type);
+        }
+        opcodes.insert(opcode, op_type);
+    }
+    Ok(opcodes)
+}
+
+pub fn decode_program(program: &[Sample4], opcodes: &HashMap<RegType, OpType>) -> Result<Program> {
+    let ops = program
+        .iter()
+        .map(|&[opcode, a, b, c]| {
+            let op_type = *opcodes
+                .get(&opcode)
+                .ok_or_else(|| format_err!("unknown opcode: {}", opcode))?;
+            Ok(Op { op_type, a, b, c })
+        })
+        .collect::<Result<_>>()?;
+    Ok(Program {
+        ip_register: None,
+        ops,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const EXAMPLE: &str = "#ip 0
+seti 5 0 1
+seti 6 0 2
+addi 0 1 0
+addr 1 2 3
+setr 1 0 0
+seti 8 0 4
+seti 9 0 5";
+
+    #[test]
+    fn test_parse() -> Result<()> {
+        let program: Program = EXAMPLE.parse()?;
+        assert_eq!(program.ip_register, Some(0));
+        assert_eq!(program.ops.len(), 7);
+        assert_eq!(
+            program.ops[3],
+            Op {
+                op_type: OpType::Addr,
+                a: 1,
+                b: 2,
+                c: 3
+            }
+        );
+        assert_eq!(program.to_string().trim(), EXAMPLE);
+        assert!("#ip x".parse::<Program>().is_err());
+        assert!("addx 1 2 3".parse::<Program>().is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_run() -> Result<()> {
+        let mut cpu = Cpu::from_input(EXAMPLE, [0; 6])?;
+        assert_eq!(cpu.run()?, 5);
+        assert_eq!(cpu.registers, [6, 5, 6, 0, 0, 9]);
+        assert_eq!(cpu.ip(), 7);
+        Ok(())
+    }
+
+    #[test]
+    fn test_invalid_register() -> Result<()> {
+        let mut cpu = Cpu::from_input("addr 1 6 0", [0; 6])?;
+        assert!(cpu.step().is_err());
+        // an #ip register out of range is caught on the idiom path too
+        let mut cpu = Cpu::from_input("#ip 6\nseti 0 0 0", [0; 6])?;
+        cpu.idioms.insert(
+            0,
+            (
+                Idiom::Divide {
+                    q: 0,
+                    t: 1,
+                    k: 2,
+                    n: 3,
+                },
+                1,
+            ),
+        );
+        assert!(cpu.step().is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_matching_ops() -> Result<()> {
+        let (samples, program) = parse_samples(
+            "Before: [3, 2, 1, 1]
+9 2 1 2
+After:  [3, 2, 2, 1]
+
+
+
+9 2 1 2
+",
+        )?;
+        assert_eq!(program, vec![[9, 2, 1, 2]]);
+        assert_eq!(
+            samples[0].matching_ops(),
+            vec![OpType::Addi, OpType::Mulr, OpType::Seti]
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_identify_opcodes() -> Result<()> {
+        let sample = |op_type: OpType, opcode, before: Sample4, (a, b, c)| {
+            let mut after = before;
+            op_type.apply(&mut after, a, b, c).unwrap();
+            Sample {
+                before,
+                instruction: [opcode, a, b, c],
+                after,
+            }
+        };
+        let samples: Vec<_> = OpType::ALL
+            .iter()
+            .enumerate()
+            .flat_map(|(opcode, &op_type)| {
+                (0..20).map(move |i| {
+                    let before = [(i * 7 + 3) % 11, (i * 5 + 1) % 13, (i * 3) % 9, i % 4];
+                    sample(
+                        op_type,
+                        opcode as RegType,
+                        before,
+                        (i % 4, (i / 4) % 4, (i * 3) % 4),
+                    )
+                })
+            })
+            .collect();
+        let opcodes = identify_opcodes(&samples)?;
+        for (opcode, op_type) in OpType::ALL.iter().enumerate() {
+            assert_eq!(opcodes.get(&(opcode as RegType)), Some(op_type));
+        }
+        Ok(())
+    }
+}
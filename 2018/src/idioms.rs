@@ -0,0 +1,333 @@
+// recognizes common elfcode loop idioms so the cpu can replace them with
+// closed-form operations. patterns are matched against instruction shapes,
+// with register variables bound to whatever registers a given input uses
+use crate::elfcode::{Op, OpType, Program, RegType};
+use std::collections::HashMap;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Operand {
+    // a register, the same one wherever the variable appears
+    Var(char),
+    // the instruction pointer register
+    Ip,
+    // a literal value
+    Lit(RegType),
+    // a literal, the same one wherever the variable appears
+    Val(char),
+    // a literal address, relative to the start of the pattern
+    At(RegType),
+    Any,
+}
+
+use self::Operand::*;
+
+type Pattern = [(OpType, Operand, Operand, Operand)];
+
+// for i in i..=max(i, n) { t = f * i == n; if t { s += f } }
+const SUM_IF_FACTOR: [(OpType, Operand, Operand, Operand); 9] = [
+    (OpType::Mulr, Var('f'), Var('i'), Var('t')),
+    (OpType::Eqrr, Var('t'), Var('n'), Var('t')),
+    (OpType::Addr, Var('t'), Ip, Ip),
+    (OpType::Addi, Ip, Lit(1), Ip),
+    (OpType::Addr, Var('f'), Var('s'), Var('s')),
+    (OpType::Addi, Var('i'), Lit(1), Var('i')),
+    (OpType::Gtrr, Var('i'), Var('n'), Var('t')),
+    (OpType::Addr, Ip, Var('t'), Ip),
+    (OpType::Seti, At(-1), Any, Ip),
+];
+
+// loop { t = (q + 1) * k > n; if t { break } q += 1 }
+const DIVIDE: [(OpType, Operand, Operand, Operand); 8] = [
+    (OpType::Addi, Var('q'), Lit(1), Var('t')),
+    (OpType::Muli, Var('t'), Val('k'), Var('t')),
+    (OpType::Gtrr, Var('t'), Var('n'), Var('t')),
+    (OpType::Addr, Var('t'), Ip, Ip),
+    (OpType::Addi, Ip, Lit(1), Ip),
+    (OpType::Seti, At(7), Any, Ip),
+    (OpType::Addi, Var('q'), Lit(1), Var('q')),
+    (OpType::Seti, At(-1), Any, Ip),
+];
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Idiom {
+    SumIfFactor {
+        f: usize,
+        i: usize,
+        t: usize,
+        n: usize,
+        s: usize,
+    },
+    // `k` is a literal, the other fields are registers
+    Divide {
+        q: usize,
+        t: usize,
+        k: RegType,
+        n: usize,
+    },
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Match {
+    pub start: usize,
+    pub len: usize,
+    pub idiom: Idiom,
+}
+
+fn commutative(op_type: OpType) -> bool {
+    use OpType::*;
+    matches!(op_type, Addr | Mulr | Banr | Borr | Eqrr)
+}
+
+#[derive(Debug, Clone, Default)]
+struct Bindings {
+    registers: HashMap<char, RegType>,
+    values: HashMap<char, RegType>,
+}
+
+struct Matcher {
+    ip_register: RegType,
+    start: usize,
+}
+
+impl Matcher {
+    fn operand(&self, expected: Operand, actual: RegType, bindings: &mut Bindings) -> bool {
+        match expected {
+            Ip => actual == self.ip_register,
+            Lit(value) => actual == value,
+            At(offset) => actual == self.start as RegType + offset,
+            Any => true,
+            Val(name) => *bindings.values.entry(name).or_insert(actual) == actual,
+            Var(name) => {
+                if actual == self.ip_register {
+                    return false;
+                }
+                if let Some(&bound) = bindings.registers.get(&name) {
+                    return bound == actual;
+                }
+                if bindings.registers.values().any(|&bound| bound == actual) {
+                    return false;
+                }
+                bindings.registers.insert(name, actual);
+                true
+            }
+        }
+    }
+
+    fn op(
+        &self,
+        expected: &(OpType, Operand, Operand, Operand),
+        op: &Op,
+        bindings: &Bindings,
+    ) -> Option<Bindings> {
+        let &(op_type, a, b, c) = expected;
+        if op.op_type != op_type {
+            return None;
+        }
+        let orders = if commutative(op_type) {
+            vec![(a, b), (b, a)]
+        } else {
+            vec![(a, b)]
+        };
+        orders.into_iter().find_map(|(a, b)| {
+            let mut bindings = bindings.clone();
+            if self.operand(a, op.a, &mut bindings)
+                && self.operand(b, op.b, &mut bindings)
+                && self.operand(c, op.c, &mut bindings)
+            {
+                Some(bindings)
+            } else {
+                None
+            }
+        })
+    }
+
+    fn pattern(&self, pattern: &Pattern, ops: &[Op], bindings: Bindings) -> Option<Bindings> {
+        match (pattern.split_first(), ops.split_first()) {
+            (None, _) => Some(bindings),
+            (Some(_), None) => None,
+            (Some((expected, pattern)), Some((op, ops))) => {
+                let bindings = self.op(expected, op, &bindings)?;
+                self.pattern(pattern, ops, bindings)
+            }
+        }
+    }
+}
+
+fn match_at(program: &Program, start: usize) -> Option<Match> {
+    let matcher = Matcher {
+        ip_register: program.ip_register? as RegType,
+        start,
+    };
+    let ops = &program.ops[start..];
+    if let Some(b) = matcher.pattern(&SUM_IF_FACTOR, ops, Bindings::default()) {
+        return Some(Match {
+            start,
+            len: SUM_IF_FACTOR.len(),
+            idiom: Idiom::SumIfFactor {
+                f: b.registers[&'f'] as usize,
+                i: b.registers[&'i'] as usize,
+                t: b.registers[&'t'] as usize,
+                n: b.registers[&'n'] as usize,
+                s: b.registers[&'s'] as usize,
+            },
+        });
+    }
+    if let Some(b) = matcher.pattern(&DIVIDE, ops, Bindings::default()) {
+        return Some(Match {
+            start,
+            len: DIVIDE.len(),
+            idiom: Idiom::Divide {
+                q: b.registers[&'q'] as usize,
+                t: b.registers[&'t'] as usize,
+                k: b.values[&'k'],
+                n: b.registers[&'n'] as usize,
+            },
+        });
+    }
+    None
+}
+
+pub fn find(program: &Program) -> Vec<Match> {
+    (0..program.ops.len())
+        .filter_map(|start| match_at(program, start))
+        .collect()
+}
+
+impl Idiom {
+    // applies the closed form of the loop. returns `None` if the loop can't be
+    // shortcut for these register values (or refers to registers that don't
+    // exist), in which case it should be stepped through as usual
+    pub fn apply(&self, registers: &mut [RegType]) -> Option<()> {
+        match *self {
+            Idiom::SumIfFactor { f, i, t, n, s } => {
+                let (factor, start, target) =
+                    (*registers.get(f)?, *registers.get(i)?, *registers.get(n)?);
+                let end = start.max(target);
+                if factor != 0 && target % factor == 0 {
+                    let other = target / factor;
+                    if start <= other && other <= end {
+                        *registers.get_mut(s)? += factor;
+                    }
+                }
+                *registers.get_mut(i)? = end + 1;
+                *registers.get_mut(t)? = 1;
+            }
+            Idiom::Divide { q, t, k, n } => {
+                if k <= 0 {
+                    return None;
+                }
+                let (quotient, target) = (*registers.get(q)?, *registers.get(n)?);
+                *registers.get_mut(q)? = quotient.max(target.div_euclid(k));
+                *registers.get_mut(t)? = 1;
+            }
+        }
+        Some(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::elfcode::Cpu;
+    use crate::Result;
+
+    const DIVISORS: &str = "#ip 5
+seti 12 0 2
+seti 1 0 1
+seti 1 0 4
+mulr 1 4 3
+eqrr 3 2 3
+addr 3 5 5
+addi 5 1 5
+addr 1 0 0
+addi 4 1 4
+gtrr 4 2 3
+addr 5 3 5
+seti 2 0 5
+addi 1 1 1
+gtrr 1 2 3
+addr 3 5 5
+seti 1 0 5
+mulr 5 5 5";
+
+    const DIVIDE_BY_256: &str = "#ip 4
+seti 1000 0 5
+seti 0 0 3
+addi 3 1 1
+muli 1 256 1
+gtrr 1 5 1
+addr 1 4 4
+addi 4 1 4
+seti 9 0 4
+addi 3 1 3
+seti 1 0 4
+setr 3 0 0";
+
+    #[test]
+    fn test_find() -> Result<()> {
+        assert_eq!(
+            find(&DIVISORS.parse()?),
+            vec![Match {
+                start: 3,
+                len: 9,
+                idiom: Idiom::SumIfFactor {
+                    f: 1,
+                    i: 4,
+                    t: 3,
+                    n: 2,
+                    s: 0
+                }
+            }]
+        );
+        assert_eq!(
+            find(&DIVIDE_BY_256.parse()?),
+            vec![Match {
+                start: 2,
+                len: 8,
+                idiom: Idiom::Divide {
+                    q: 3,
+                    t: 1,
+                    k: 256,
+                    n: 5
+                }
+            }]
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_optimized_run() -> Result<()> {
+        for (input, expected) in &[(DIVISORS, 28), (DIVIDE_BY_256, 3)] {
+            let mut plain = Cpu::from_input(input, [0; 6])?;
+            let plain_steps = plain.run()?;
+            let mut optimized = Cpu::from_input(input, [0; 6])?;
+            optimized.optimize();
+            let optimized_steps = optimized.run()?;
+            assert_eq!(plain.registers[0], *expected);
+            assert_eq!(optimized.registers, plain.registers);
+            assert!(optimized_steps < plain_steps);
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn test_sum_if_factor_edges() {
+        let idiom = Idiom::SumIfFactor {
+            f: 0,
+            i: 1,
+            t: 2,
+            n: 3,
+            s: 4,
+        };
+        // the loop body always runs once, even if i starts past n
+        let mut registers = [3, 4, 0, 12, 0, 0];
+        idiom.apply(&mut registers);
+        assert_eq!(registers, [3, 13, 1, 12, 3, 0]);
+        let mut registers = [5, 4, 0, 12, 0, 0];
+        idiom.apply(&mut registers);
+        assert_eq!(registers, [5, 13, 1, 12, 0, 0]);
+        let mut registers = [3, 20, 0, 12, 0, 0];
+        idiom.apply(&mut registers);
+        assert_eq!(registers, [3, 21, 1, 12, 0, 0]);
+    }
+}
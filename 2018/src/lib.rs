@@ -1,4 +1,7 @@
 // mod direction;
+pub mod decompile;
+pub mod elfcode;
+pub mod idioms;
 // mod parsers;
 // mod position;
 pub mod shared;
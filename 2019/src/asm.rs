@@ -0,0 +1,441 @@
+// a small assembler for intcode. one statement per line, `;` starts a comment:
+//
+//     loop:   in [rb+1]          ; relative mode
+//             out [value]        ; position mode
+//             jt 1, loop         ; immediate mode, labels resolve to addresses
+//             hlt
+//     value:  data 0, 1, 2
+//
+// a line may start with a numeric address (`12:`), which must match the
+// address the statement is assembled to. `disassemble` produces listings in
+// this format, so they assemble back to the same program
+use super::Result;
+use crate::cpu::{Mode, Op};
+use failure::{bail, format_err};
+use std::collections::HashMap;
+use std::convert::TryFrom;
+
+#[derive(Debug, PartialEq, Eq, Clone)]
+enum Value {
+    Number(i64),
+    Label(String),
+}
+
+#[derive(Debug, PartialEq, Eq, Clone)]
+enum Operand {
+    Immediate(Value),
+    Position(Value),
+    Relative(i64),
+}
+
+#[derive(Debug, PartialEq, Eq, Clone)]
+enum Statement {
+    Instruction(&'static Mnemonic, Vec<Operand>),
+    Data(Vec<Value>),
+}
+
+struct Line {
+    number: usize,
+    address: usize,
+    statement: Statement,
+}
+
+#[derive(Debug, PartialEq, Eq)]
+struct Mnemonic {
+    name: &'static str,
+    code: i64,
+    params: usize,
+    // index of the parameter that is written to, which can't be immediate
+    writes: Option<usize>,
+}
+
+#[rustfmt::skip]
+const MNEMONICS: [Mnemonic; 10] = [
+    Mnemonic { name: "add", code: 1, params: 3, writes: Some(2) },
+    Mnemonic { name: "mul", code: 2, params: 3, writes: Some(2) },
+    Mnemonic { name: "in", code: 3, params: 1, writes: Some(0) },
+    Mnemonic { name: "out", code: 4, params: 1, writes: None },
+    Mnemonic { name: "jt", code: 5, params: 2, writes: None },
+    Mnemonic { name: "jf", code: 6, params: 2, writes: None },
+    Mnemonic { name: "lt", code: 7, params: 3, writes: Some(2) },
+    Mnemonic { name: "eq", code: 8, params: 3, writes: Some(2) },
+    Mnemonic { name: "arb", code: 9, params: 1, writes: None },
+    Mnemonic { name: "hlt", code: 99, params: 0, writes: None },
+];
+
+fn mnemonic(name: &str) -> Option<&'static Mnemonic> {
+    MNEMONICS.iter().find(|m| m.name == name)
+}
+
+fn is_label(name: &str) -> bool {
+    let mut chars = name.chars();
+    match chars.next() {
+        Some(c) if c.is_ascii_alphabetic() || c == '_' => {}
+        _ => return false,
+    }
+    chars.all(|c| c.is_ascii_alphanumeric() || c == '_')
+}
+
+fn parse_value(value: &str) -> Result<Value> {
+    if is_label(value) {
+        return Ok(Value::Label(value.into()));
+    }
+    value
+        .parse()
+        .map(Value::Number)
+        .map_err(|_| format_err!("invalid value: {}", value))
+}
+
+fn parse_operand(operand: &str) -> Result<Operand> {
+    if !operand.starts_with('[') {
+        return Ok(Operand::Immediate(parse_value(operand)?));
+    }
+    if !operand.ends_with(']') {
+        bail!("unclosed bracket: {}", operand);
+    }
+    let inner = operand[1..operand.len() - 1].trim();
+    // `rb` only when alone or followed by an offset, so labels like `rbase`
+    // are still positions
+    let offset = inner
+        .strip_prefix("rb")
+        .map(|offset| offset.replace(' ', ""))
+        .filter(|offset| offset.is_empty() || offset.starts_with(&['+', '-'][..]));
+    if let Some(offset) = offset {
+        return Ok(Operand::Relative(match offset.as_str() {
+            "" => 0,
+            offset if offset.starts_with('+') => offset[1..]
+                .parse()
+                .map_err(|_| format_err!("invalid offset: {}", operand))?,
+            offset => offset
+                .parse()
+                .map_err(|_| format_err!("invalid offset: {}", operand))?,
+        }));
+    }
+    Ok(Operand::Position(parse_value(inner)?))
+}
+
+fn split_operands(operands: &str) -> Vec<&str> {
+    operands
+        .split(',')
+        .map(|operand| operand.trim())
+        .filter(|operand| !operand.is_empty())
+        .collect()
+}
+
+fn parse_statement(statement: &str) -> Result<Statement> {
+    let mut parts = statement.splitn(2, char::is_whitespace);
+    let name = parts.next().unwrap_or("");
+    let operands = split_operands(parts.next().unwrap_or(""));
+    if name == "data" {
+        return Ok(Statement::Data(
+            operands
+                .into_iter()
+                .map(parse_value)
+                .collect::<Result<_>>()?,
+        ));
+    }
+    let mnemonic = mnemonic(name).ok_or_else(|| format_err!("unknown mnemonic: {}", name))?;
+    if operands.len() != mnemonic.params {
+        bail!(
+            "{} takes {} operands, got {}",
+            name,
+            mnemonic.params,
+            operands.len()
+        );
+    }
+    let operands = operands
+        .into_iter()
+        .map(parse_operand)
+        .collect::<Result<Vec<_>>>()?;
+    if let Some(index) = mnemonic.writes {
+        if let Operand::Immediate(_) = operands[index] {
+            bail!("{} can't write to an immediate operand", name);
+        }
+    }
+    Ok(Statement::Instruction(mnemonic, operands))
+}
+
+fn size(statement: &Statement) -> usize {
+    match statement {
+        Statement::Instruction(mnemonic, _) => mnemonic.params + 1,
+        Statement::Data(values) => values.len(),
+    }
+}
+
+fn parse(source: &str) -> Result<(Vec<Line>, HashMap<String, usize>)> {
+    let mut lines = vec![];
+    let mut labels = HashMap::new();
+    let mut address = 0;
+    for (index, text) in source.lines().enumerate() {
+        let number = index + 1;
+        let context = |e: failure::Error| format_err!("line {}: {}", number, e);
+        let mut text = text.split(';').next().unwrap_or("").trim();
+        while let Some(colon) = text.find(':') {
+            let label = text[..colon].trim();
+            if let Ok(expected) = label.parse::<usize>() {
+                if expected != address {
+                    return Err(context(format_err!(
+                        "address {} doesn't match actual address {}",
+                        expected,
+                        address
+                    )));
+                }
+            } else if is_label(label) {
+                if labels.insert(label.to_string(), address).is_some() {
+                    return Err(context(format_err!("duplicate label: {}", label)));
+                }
+            } else {
+                return Err(context(format_err!("invalid label: {}", label)));
+            }
+            text = text[colon + 1..].trim();
+        }
+        if text.is_empty() {
+            continue;
+        }
+        let statement = parse_statement(text).map_err(context)?;
+        let line = Line {
+            number,
+            address,
+            statement,
+        };
+        address += size(&line.statement);
+        lines.push(line);
+    }
+    Ok((lines, labels))
+}
+
+fn resolve(value: &Value, labels: &HashMap<String, usize>) -> Result<i64> {
+    match value {
+        Value::Number(n) => Ok(*n),
+        Value::Label(label) => labels
+            .get(label)
+            .map(|&address| address as i64)
+            .ok_or_else(|| format_err!("undefined label: {}", label)),
+    }
+}
+
+pub fn assemble_program(source: &str) -> Result<Vec<i64>> {
+    let (lines, labels) = parse(source)?;
+    let mut program = vec![];
+    for line in lines.iter() {
+        let context = |e: failure::Error| format_err!("line {}: {}", line.number, e);
+        debug_assert_eq!(program.len(), line.address);
+        match &line.statement {
+            Statement::Data(values) => {
+                for value in values.iter() {
+                    program.push(resolve(value, &labels).map_err(context)?);
+                }
+            }
+            Statement::Instruction(mnemonic, operands) => {
+                let mut code = mnemonic.code;
+                let mut factor = 100;
+                let mut params = vec![];
+                for operand in operands.iter() {
+                    let (mode, param) = match operand {
+                        Operand::Position(value) => (0, resolve(value, &labels).map_err(context)?),
+                        Operand::Immediate(value) => (1, resolve(value, &labels).map_err(context)?),
+                        Operand::Relative(offset) => (2, *offset),
+                    };
+                    code += mode * factor;
+                    factor *= 10;
+                    params.push(param);
+                }
+                program.push(code);
+                program.extend(params);
+            }
+        }
+    }
+    Ok(program)
+}
+
+pub fn assemble(source: &str) -> Result<String> {
+    Ok(assemble_program(source)?
+        .iter()
+        .map(|value| value.to_string())
+        .collect::<Vec<_>>()
+        .join(","))
+}
+
+fn op_mnemonic(op: &Op) -> (&'static Mnemonic, Vec<Mode>) {
+    use Op::*;
+    let (name, modes) = match *op {
+        Add(a, b, c) => ("add", vec![a, b, c]),
+        Mul(a, b, c) => ("mul", vec![a, b, c]),
+        Input(a) => ("in", vec![a]),
+        Output(a) => ("out", vec![a]),
+        JumpIfTrue(a, b) => ("jt", vec![a, b]),
+        JumpIfFalse(a, b) => ("jf", vec![a, b]),
+        LessThan(a, b, c) => ("lt", vec![a, b, c]),
+        Equals(a, b, c) => ("eq", vec![a, b, c]),
+        AdjustRelativeBase(a) => ("arb", vec![a]),
+        Halt => ("hlt", vec![]),
+    };
+    (mnemonic(name).expect("all ops have mnemonics"), modes)
+}
+
+fn format_operand(mode: Mode, param: i64) -> String {
+    match mode {
+        Mode::Immediate => param.to_string(),
+        Mode::Position => format!("[{}]", param),
+        Mode::Relative if param < 0 => format!("[rb{}]", param),
+        Mode::Relative => format!("[rb+{}]", param),
+    }
+}
+
+fn mode_digit(mode: Mode) -> i64 {
+    match mode {
+        Mode::Position => 0,
+        Mode::Immediate => 1,
+        Mode::Relative => 2,
+    }
+}
+
+// decodes the instruction at the start of `values` into its text and size, if
+// it is one that `assemble` would produce
+fn decode(values: &[i64]) -> Option<(String, usize)> {
+    let op = Op::try_from(values[0]).ok()?;
+    let (mnemonic, modes) = op_mnemonic(&op);
+    let params = values.get(1..=mnemonic.params)?;
+    let canonical: i64 = modes
+        .iter()
+        .enumerate()
+        .map(|(index, &mode)| mode_digit(mode) * 10i64.pow(index as u32 + 2))
+        .sum::<i64>()
+        + mnemonic.code;
+    if canonical != values[0] {
+        return None;
+    }
+    if let Some(index) = mnemonic.writes {
+        if modes[index] == Mode::Immediate {
+            return None;
+        }
+    }
+    let operands: Vec<_> = modes
+        .iter()
+        .zip(params.iter())
+        .map(|(&mode, &param)| format_operand(mode, param))
+        .collect();
+    let text = if operands.is_empty() {
+        mnemonic.name.to_string()
+    } else {
+        format!("{} {}", mnemonic.name, operands.join(", "))
+    };
+    Some((text, mnemonic.params + 1))
+}
+
+pub fn listing(program: &[i64]) -> String {
+    let mut lines = vec![];
+    let mut address = 0;
+    while address < program.len() {
+        let (text, size) = decode(&program[address..])
+            .unwrap_or_else(|| (format!("data {}", program[address]), 1));
+        lines.push(format!("{}: {}", address, text));
+        address += size;
+    }
+    lines.join("\n")
+}
+
+pub fn disassemble(program_str: &str) -> Result<String> {
+    let program = program_str
+        .trim()
+        .split(',')
+        .map(|value| {
+            value
+                .trim()
+                .parse()
+                .map_err(|_| format_err!("invalid integer: {}", value))
+        })
+        .collect::<Result<Vec<i64>>>()?;
+    Ok(listing(&program))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::cpu::{read_memory, Cpu, CpuState};
+
+    #[test]
+    fn test_assemble() -> Result<()> {
+        assert_eq!(assemble("in [rb+10]\nhlt")?, "203,10,99");
+        assert_eq!(
+            assemble(
+                "
+                start:  in [value]      ; read
+                        mul [value], 2, [rb-1]
+                        jt 0, start
+                        hlt
+                value:  data 7, start
+                "
+            )?,
+            "3,10,21002,10,2,-1,1105,0,0,99,7,0"
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_errors() {
+        let error = |source| assemble(source).unwrap_err().to_string();
+        assert_eq!(error("hlt\nfoo 1"), "line 2: unknown mnemonic: foo");
+        assert_eq!(error("add 1, 2"), "line 1: add takes 3 operands, got 2");
+        assert_eq!(
+            error("add 1, 2, 3"),
+            "line 1: add can't write to an immediate operand"
+        );
+        assert_eq!(error("jt 1, nowhere"), "line 1: undefined label: nowhere");
+        assert_eq!(error("a: hlt\na: hlt"), "line 2: duplicate label: a");
+        assert_eq!(
+            error("hlt\n0: hlt"),
+            "line 2: address 0 doesn't match actual address 1"
+        );
+    }
+
+    #[test]
+    fn test_run() -> Result<()> {
+        let mut cpu = Cpu::from_str(&assemble(
+            "
+                    arb 2
+                    in [rb+30]
+            loop:   out [counter]
+                    add [counter], 1, [counter]
+                    lt [counter], 3, [rb-2]
+                    jt [0], loop
+                    hlt
+            counter: data 0
+            ",
+        )?);
+        cpu.enqueue_input(5);
+        for expected in 0..3 {
+            assert_eq!(cpu.run()?, CpuState::Output(expected));
+        }
+        assert_eq!(cpu.run()?, CpuState::Halted);
+        assert_eq!(read_memory(&cpu, 32), 5);
+        Ok(())
+    }
+
+    #[test]
+    fn test_rb_label() -> Result<()> {
+        assert_eq!(
+            assemble("in [rbase]\nin [rb]\nin [rb - 1]\nhlt\nrbase: data 0")?,
+            "3,7,203,0,203,-1,99,0"
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_round_trip() -> Result<()> {
+        for program in &[
+            "203,10,99",
+            "109,1,204,-1,1001,100,1,100,1008,100,16,101,1006,101,0,99",
+            "3,15,3,16,1002,16,10,16,1,16,15,15,4,15,99,0,0",
+            "11104,3,1101,1,2,1,99,1",
+        ] {
+            let listing = disassemble(program)?;
+            assert_eq!(&assemble(&listing)?, program);
+        }
+        assert_eq!(
+            disassemble("11104,3,1101,1,2,3,99,1")?,
+            "0: data 11104\n1: in [1101]\n3: add [2], [3], [99]\n7: data 1"
+        );
+        Ok(())
+    }
+}
@@ -1,5 +1,7 @@
 use aoc2019::cpu::{Cpu, CpuState};
+use aoc2019::network::{Address, Network, StopCondition, Topology};
 use aoc2019::{dispatch, Result};
+use failure::err_msg;
 use permutohedron::LexicalPermutation;
 
 fn main() -> Result<()> {
@@ -42,38 +44,17 @@ fn part2(input: &str) -> Result<i64> {
     let mut max_signal = 0;
 
     loop {
-        let mut cpus = vec![
-            Cpu::from_str(input),
-            Cpu::from_str(input),
-            Cpu::from_str(input),
-            Cpu::from_str(input),
-            Cpu::from_str(input),
-        ];
-
+        let mut network = Network::from_program(input, 5, Topology::Ring);
         for (index, &phase) in phases.iter().enumerate() {
-            cpus[index].enqueue_input(phase);
+            network.enqueue_input(index, phase);
         }
-        cpus[0].enqueue_input(0);
+        network.enqueue_input(0, 0);
 
-        let mut running = [true; 5];
-        let mut signal = 0;
-        for index in (0..5).cycle() {
-            if !running.iter().any(|&r| r) {
-                break;
-            }
-            let state = cpus[index].run()?;
-            // dbg!(index, state);
-            match state {
-                CpuState::Halted => running[index] = false,
-                CpuState::NeedsInput => {}
-                CpuState::Output(value) => {
-                    if index == 4 {
-                        signal = value;
-                    }
-                    cpus[(index + 1) % 5].enqueue_input(value);
-                }
-            }
-        }
+        let trace = network.run(StopCondition::AllHalted)?;
+        let signal = trace
+            .last_from(Address::Cpu(4))
+            .map(|message| message.values[0])
+            .ok_or_else(|| err_msg("no output from last amplifier"))?;
 
         if signal > max_signal {
             max_signal = signal;
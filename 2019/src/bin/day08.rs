@@ -72,7 +72,7 @@ fn write_image(input: &str, width: usize, height: usize) -> Result<String> {
 }
 
 fn part2(input: &str) -> Result<String> {
-    write_image(input, 25, 6)
+    Ok(ocr::read(&write_image(input, 25, 6)?)?)
 }
 
 #[cfg(test)]
@@ -1,7 +1,7 @@
 use aoc2019::cpu::{Cpu, CpuState};
 use aoc2019::{dispatch, Result};
 use failure::{bail, Error};
-use std::collections::HashMap;
+use std::collections::{HashMap, VecDeque};
 use std::convert::TryFrom;
 use std::fmt;
 use std::ops::Add;
@@ -41,15 +41,6 @@ enum Direction {
 }
 
 impl Direction {
-    fn opposite(&self) -> Self {
-        use Direction::*;
-        match self {
-            North => South,
-            South => North,
-            West => East,
-            East => West,
-        }
-    }
     fn as_input(&self) -> i64 {
         use Direction::*;
         match self {
@@ -112,56 +103,35 @@ fn step(cpu: &mut Cpu, direction: Direction) -> Result<Tile> {
 
 fn map(input: &str) -> Result<(HashMap<Coor, Tile>, Coor, usize)> {
     use Tile::*;
-    let mut cpu = Cpu::from_str(input);
-    let mut queue = vec![];
     let start = Coor::default();
-    for direction in &Direction::all() {
-        queue.push((start, *direction, 1));
-    }
+    let mut queue = VecDeque::new();
+    queue.push_back((start, Cpu::from_str(input), 0));
     let mut tried = HashMap::new();
     tried.insert(start, Tile::Open);
-    let mut path: Vec<(Direction, Coor)> = vec![];
     let mut oxygen: Option<(Coor, usize)> = None;
-    loop {
-        let (pos, direction, distance) = match queue.pop() {
-            Some(entry) => entry,
-            None => {
-                if let Some(target) = oxygen {
-                    return Ok((tried, target.0, target.1));
-                } else {
-                    bail!("didn't find oxygen");
-                }
+    while let Some((pos, cpu, distance)) = queue.pop_front() {
+        for direction in &Direction::all() {
+            let next = pos + direction.as_coor();
+            if tried.contains_key(&next) {
+                continue;
             }
-        };
-
-        while path.len() >= distance {
-            let (direction, _) = path.pop().expect("path should not be empty");
-            match step(&mut cpu, direction.opposite())? {
-                Open | Oxygen => {}
-                t => unreachable!("Unexpected tile when going back: {:?}", t),
-            };
-        }
-
-        let next = pos + direction.as_coor();
-
-        let response = step(&mut cpu, direction)?;
-        tried.insert(next, response);
-        if response == Oxygen {
-            oxygen = Some((next, distance));
-        }
-        match response {
-            Open | Oxygen => {
-                path.push((direction, next));
-                for direction in &Direction::all() {
-                    if tried.contains_key(&(next + direction.as_coor())) {
-                        continue;
-                    }
-                    queue.push((next, *direction, distance + 1));
+            let mut branch = cpu.snapshot();
+            let response = step(&mut branch, *direction)?;
+            tried.insert(next, response);
+            match response {
+                Oxygen => {
+                    oxygen.get_or_insert((next, distance + 1));
+                    queue.push_back((next, branch, distance + 1));
                 }
+                Open => queue.push_back((next, branch, distance + 1)),
+                Wall => {}
             }
-            Wall => {}
         }
     }
+    match oxygen {
+        Some((target, distance)) => Ok((tried, target, distance)),
+        None => bail!("didn't find oxygen"),
+    }
 }
 
 fn part1(input: &str) -> Result<usize> {
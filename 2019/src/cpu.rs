@@ -1,10 +1,15 @@
 use super::Result;
-use failure::{bail, err_msg, Error};
+use crate::memory::Memory;
+use failure::{bail, err_msg, format_err, Error};
+use std::cell::RefCell;
 use std::collections::{HashMap, VecDeque};
 use std::convert::TryFrom;
+use std::fs;
+use std::path::Path;
+use std::rc::Rc;
 
-#[derive(Debug, PartialEq, Eq)]
-enum Mode {
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub(crate) enum Mode {
     Position,
     Immediate,
     Relative,
@@ -49,8 +54,8 @@ impl Modes {
     }
 }
 
-#[derive(Debug, PartialEq, Eq)]
-enum Op {
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub(crate) enum Op {
     Add(Mode, Mode, Mode),
     Mul(Mode, Mode, Mode),
     Input(Mode),
@@ -97,23 +102,168 @@ pub enum CpuState {
     Halted,
 }
 
+// decoded ops by address, tagged with the raw value they were decoded from so
+// that self-modifying programs still decode correctly. the tag makes entries
+// valid for any cpu, so the cache is shared between snapshots
+type DecodeCache = Rc<RefCell<Vec<Option<(i64, Op)>>>>;
+
+// memory pages are shared between clones until written to, so snapshotting a
+// cpu to branch a search is cheap
+#[derive(Debug, Clone)]
 pub struct Cpu {
     pc: usize,
-    program: Vec<i64>,
+    program_len: usize,
     input: VecDeque<i64>,
     relative_base: i64,
-    memory: HashMap<i64, i64>,
+    memory: Memory,
+    decoded: DecodeCache,
+}
+
+fn join(values: impl Iterator<Item = String>) -> String {
+    values.collect::<Vec<_>>().join(",")
+}
+
+fn parse_list<T>(value: &str, parse: impl Fn(&str) -> Result<T>) -> Result<Vec<T>> {
+    value
+        .split(',')
+        .filter(|item| !item.is_empty())
+        .map(parse)
+        .collect()
+}
+
+fn parse_int(value: &str) -> Result<i64> {
+    value
+        .parse()
+        .map_err(|_| format_err!("invalid integer: {}", value))
+}
+
+// fnv-1a, so hashes are stable across runs and compiler versions
+struct StateHasher(u64);
+
+impl StateHasher {
+    fn new() -> Self {
+        StateHasher(0xcbf29ce484222325)
+    }
+
+    fn write(&mut self, value: i64) {
+        for byte in value.to_le_bytes().iter() {
+            self.0 ^= *byte as u64;
+            self.0 = self.0.wrapping_mul(0x100000001b3);
+        }
+    }
 }
 
 impl Cpu {
     fn new(program: Vec<i64>) -> Self {
+        Self::with_memory(program.len(), Memory::from_vec(program))
+    }
+
+    fn with_memory(program_len: usize, memory: Memory) -> Self {
         Cpu {
             pc: 0,
-            program,
+            program_len,
             input: VecDeque::new(),
             relative_base: 0,
-            memory: HashMap::new(),
+            memory,
+            decoded: Rc::new(RefCell::new(vec![None; program_len])),
+        }
+    }
+
+    fn program(&self) -> impl Iterator<Item = i64> + '_ {
+        (0..self.program_len).map(move |address| self.memory.get(address))
+    }
+
+    pub fn snapshot(&self) -> Self {
+        self.clone()
+    }
+
+    fn sorted_memory(&self) -> Vec<(i64, i64)> {
+        self.memory
+            .nonzero_from(self.program_len)
+            .map(|(address, value)| (address as i64, value))
+            .collect()
+    }
+
+    pub fn state_hash(&self) -> u64 {
+        let mut hasher = StateHasher::new();
+        hasher.write(self.pc as i64);
+        hasher.write(self.relative_base);
+        for value in self.program() {
+            hasher.write(value);
+        }
+        // separators keep e.g. program [1] + memory [] apart from program [] + memory [1]
+        hasher.write(i64::MIN);
+        for (address, value) in self.sorted_memory() {
+            hasher.write(address);
+            hasher.write(value);
+        }
+        hasher.write(i64::MIN);
+        for &value in self.input.iter() {
+            hasher.write(value);
+        }
+        hasher.0
+    }
+
+    pub fn serialize(&self) -> String {
+        format!(
+            "pc {}\nrelative_base {}\nprogram {}\nmemory {}\ninput {}\n",
+            self.pc,
+            self.relative_base,
+            join(self.program().map(|v| v.to_string())),
+            join(
+                self.sorted_memory()
+                    .into_iter()
+                    .map(|(address, value)| format!("{}={}", address, value))
+            ),
+            join(self.input.iter().map(|v| v.to_string())),
+        )
+    }
+
+    pub fn deserialize(state: &str) -> Result<Self> {
+        let mut fields = HashMap::new();
+        for line in state.lines().filter(|line| !line.is_empty()) {
+            let mut parts = line.splitn(2, ' ');
+            let key = parts.next().unwrap_or("");
+            fields.insert(key, parts.next().unwrap_or(""));
+        }
+        let field = |key| {
+            fields
+                .get(key)
+                .copied()
+                .ok_or_else(|| format_err!("missing field: {}", key))
+        };
+        let memory = parse_list(field("memory")?, |entry| {
+            let mut parts = entry.splitn(2, '=');
+            match (parts.next(), parts.next()) {
+                (Some(address), Some(value)) => Ok((parse_int(address)?, parse_int(value)?)),
+                _ => bail!("invalid memory entry: {}", entry),
+            }
+        })?;
+        let program = parse_list(field("program")?, parse_int)?;
+        let program_len = program.len();
+        let mut cpu = Self::with_memory(program_len, Memory::from_vec(program));
+        for (address, value) in memory {
+            if address < program_len as i64 {
+                bail!("memory entry inside program: {}", address);
+            }
+            cpu.memory.set(address as usize, value);
         }
+        let pc = parse_int(field("pc")?)?;
+        cpu.pc = usize::try_from(pc).map_err(|_| format_err!("negative pc: {}", pc))?;
+        cpu.relative_base = parse_int(field("relative_base")?)?;
+        cpu.input = parse_list(field("input")?, parse_int)?
+            .into_iter()
+            .collect();
+        Ok(cpu)
+    }
+
+    pub fn save<P: AsRef<Path>>(&self, path: P) -> Result<()> {
+        fs::write(path, self.serialize())?;
+        Ok(())
+    }
+
+    pub fn load<P: AsRef<Path>>(path: P) -> Result<Self> {
+        Self::deserialize(&fs::read_to_string(path)?)
     }
 
     pub fn from_str(program_str: &str) -> Self {
@@ -128,6 +278,10 @@ impl Cpu {
         self.input.push_back(value);
     }
 
+    pub fn input_len(&self) -> usize {
+        self.input.len()
+    }
+
     fn get(&self, mode: Mode, source: i64) -> i64 {
         match mode {
             Mode::Immediate => source,
@@ -137,65 +291,86 @@ impl Cpu {
     }
 
     fn get_mem(&self, source: i64) -> i64 {
-        *self
-            .program
-            .get(source as usize)
-            .unwrap_or(self.memory.get(&source).unwrap_or(&0))
+        if source < 0 {
+            return 0;
+        }
+        self.memory.get(source as usize)
+    }
+
+    #[inline]
+    fn param(&self, offset: usize) -> i64 {
+        self.memory.get(self.pc + offset)
     }
 
-    fn set(&mut self, mode: Mode, destination: i64, value: i64) {
+    fn decode(&self) -> Result<Op> {
+        let value = self.memory.get(self.pc);
+        let mut decoded = self.decoded.borrow_mut();
+        if let Some(Some((raw, op))) = decoded.get(self.pc) {
+            if *raw == value {
+                return Ok(*op);
+            }
+        }
+        let op = Op::try_from(value)?;
+        // only the program itself is cached; code jumped to far past it is
+        // decoded every time rather than growing the cache up to it
+        if let Some(entry) = decoded.get_mut(self.pc) {
+            *entry = Some((value, op));
+        }
+        Ok(op)
+    }
+
+    fn set(&mut self, mode: Mode, destination: i64, value: i64) -> Result<()> {
         let destination = match mode {
             Mode::Immediate => unreachable!("set called with immediate mode"),
             Mode::Position => destination,
             Mode::Relative => self.relative_base + destination,
         };
-        assert!(destination >= 0);
-        if destination as usize >= self.program.len() {
-            self.memory.insert(destination, value);
-        } else {
-            self.program[destination as usize] = value;
+        if destination < 0 {
+            bail!("write to negative address {} at {}", destination, self.pc);
         }
+        self.memory.set(destination as usize, value);
+        Ok(())
     }
 
     pub fn run(&mut self) -> Result<CpuState> {
         let state = loop {
-            let op = Op::try_from(self.program[self.pc])?;
-            // dbg!(self.pc, &op, &self.program);
+            let op = self.decode()?;
+            // dbg!(self.pc, &op);
             use Op::*;
             match op {
                 Add(mode1, mode2, mode3) => {
-                    let a = self.program[self.pc + 1];
-                    let b = self.program[self.pc + 2];
-                    let c = self.program[self.pc + 3];
-                    self.set(mode3, c, self.get(mode1, a) + self.get(mode2, b));
+                    let a = self.param(1);
+                    let b = self.param(2);
+                    let c = self.param(3);
+                    self.set(mode3, c, self.get(mode1, a) + self.get(mode2, b))?;
                     self.pc += 4;
                 }
                 Mul(mode1, mode2, mode3) => {
-                    let a = self.program[self.pc + 1];
-                    let b = self.program[self.pc + 2];
-                    let c = self.program[self.pc + 3];
-                    self.set(mode3, c, self.get(mode1, a) * self.get(mode2, b));
+                    let a = self.param(1);
+                    let b = self.param(2);
+                    let c = self.param(3);
+                    self.set(mode3, c, self.get(mode1, a) * self.get(mode2, b))?;
                     self.pc += 4;
                 }
                 Input(mode) => {
-                    let a = self.program[self.pc + 1];
+                    let a = self.param(1);
                     match self.input.pop_front() {
                         None => break CpuState::NeedsInput,
                         Some(value) => {
-                            self.set(mode, a, value);
+                            self.set(mode, a, value)?;
                             self.pc += 2;
                         }
                     }
                 }
                 Output(mode) => {
-                    let a = self.program[self.pc + 1];
+                    let a = self.param(1);
                     let value = self.get(mode, a);
                     self.pc += 2;
                     break CpuState::Output(value);
                 }
                 JumpIfTrue(mode1, mode2) => {
-                    let a = self.program[self.pc + 1];
-                    let b = self.program[self.pc + 2];
+                    let a = self.param(1);
+                    let b = self.param(2);
                     if self.get(mode1, a) != 0 {
                         self.pc = self.get(mode2, b) as usize;
                     } else {
@@ -203,8 +378,8 @@ impl Cpu {
                     }
                 }
                 JumpIfFalse(mode1, mode2) => {
-                    let a = self.program[self.pc + 1];
-                    let b = self.program[self.pc + 2];
+                    let a = self.param(1);
+                    let b = self.param(2);
                     if self.get(mode1, a) == 0 {
                         self.pc = self.get(mode2, b) as usize;
                     } else {
@@ -212,9 +387,9 @@ impl Cpu {
                     }
                 }
                 LessThan(mode1, mode2, mode3) => {
-                    let a = self.program[self.pc + 1];
-                    let b = self.program[self.pc + 2];
-                    let c = self.program[self.pc + 3];
+                    let a = self.param(1);
+                    let b = self.param(2);
+                    let c = self.param(3);
                     self.set(
                         mode3,
                         c,
@@ -223,13 +398,13 @@ impl Cpu {
                         } else {
                             0
                         },
-                    );
+                    )?;
                     self.pc += 4;
                 }
                 Equals(mode1, mode2, mode3) => {
-                    let a = self.program[self.pc + 1];
-                    let b = self.program[self.pc + 2];
-                    let c = self.program[self.pc + 3];
+                    let a = self.param(1);
+                    let b = self.param(2);
+                    let c = self.param(3);
                     self.set(
                         mode3,
                         c,
@@ -238,11 +413,11 @@ impl Cpu {
                         } else {
                             0
                         },
-                    );
+                    )?;
                     self.pc += 4;
                 }
                 AdjustRelativeBase(mode) => {
-                    let a = self.program[self.pc + 1];
+                    let a = self.param(1);
                     self.relative_base += self.get(mode, a);
                     self.pc += 2;
                 }
@@ -259,12 +434,13 @@ pub fn read_memory(cpu: &Cpu, position: usize) -> i64 {
 }
 
 pub fn set_memory(cpu: &mut Cpu, position: usize, value: i64) {
-    cpu.program[position] = value;
+    cpu.memory.set(position, value);
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::asm::assemble;
 
     #[test]
     fn test_op() -> Result<()> {
@@ -278,11 +454,211 @@ mod tests {
 
     #[test]
     fn test_203() -> Result<()> {
-        let mut cpu = Cpu::from_str("203,10,99");
+        let mut cpu = Cpu::from_str(&assemble("in [rb+10]\nhlt")?);
         cpu.relative_base = 2;
         cpu.enqueue_input(1);
         assert_eq!(cpu.run()?, CpuState::Halted);
         assert_eq!(read_memory(&cpu, 12), 1);
         Ok(())
     }
+
+    #[test]
+    fn test_snapshot() -> Result<()> {
+        let mut cpu = Cpu::from_str("3,0,4,0,99");
+        let mut branch = cpu.snapshot();
+        cpu.enqueue_input(1);
+        branch.enqueue_input(2);
+        assert_eq!(cpu.run()?, CpuState::Output(1));
+        assert_eq!(branch.run()?, CpuState::Output(2));
+        assert_eq!(read_memory(&cpu, 0), 1);
+        assert_eq!(read_memory(&branch, 0), 2);
+        Ok(())
+    }
+
+    #[test]
+    fn test_serialize() -> Result<()> {
+        let mut cpu = Cpu::from_str("109,5,203,1000,3,0,99");
+        cpu.enqueue_input(7);
+        cpu.enqueue_input(8);
+        cpu.enqueue_input(9);
+        assert_eq!(cpu.run()?, CpuState::Halted);
+        assert_eq!(read_memory(&cpu, 1005), 7);
+
+        let state = cpu.serialize();
+        assert_eq!(
+            state,
+            "pc 6\nrelative_base 5\nprogram 8,5,203,1000,3,0,99\nmemory 1005=7\ninput 9\n"
+        );
+        let restored = Cpu::deserialize(&state)?;
+        assert_eq!(restored.serialize(), state);
+        assert_eq!(restored.state_hash(), cpu.state_hash());
+        Ok(())
+    }
+
+    #[test]
+    fn test_deserialize_negative_pc() {
+        let state = "pc -1\nrelative_base 0\nprogram 99\nmemory \ninput \n";
+        assert_eq!(
+            Cpu::deserialize(state).unwrap_err().to_string(),
+            "negative pc: -1"
+        );
+    }
+
+    #[test]
+    fn test_state_hash() -> Result<()> {
+        let a = Cpu::from_str("3,0,99");
+        let mut b = a.snapshot();
+        assert_eq!(a.state_hash(), b.state_hash());
+        b.enqueue_input(0);
+        assert_ne!(a.state_hash(), b.state_hash());
+        assert_eq!(b.run()?, CpuState::Halted);
+        let mut c = Cpu::from_str("0,0,99");
+        c.pc = 2;
+        assert_eq!(b.state_hash(), c.state_hash());
+        Ok(())
+    }
+
+    fn outputs(cpu: &mut Cpu) -> Result<Vec<i64>> {
+        let mut output = vec![];
+        loop {
+            match cpu.run()? {
+                CpuState::Output(value) => output.push(value),
+                CpuState::Halted => break,
+                CpuState::NeedsInput => bail!("unexpected input request"),
+            }
+        }
+        Ok(output)
+    }
+
+    #[test]
+    fn test_high_addresses() -> Result<()> {
+        let quine = "109,1,204,-1,1001,100,1,100,1008,100,16,101,1006,101,0,99";
+        let mut cpu = Cpu::from_str(quine);
+        assert_eq!(
+            join(outputs(&mut cpu)?.iter().map(|v| v.to_string())),
+            quine
+        );
+
+        let mut cpu = Cpu::from_str("21101,3,4,1000000,204,1000000,99");
+        assert_eq!(outputs(&mut cpu)?, vec![7]);
+        Ok(())
+    }
+
+    #[test]
+    fn test_self_modifying() -> Result<()> {
+        // outputs, then overwrites the output at 0 with a halt and jumps back
+        let mut cpu = Cpu::from_str(&assemble(
+            "
+            start:  out 1
+                    add 0, 99, [start]
+                    jt 1, start
+            ",
+        )?);
+        assert_eq!(outputs(&mut cpu)?, vec![1]);
+        Ok(())
+    }
+
+    #[test]
+    fn test_far_jump() -> Result<()> {
+        // writes `out 7, hlt` far past the program and jumps there
+        let mut cpu = Cpu::from_str(&assemble(
+            "
+                    add 104, 0, [1000000000]
+                    add 7, 0, [1000000001]
+                    add 99, 0, [1000000002]
+                    jt 1, 1000000000
+            ",
+        )?);
+        assert_eq!(outputs(&mut cpu)?, vec![7]);
+        assert_eq!(cpu.decoded.borrow().len(), cpu.program_len);
+        Ok(())
+    }
+
+    #[test]
+    fn test_negative_address() -> Result<()> {
+        let mut cpu = Cpu::from_str(&assemble("arb -5\nadd 1, 2, [rb+1]\nhlt")?);
+        assert_eq!(
+            cpu.run().unwrap_err().to_string(),
+            "write to negative address -4 at 2"
+        );
+        Ok(())
+    }
+
+    // counts the primes below its input with a sieve of eratosthenes, with a
+    // flag per number from address 1000 up. the relative base points at the
+    // current number's flag
+    const SIEVE: &str = "
+                in [n]
+                arb 1002
+                add 2, 0, [i]
+        outer:  lt [i], [n], [t]
+                jf [t], done
+                jt [rb+0], next
+                add [count], 1, [count]
+                add [i], [i], [j]
+                mul [i], -1, [back]
+                arb [i]
+        inner:  lt [j], [n], [t]
+                jf [t], restore
+                add 1, 0, [rb+0]
+                add [j], [i], [j]
+                mul [i], -1, [t]
+                add [back], [t], [back]
+                arb [i]
+                jt 1, inner
+        restore: arb [back]
+        next:   add [i], 1, [i]
+                arb 1
+                jt 1, outer
+        done:   out [count]
+                hlt
+        n:      data 0
+        i:      data 0
+        j:      data 0
+        t:      data 0
+        back:   data 0
+        count:  data 0
+    ";
+
+    fn sieve(below: i64) -> Result<Vec<i64>> {
+        let mut cpu = Cpu::from_str(&assemble(SIEVE)?);
+        cpu.enqueue_input(below);
+        outputs(&mut cpu)
+    }
+
+    #[test]
+    fn test_sieve() -> Result<()> {
+        assert_eq!(sieve(100)?, vec![25]);
+        Ok(())
+    }
+
+    // cargo test --release --lib bench_sieve -- --ignored --nocapture
+    #[test]
+    #[ignore]
+    fn bench_sieve() -> Result<()> {
+        let start = std::time::Instant::now();
+        assert_eq!(sieve(10_000)?, vec![1229]);
+        println!("sieve below 10,000 in {:?}", start.elapsed());
+        Ok(())
+    }
+
+    // the day 9 BOOST program in sensor boost mode, skipped without the input:
+    // cargo test --release --lib bench_boost -- --ignored --nocapture
+    #[test]
+    #[ignore]
+    fn bench_boost() -> Result<()> {
+        let program = match fs::read_to_string("input/day09.txt") {
+            Ok(program) => program,
+            Err(_) => {
+                println!("input/day09.txt not found, skipping");
+                return Ok(());
+            }
+        };
+        let start = std::time::Instant::now();
+        let mut cpu = Cpu::from_str(program.trim());
+        cpu.enqueue_input(2);
+        let output = outputs(&mut cpu)?;
+        println!("{:?} in {:?}", output, start.elapsed());
+        Ok(())
+    }
 }
@@ -1,3 +1,6 @@
+pub mod asm;
 pub mod cpu;
+pub mod memory;
+pub mod network;
 pub mod shared;
 pub use crate::shared::{dispatch, Result};
@@ -0,0 +1,140 @@
+use std::collections::HashMap;
+use std::rc::Rc;
+
+const PAGE_BITS: u32 = 10;
+const PAGE_SIZE: usize = 1 << PAGE_BITS;
+const OFFSET_MASK: usize = PAGE_SIZE - 1;
+// pages below this are indexed directly, the rest are looked up in a map so
+// that a write far out doesn't allocate a page table all the way up to it
+const DENSE_PAGES: usize = 1 << 10;
+
+type Page = [i64; PAGE_SIZE];
+
+// intcode memory as fixed size pages. pages are only allocated once written
+// to, and are shared between clones until one of them writes to the page
+#[derive(Debug, Clone, Default)]
+pub struct Memory {
+    dense: Vec<Option<Rc<Page>>>,
+    sparse: HashMap<usize, Rc<Page>>,
+}
+
+impl Memory {
+    pub fn new() -> Self {
+        Memory {
+            dense: vec![],
+            sparse: HashMap::new(),
+        }
+    }
+
+    pub fn from_vec(values: Vec<i64>) -> Self {
+        let mut memory = Memory::new();
+        for (address, value) in values.into_iter().enumerate() {
+            if value != 0 {
+                memory.set(address, value);
+            }
+        }
+        memory
+    }
+
+    #[inline]
+    fn page(&self, index: usize) -> Option<&Rc<Page>> {
+        if index < DENSE_PAGES {
+            self.dense.get(index).and_then(Option::as_ref)
+        } else {
+            self.sparse.get(&index)
+        }
+    }
+
+    #[inline]
+    pub fn get(&self, address: usize) -> i64 {
+        match self.page(address >> PAGE_BITS) {
+            Some(page) => page[address & OFFSET_MASK],
+            None => 0,
+        }
+    }
+
+    #[inline]
+    pub fn set(&mut self, address: usize, value: i64) {
+        let index = address >> PAGE_BITS;
+        if value == 0 && self.page(index).is_none() {
+            return;
+        }
+        let page = if index < DENSE_PAGES {
+            if index >= self.dense.len() {
+                self.dense.resize(index + 1, None);
+            }
+            self.dense[index].get_or_insert_with(|| Rc::new([0; PAGE_SIZE]))
+        } else {
+            self.sparse
+                .entry(index)
+                .or_insert_with(|| Rc::new([0; PAGE_SIZE]))
+        };
+        Rc::make_mut(page)[address & OFFSET_MASK] = value;
+    }
+
+    // non-zero values at or above `start`, in address order
+    pub fn nonzero_from(&self, start: usize) -> impl Iterator<Item = (usize, i64)> + '_ {
+        let mut sparse: Vec<(usize, &Rc<Page>)> = self
+            .sparse
+            .iter()
+            .map(|(&index, page)| (index, page))
+            .collect();
+        sparse.sort_by_key(|&(index, _)| index);
+        self.dense
+            .iter()
+            .enumerate()
+            .filter_map(|(index, page)| page.as_ref().map(|page| (index, page)))
+            .chain(sparse)
+            .filter(move |&(index, _)| index >= start >> PAGE_BITS)
+            .flat_map(|(index, page)| {
+                page.iter()
+                    .enumerate()
+                    .map(move |(offset, &value)| ((index << PAGE_BITS) + offset, value))
+            })
+            .filter(move |&(address, value)| address >= start && value != 0)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_get_set() {
+        let mut memory = Memory::from_vec(vec![1, 0, 3]);
+        assert_eq!(memory.get(0), 1);
+        assert_eq!(memory.get(1), 0);
+        assert_eq!(memory.get(100_000), 0);
+        memory.set(100_000, 5);
+        assert_eq!(memory.get(100_000), 5);
+        assert_eq!(
+            memory.nonzero_from(1).collect::<Vec<_>>(),
+            vec![(2, 3), (100_000, 5)]
+        );
+    }
+
+    #[test]
+    fn test_copy_on_write() {
+        let mut a = Memory::from_vec(vec![1, 2, 3]);
+        let b = a.clone();
+        a.set(1, 20);
+        assert_eq!(a.get(1), 20);
+        assert_eq!(b.get(1), 2);
+    }
+
+    #[test]
+    fn test_sparse() {
+        let mut memory = Memory::from_vec(vec![1]);
+        memory.set(1_000_000_000_000, 5);
+        memory.set(3_000_000, 4);
+        memory.set(2_000_000, 0);
+        assert_eq!(memory.dense.len(), 1);
+        assert_eq!(memory.sparse.len(), 2);
+        assert_eq!(memory.get(1_000_000_000_000), 5);
+        assert_eq!(memory.get(1_000_000_000_001), 0);
+        assert_eq!(
+            memory.nonzero_from(0).collect::<Vec<_>>(),
+            vec![(0, 1), (3_000_000, 4), (1_000_000_000_000, 5)]
+        );
+    }
+}
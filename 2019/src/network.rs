@@ -0,0 +1,299 @@
+use super::Result;
+use crate::cpu::{Cpu, CpuState};
+use failure::bail;
+
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum Topology {
+    // outputs of cpu `i` feed cpu `i + 1`; the last cpu's outputs leave the network
+    Chain,
+    // like `Chain`, but the last cpu feeds the first
+    Ring,
+    // outputs are `(dest, x, y)` triples; cpus without input read -1, and
+    // packets to address 255 go to the nat
+    Packet,
+}
+
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum StopCondition {
+    AllHalted,
+    FirstNatPacket,
+    NatRepeatedY,
+}
+
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum Address {
+    Cpu(usize),
+    Nat,
+    External,
+}
+
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub struct Message {
+    pub round: usize,
+    pub from: Address,
+    pub to: Address,
+    pub values: Vec<i64>,
+}
+
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum StopReason {
+    AllHalted,
+    Deadlock,
+    NatPacket(i64, i64),
+    NatRepeatedY(i64),
+}
+
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub struct Trace {
+    pub messages: Vec<Message>,
+    pub rounds: usize,
+    pub stop: StopReason,
+}
+
+impl Trace {
+    pub fn last_from(&self, from: Address) -> Option<&Message> {
+        self.messages.iter().rev().find(|m| m.from == from)
+    }
+}
+
+pub const NAT_ADDRESS: i64 = 255;
+
+pub struct Network {
+    cpus: Vec<Cpu>,
+    topology: Topology,
+    halted: Vec<bool>,
+    pending: Vec<Vec<i64>>,
+    nat: Option<(i64, i64)>,
+    last_nat_y: Option<i64>,
+    messages: Vec<Message>,
+    round: usize,
+}
+
+impl Network {
+    pub fn new(cpus: Vec<Cpu>, topology: Topology) -> Self {
+        let size = cpus.len();
+        let mut network = Network {
+            cpus,
+            topology,
+            halted: vec![false; size],
+            pending: vec![vec![]; size],
+            nat: None,
+            last_nat_y: None,
+            messages: vec![],
+            round: 0,
+        };
+        if topology == Topology::Packet {
+            for (address, cpu) in network.cpus.iter_mut().enumerate() {
+                cpu.enqueue_input(address as i64);
+            }
+        }
+        network
+    }
+
+    pub fn from_program(program_str: &str, size: usize, topology: Topology) -> Self {
+        Self::new(
+            (0..size).map(|_| Cpu::from_str(program_str)).collect(),
+            topology,
+        )
+    }
+
+    pub fn enqueue_input(&mut self, index: usize, value: i64) {
+        self.cpus[index].enqueue_input(value);
+    }
+
+    fn send(&mut self, from: Address, to: Address, values: Vec<i64>) {
+        if let Address::Cpu(index) = to {
+            for &value in values.iter() {
+                self.cpus[index].enqueue_input(value);
+            }
+        }
+        self.messages.push(Message {
+            round: self.round,
+            from,
+            to,
+            values,
+        });
+    }
+
+    fn route(&mut self, index: usize, value: i64) -> Result<Option<StopReason>> {
+        let last = self.cpus.len() - 1;
+        match self.topology {
+            Topology::Chain if index == last => {
+                self.send(Address::Cpu(index), Address::External, vec![value])
+            }
+            Topology::Chain | Topology::Ring => self.send(
+                Address::Cpu(index),
+                Address::Cpu((index + 1) % self.cpus.len()),
+                vec![value],
+            ),
+            Topology::Packet => {
+                self.pending[index].push(value);
+                if self.pending[index].len() < 3 {
+                    return Ok(None);
+                }
+                let packet: Vec<_> = self.pending[index].drain(..).collect();
+                let (dest, x, y) = (packet[0], packet[1], packet[2]);
+                if dest == NAT_ADDRESS {
+                    self.send(Address::Cpu(index), Address::Nat, vec![x, y]);
+                    self.nat = Some((x, y));
+                    return Ok(Some(StopReason::NatPacket(x, y)));
+                }
+                if dest < 0 || dest as usize >= self.cpus.len() {
+                    bail!("cpu {} sent packet to invalid address {}", index, dest);
+                }
+                self.send(Address::Cpu(index), Address::Cpu(dest as usize), vec![x, y]);
+            }
+        }
+        Ok(None)
+    }
+
+    // runs every cpu once until it blocks on input or halts. returns whether
+    // the network made progress, and a stop reason if one was hit
+    fn step(&mut self, condition: StopCondition) -> Result<(bool, Option<StopReason>)> {
+        let mut progress = false;
+        for index in 0..self.cpus.len() {
+            if self.halted[index] {
+                continue;
+            }
+            if self.topology == Topology::Packet && self.cpus[index].input_len() == 0 {
+                self.cpus[index].enqueue_input(-1);
+            } else if self.cpus[index].input_len() > 0 {
+                progress = true;
+            }
+            loop {
+                match self.cpus[index].run()? {
+                    CpuState::Output(value) => {
+                        progress = true;
+                        let reason = self.route(index, value)?;
+                        if let (StopCondition::FirstNatPacket, Some(reason)) = (condition, reason) {
+                            return Ok((true, Some(reason)));
+                        }
+                    }
+                    CpuState::NeedsInput => break,
+                    CpuState::Halted => {
+                        progress = true;
+                        self.halted[index] = true;
+                        break;
+                    }
+                }
+            }
+        }
+        if self.halted.iter().all(|&h| h) {
+            return Ok((progress, Some(StopReason::AllHalted)));
+        }
+        Ok((progress, None))
+    }
+
+    fn wake_from_nat(&mut self) -> Option<StopReason> {
+        let (x, y) = self.nat?;
+        if self.last_nat_y == Some(y) {
+            return Some(StopReason::NatRepeatedY(y));
+        }
+        self.last_nat_y = Some(y);
+        self.send(Address::Nat, Address::Cpu(0), vec![x, y]);
+        None
+    }
+
+    pub fn run(mut self, condition: StopCondition) -> Result<Trace> {
+        let stop = loop {
+            self.round += 1;
+            let (progress, reason) = self.step(condition)?;
+            if let Some(reason) = reason {
+                break reason;
+            }
+            if progress {
+                continue;
+            }
+            if self.topology == Topology::Packet && condition == StopCondition::NatRepeatedY {
+                if let Some(reason) = self.wake_from_nat() {
+                    break reason;
+                }
+                if self.nat.is_some() {
+                    continue;
+                }
+            }
+            break StopReason::Deadlock;
+        };
+        Ok(Trace {
+            messages: self.messages,
+            rounds: self.round,
+            stop,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // reads its address, sends (255, address, 7), then reads input forever
+    const NAT_PROGRAM: &str = "3,100,104,255,4,100,104,7,3,101,1105,1,8,99";
+
+    #[test]
+    fn test_ring() -> Result<()> {
+        let program =
+            "3,26,1001,26,-4,26,3,27,1002,27,2,27,1,27,26,27,4,27,1001,28,-1,28,1005,28,6,99,0,0,5";
+        let mut network = Network::from_program(program, 5, Topology::Ring);
+        for (index, &phase) in [9, 8, 7, 6, 5].iter().enumerate() {
+            network.enqueue_input(index, phase);
+        }
+        network.enqueue_input(0, 0);
+        let trace = network.run(StopCondition::AllHalted)?;
+        assert_eq!(trace.stop, StopReason::AllHalted);
+        assert_eq!(
+            trace.last_from(Address::Cpu(4)).map(|m| m.values.clone()),
+            Some(vec![139629729])
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_chain() -> Result<()> {
+        // outputs input + 1
+        let mut network = Network::from_program("3,9,1001,9,1,9,4,9,99,0", 3, Topology::Chain);
+        network.enqueue_input(0, 10);
+        let trace = network.run(StopCondition::AllHalted)?;
+        assert_eq!(trace.stop, StopReason::AllHalted);
+        assert_eq!(
+            trace.messages.last(),
+            Some(&Message {
+                round: 1,
+                from: Address::Cpu(2),
+                to: Address::External,
+                values: vec![13],
+            })
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_deadlock() -> Result<()> {
+        let network = Network::from_program("3,9,1001,9,1,9,4,9,99,0", 2, Topology::Ring);
+        let trace = network.run(StopCondition::AllHalted)?;
+        assert_eq!(trace.stop, StopReason::Deadlock);
+        assert!(trace.messages.is_empty());
+        Ok(())
+    }
+
+    #[test]
+    fn test_first_nat_packet() -> Result<()> {
+        let network = Network::from_program(NAT_PROGRAM, 3, Topology::Packet);
+        let trace = network.run(StopCondition::FirstNatPacket)?;
+        assert_eq!(trace.stop, StopReason::NatPacket(0, 7));
+        Ok(())
+    }
+
+    #[test]
+    fn test_nat_repeated_y() -> Result<()> {
+        let network = Network::from_program(NAT_PROGRAM, 3, Topology::Packet);
+        let trace = network.run(StopCondition::NatRepeatedY)?;
+        assert_eq!(trace.stop, StopReason::NatRepeatedY(7));
+        assert_eq!(
+            trace
+                .last_from(Address::Nat)
+                .map(|m| (m.to, m.values.clone())),
+            Some((Address::Cpu(0), vec![2, 7]))
+        );
+        Ok(())
+    }
+}
@@ -1,19 +1,20 @@
-use anyhow::{anyhow, bail, Error, Result};
+use anyhow::{bail, Error, Result};
 use aoc2020::dispatch;
-use std::collections::HashSet;
-use std::convert::TryFrom;
+use aoc2020::repair::{repair, Repair, StaticFlow};
+use machine::{self, parse_program, Flow, Machine, Outcome};
+use std::str::FromStr;
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, PartialEq)]
 enum Instruction {
     Acc(i64),
     Jmp(i64),
     Nop(i64),
 }
 
-impl TryFrom<&str> for Instruction {
-    type Error = Error;
+impl FromStr for Instruction {
+    type Err = Error;
 
-    fn try_from(s: &str) -> Result<Self> {
+    fn from_str(s: &str) -> Result<Self> {
         let n: i64 = s[4..].parse()?;
         Ok(match &s[..3] {
             "acc" => Instruction::Acc(n),
@@ -24,6 +25,27 @@ impl TryFrom<&str> for Instruction {
     }
 }
 
+impl machine::Instruction for Instruction {
+    // the accumulator
+    type State = i64;
+
+    fn execute(&self, acc: &mut i64) -> Flow {
+        if let Instruction::Acc(n) = self {
+            *acc += n;
+        }
+        self.flow()
+    }
+}
+
+impl StaticFlow for Instruction {
+    fn flow(&self) -> Flow {
+        match self {
+            Instruction::Jmp(n) => Flow::Jump(*n),
+            Instruction::Acc(_) | Instruction::Nop(_) => Flow::Next,
+        }
+    }
+}
+
 fn main() -> Result<()> {
     dispatch(part1, part2)
 }
@@ -31,10 +53,7 @@ fn main() -> Result<()> {
 type Program = Vec<Instruction>;
 
 fn parse(input: &str) -> Result<Program> {
-    input
-        .split("\n")
-        .map(|l| Instruction::try_from(l))
-        .collect::<Result<_>>()
+    Ok(parse_program(input)?)
 }
 
 #[derive(Debug, PartialEq)]
@@ -44,28 +63,14 @@ enum RunResult {
 }
 
 fn run(program: &Program) -> Result<RunResult> {
-    let mut pc: i64 = 0;
-    let mut acc = 0;
-    let mut seen = HashSet::new();
-    while !seen.contains(&pc) {
-        if pc == program.len() as i64 {
-            return Ok(RunResult::Terminates(acc));
-        }
-        seen.insert(pc);
-        if pc < 0 {
-            bail!("segfault");
-        }
-        let instruction: &Instruction = program.get(pc as usize).ok_or(anyhow!("segfault"))?;
-        match instruction {
-            Instruction::Acc(n) => {
-                acc += n;
-                pc += 1
-            }
-            Instruction::Jmp(n) => pc += n,
-            Instruction::Nop(_) => pc += 1,
+    let mut machine = Machine::new(program.clone(), 0);
+    match machine.run_until_repeat() {
+        Outcome::Repeated(_) => Ok(RunResult::Loops(machine.state)),
+        Outcome::Halted if machine.pc == program.len() as i64 => {
+            Ok(RunResult::Terminates(machine.state))
         }
+        Outcome::Halted => bail!("segfault"),
     }
-    Ok(RunResult::Loops(acc))
 }
 
 fn part1(input: &str) -> Result<i64> {
@@ -77,24 +82,27 @@ fn part1(input: &str) -> Result<i64> {
     }
 }
 
-fn swap(instruction: &Instruction) -> Instruction {
+fn swap(instruction: &Instruction) -> Vec<Instruction> {
     match instruction {
-        Instruction::Nop(n) => Instruction::Jmp(*n),
-        Instruction::Jmp(n) => Instruction::Nop(*n),
-        Instruction::Acc(n) => Instruction::Acc(*n),
+        Instruction::Nop(n) => vec![Instruction::Jmp(*n)],
+        Instruction::Jmp(n) => vec![Instruction::Nop(*n)],
+        Instruction::Acc(_) => vec![],
+    }
+}
+
+fn fix(program: &Program) -> Result<Repair<Instruction>> {
+    match repair(program, swap) {
+        Some(repair) => Ok(repair),
+        None => bail!("no terminating solution found"),
     }
 }
 
 fn part2(input: &str) -> Result<i64> {
-    let mut program = parse(input)?;
-    for index in 0..program.len() {
-        program[index] = swap(&program[index]);
-        if let RunResult::Terminates(val) = run(&program)? {
-            return Ok(val);
-        }
-        program[index] = swap(&program[index]);
+    let program = parse(input)?;
+    match run(&fix(&program)?.program)? {
+        RunResult::Terminates(val) => Ok(val),
+        RunResult::Loops(_) => bail!("repaired program still loops"),
     }
-    bail!("no terminating solution found");
 }
 
 #[cfg(test)]
@@ -141,6 +149,25 @@ acc +6",
         Ok(())
     }
 
+    #[test]
+    fn test_fix() -> Result<()> {
+        let repair = fix(&parse(INPUT)?)?;
+        assert_eq!(repair.index, 7);
+        assert_eq!(repair.original, Instruction::Jmp(-4));
+        assert_eq!(repair.replacement, Instruction::Nop(-4));
+        Ok(())
+    }
+
+    #[test]
+    fn test_fix_edges() -> Result<()> {
+        let fixed = fix(&parse("jmp +0\nacc +1")?)?;
+        assert_eq!(fixed.index, 0);
+        assert_eq!(run(&fixed.program)?, RunResult::Terminates(1));
+        assert!(repair(&parse("acc +1")?, swap).is_none());
+        assert!(repair(&parse("jmp +0\njmp -1")?, swap).is_none());
+        Ok(())
+    }
+
     #[test]
     fn test_part2() -> Result<()> {
         assert_eq!(part2(INPUT)?, 8);
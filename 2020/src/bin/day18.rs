@@ -1,63 +1,37 @@
-use anyhow::Result;
+use anyhow::{Context, Result};
 use aoc2020::dispatch;
-use pest::iterators::{Pair, Pairs};
-use pest::prec_climber::{Assoc, Operator, PrecClimber};
-use pest::Parser;
-use pest_derive::*;
+use aoc2020::expression::{parse, Assoc, BinOp, Precedence};
 
 fn main() -> Result<()> {
     dispatch(part1, part2)
 }
 
-#[derive(Parser)]
-#[grammar = "bin/day18.pest"] // relative to src
-struct Calc;
-
-fn eval(expression: Pairs<Rule>, climber: &PrecClimber<Rule>) -> i64 {
-    climber.climb(
-        expression,
-        |pair: Pair<Rule>| match pair.as_rule() {
-            Rule::num => pair.as_str().parse::<i64>().unwrap(),
-            Rule::expr => eval(pair.into_inner(), climber),
-            _ => unreachable!(),
-        },
-        |lhs: i64, op: Pair<Rule>, rhs: i64| match op.as_rule() {
-            Rule::add => lhs + rhs,
-            Rule::multiply => lhs * rhs,
-            _ => unreachable!(),
-        },
-    )
+fn sum(input: &str, precedence: &Precedence) -> Result<i64> {
+    input
+        .lines()
+        .enumerate()
+        .map(|(index, line)| {
+            parse(line, precedence)
+                .and_then(|expr| expr.eval::<i64>())
+                .with_context(|| format!("line {}", index + 1))
+        })
+        .sum()
 }
 
 fn part1(input: &str) -> Result<i64> {
-    use Assoc::*;
-    use Rule::*;
-    let climber = PrecClimber::new(vec![
-        Operator::new(add, Left) | Operator::new(multiply, Left),
-    ]);
-
-    let mut sum = 0;
-    for line in input.split('\n') {
-        let ast = Calc::parse(Rule::calculation, line)?;
-        sum += eval(ast, &climber);
-    }
-    Ok(sum)
+    let precedence =
+        Precedence::new()
+            .op(BinOp::Add, 1, Assoc::Left)
+            .op(BinOp::Mul, 1, Assoc::Left);
+    sum(input, &precedence)
 }
 
 fn part2(input: &str) -> Result<i64> {
-    use Assoc::*;
-    use Rule::*;
-    let climber = PrecClimber::new(vec![
-        Operator::new(multiply, Left),
-        Operator::new(add, Left),
-    ]);
-
-    let mut sum = 0;
-    for line in input.split('\n') {
-        let ast = Calc::parse(Rule::calculation, line)?;
-        sum += eval(ast, &climber);
-    }
-    Ok(sum)
+    let precedence =
+        Precedence::new()
+            .op(BinOp::Mul, 1, Assoc::Left)
+            .op(BinOp::Add, 2, Assoc::Left);
+    sum(input, &precedence)
 }
 
 #[cfg(test)]
@@ -74,6 +48,10 @@ mod tests {
     #[test]
     fn test_part2() -> Result<()> {
         assert_eq!(part2("1 + 2 * 3 + 4 * 5 + 6")?, 231);
+        assert_eq!(
+            part2("((2 + 4 * 9) * (6 + 9 * 8 + 6) + 6) + 2 + 4 * 2")?,
+            23340
+        );
         Ok(())
     }
 }
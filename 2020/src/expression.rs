@@ -0,0 +1,577 @@
+// arithmetic expressions with a configurable operator table, parsed with a
+// pratt parser into an ast that can be evaluated or printed back out
+use anyhow::{bail, Context, Result};
+use std::collections::HashMap;
+use std::convert::TryFrom;
+use std::fmt;
+use std::iter::Peekable;
+use std::str::CharIndices;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum BinOp {
+    Add,
+    Sub,
+    Mul,
+    Div,
+}
+
+impl BinOp {
+    fn from_char(c: char) -> Option<Self> {
+        Some(match c {
+            '+' => BinOp::Add,
+            '-' => BinOp::Sub,
+            '*' => BinOp::Mul,
+            '/' => BinOp::Div,
+            _ => return None,
+        })
+    }
+
+    fn symbol(self) -> char {
+        match self {
+            BinOp::Add => '+',
+            BinOp::Sub => '-',
+            BinOp::Mul => '*',
+            BinOp::Div => '/',
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Assoc {
+    Left,
+    Right,
+}
+
+// binding power and associativity for each supported binary operator. higher
+// precedence binds tighter; unary minus binds tighter than all of them.
+// stored wider than callers give it, so the parser can shift it up by one
+#[derive(Debug, Clone, Default)]
+pub struct Precedence {
+    ops: HashMap<BinOp, (u16, Assoc)>,
+}
+
+impl Precedence {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn op(mut self, op: BinOp, precedence: u8, assoc: Assoc) -> Self {
+        self.ops.insert(op, (precedence.into(), assoc));
+        self
+    }
+
+    // the usual school rules
+    pub fn standard() -> Self {
+        Precedence::new()
+            .op(BinOp::Add, 1, Assoc::Left)
+            .op(BinOp::Sub, 1, Assoc::Left)
+            .op(BinOp::Mul, 2, Assoc::Left)
+            .op(BinOp::Div, 2, Assoc::Left)
+    }
+
+    fn get(&self, op: BinOp) -> Option<(u16, Assoc)> {
+        self.ops.get(&op).copied()
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Expr {
+    Num(i64),
+    Neg(Box<Expr>),
+    Bin(BinOp, Box<Expr>, Box<Expr>),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Token {
+    Num(i64),
+    Op(char),
+    Open,
+    Close,
+    End,
+}
+
+impl fmt::Display for Token {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Token::Num(n) => write!(f, "`{}`", n),
+            Token::Op(c) => write!(f, "`{}`", c),
+            Token::Open => write!(f, "`(`"),
+            Token::Close => write!(f, "`)`"),
+            Token::End => write!(f, "end of input"),
+        }
+    }
+}
+
+struct Lexer<'a> {
+    input: &'a str,
+    chars: Peekable<CharIndices<'a>>,
+}
+
+impl<'a> Lexer<'a> {
+    fn new(input: &'a str) -> Self {
+        Lexer {
+            input,
+            chars: input.char_indices().peekable(),
+        }
+    }
+
+    // the next token and its (1 based) column
+    fn next(&mut self) -> Result<(Token, usize)> {
+        while let Some((_, ' ')) | Some((_, '\t')) = self.chars.peek() {
+            self.chars.next();
+        }
+        let (start, c) = match self.chars.next() {
+            Some(next) => next,
+            None => return Ok((Token::End, self.input.len() + 1)),
+        };
+        let token = match c {
+            '(' => Token::Open,
+            ')' => Token::Close,
+            '+' | '-' | '*' | '/' => Token::Op(c),
+            '0'..='9' => {
+                let mut end = start + 1;
+                while let Some(&(index, '0'..='9')) = self.chars.peek() {
+                    end = index + 1;
+                    self.chars.next();
+                }
+                Token::Num(
+                    self.input[start..end]
+                        .parse()
+                        .with_context(|| format!("column {}: number too large", start + 1))?,
+                )
+            }
+            _ => bail!("column {}: unexpected character `{}`", start + 1, c),
+        };
+        Ok((token, start + 1))
+    }
+}
+
+struct Parser<'a> {
+    lexer: Lexer<'a>,
+    peeked: (Token, usize),
+    precedence: &'a Precedence,
+}
+
+impl<'a> Parser<'a> {
+    fn advance(&mut self) -> Result<(Token, usize)> {
+        let next = self.lexer.next()?;
+        Ok(std::mem::replace(&mut self.peeked, next))
+    }
+
+    fn prefix(&mut self) -> Result<Expr> {
+        match self.advance()? {
+            (Token::Num(n), _) => Ok(Expr::Num(n)),
+            (Token::Op('-'), _) => Ok(Expr::Neg(Box::new(self.prefix()?))),
+            (Token::Open, column) => {
+                let expr = self.expression(0)?;
+                match self.advance()? {
+                    (Token::Close, _) => Ok(expr),
+                    (token, at) => {
+                        bail!(
+                            "column {}: expected `)` to close column {}, found {}",
+                            at,
+                            column,
+                            token
+                        )
+                    }
+                }
+            }
+            (token, column) => bail!(
+                "column {}: expected a number or `(`, found {}",
+                column,
+                token
+            ),
+        }
+    }
+
+    // parses operators binding tighter than `min`
+    fn expression(&mut self, min: u16) -> Result<Expr> {
+        let mut lhs = self.prefix()?;
+        loop {
+            let (op, column) = match self.peeked {
+                (Token::Op(c), column) => (BinOp::from_char(c).unwrap(), column),
+                _ => return Ok(lhs),
+            };
+            let (precedence, assoc) = self.precedence.get(op).with_context(|| {
+                format!(
+                    "column {}: operator `{}` not supported",
+                    column,
+                    op.symbol()
+                )
+            })?;
+            if precedence <= min {
+                return Ok(lhs);
+            }
+            self.advance()?;
+            let rhs = match assoc {
+                Assoc::Left => self.expression(precedence)?,
+                Assoc::Right => self.expression(precedence - 1)?,
+            };
+            lhs = Expr::Bin(op, Box::new(lhs), Box::new(rhs));
+        }
+    }
+}
+
+// precedences are offset by one internally so that 0 can mean "anything"
+pub fn parse(input: &str, precedence: &Precedence) -> Result<Expr> {
+    let shifted = Precedence {
+        ops: precedence
+            .ops
+            .iter()
+            .map(|(&op, &(p, assoc))| (op, (p + 1, assoc)))
+            .collect(),
+    };
+    let mut lexer = Lexer::new(input);
+    let peeked = lexer.next()?;
+    let mut parser = Parser {
+        lexer,
+        peeked,
+        precedence: &shifted,
+    };
+    let expr = parser.expression(0)?;
+    match parser.peeked {
+        (Token::End, _) => Ok(expr),
+        (token, column) => bail!("column {}: unexpected {}", column, token),
+    }
+}
+
+// the number types an expression can be evaluated over, with arithmetic that
+// reports overflow instead of panicking
+pub trait Number: Sized + Copy + From<i64> {
+    fn plus(self, other: Self) -> Result<Self>;
+    fn minus(self, other: Self) -> Result<Self>;
+    fn times(self, other: Self) -> Result<Self>;
+    fn negate(self) -> Result<Self>;
+    fn divide(self, other: Self) -> Result<Self>;
+}
+
+impl Number for i64 {
+    fn plus(self, other: Self) -> Result<Self> {
+        self.checked_add(other).context("overflow")
+    }
+
+    fn minus(self, other: Self) -> Result<Self> {
+        self.checked_sub(other).context("overflow")
+    }
+
+    fn times(self, other: Self) -> Result<Self> {
+        self.checked_mul(other).context("overflow")
+    }
+
+    fn negate(self) -> Result<Self> {
+        self.checked_neg().context("overflow")
+    }
+
+    // truncating, like rust
+    fn divide(self, other: Self) -> Result<Self> {
+        if other == 0 {
+            bail!("division by zero");
+        }
+        self.checked_div(other).context("overflow")
+    }
+}
+
+fn gcd(a: i128, b: i128) -> i128 {
+    if b == 0 {
+        a.abs()
+    } else {
+        gcd(b, a % b)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Rational {
+    // in lowest terms, with a positive denominator
+    pub numerator: i64,
+    pub denominator: i64,
+}
+
+impl Rational {
+    pub fn new(numerator: i64, denominator: i64) -> Result<Self> {
+        Rational::reduced(numerator.into(), denominator.into())
+    }
+
+    // products of two i64s always fit in an i128, so the arithmetic is done
+    // wide and only has to fit back once reduced
+    fn reduced(numerator: i128, denominator: i128) -> Result<Self> {
+        if denominator == 0 {
+            bail!("division by zero");
+        }
+        let divisor = gcd(numerator, denominator) * denominator.signum();
+        let narrow = |n: i128| i64::try_from(n / divisor).context("overflow");
+        Ok(Rational {
+            numerator: narrow(numerator)?,
+            denominator: narrow(denominator)?,
+        })
+    }
+
+    fn wide(self) -> (i128, i128) {
+        (self.numerator.into(), self.denominator.into())
+    }
+}
+
+impl From<i64> for Rational {
+    fn from(n: i64) -> Self {
+        Rational {
+            numerator: n,
+            denominator: 1,
+        }
+    }
+}
+
+impl fmt::Display for Rational {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.denominator == 1 {
+            write!(f, "{}", self.numerator)
+        } else {
+            write!(f, "{}/{}", self.numerator, self.denominator)
+        }
+    }
+}
+
+impl Number for Rational {
+    fn plus(self, other: Self) -> Result<Self> {
+        let ((a, b), (c, d)) = (self.wide(), other.wide());
+        Rational::reduced(a * d + c * b, b * d)
+    }
+
+    fn minus(self, other: Self) -> Result<Self> {
+        let ((a, b), (c, d)) = (self.wide(), other.wide());
+        Rational::reduced(a * d - c * b, b * d)
+    }
+
+    fn times(self, other: Self) -> Result<Self> {
+        let ((a, b), (c, d)) = (self.wide(), other.wide());
+        Rational::reduced(a * c, b * d)
+    }
+
+    fn negate(self) -> Result<Self> {
+        let (a, b) = self.wide();
+        Rational::reduced(-a, b)
+    }
+
+    fn divide(self, other: Self) -> Result<Self> {
+        let ((a, b), (c, d)) = (self.wide(), other.wide());
+        Rational::reduced(a * d, b * c)
+    }
+}
+
+impl Expr {
+    pub fn eval<N: Number>(&self) -> Result<N> {
+        Ok(match self {
+            Expr::Num(n) => N::from(*n),
+            Expr::Neg(expr) => expr.eval::<N>()?.negate()?,
+            Expr::Bin(op, lhs, rhs) => {
+                let (lhs, rhs) = (lhs.eval::<N>()?, rhs.eval::<N>()?);
+                match op {
+                    BinOp::Add => lhs.plus(rhs)?,
+                    BinOp::Sub => lhs.minus(rhs)?,
+                    BinOp::Mul => lhs.times(rhs)?,
+                    BinOp::Div => lhs.divide(rhs)?,
+                }
+            }
+        })
+    }
+
+    // prints the expression with only the parentheses needed to parse back
+    // to the same tree under `precedence`
+    pub fn display<'a>(&'a self, precedence: &'a Precedence) -> Display<'a> {
+        Display {
+            expr: self,
+            precedence,
+        }
+    }
+}
+
+pub struct Display<'a> {
+    expr: &'a Expr,
+    precedence: &'a Precedence,
+}
+
+impl Display<'_> {
+    fn child<'a>(&'a self, expr: &'a Expr) -> Display<'a> {
+        Display {
+            expr,
+            precedence: self.precedence,
+        }
+    }
+
+    fn precedence(&self, expr: &Expr) -> Option<(u16, Assoc)> {
+        match expr {
+            Expr::Bin(op, ..) => self.precedence.get(*op),
+            _ => None,
+        }
+    }
+
+    // whether `child` needs parentheses as the left (or right) operand of a
+    // binary operator with the given precedence
+    fn needs_parens(&self, child: &Expr, parent: (u16, Assoc), left: bool) -> bool {
+        match self.precedence(child) {
+            None => false,
+            Some((precedence, _)) if precedence != parent.0 => precedence < parent.0,
+            // at the same precedence, a left operand keeps its operator only
+            // if that stops at the parent's, and a right operand only if the
+            // parent's reaches past its own
+            Some((_, assoc)) if left => assoc == Assoc::Right,
+            Some(_) => parent.1 == Assoc::Left,
+        }
+    }
+
+    fn operand(&self, f: &mut fmt::Formatter<'_>, expr: &Expr, parens: bool) -> fmt::Result {
+        if parens {
+            write!(f, "({})", self.child(expr))
+        } else {
+            write!(f, "{}", self.child(expr))
+        }
+    }
+}
+
+impl fmt::Display for Display<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.expr {
+            Expr::Num(n) => write!(f, "{}", n),
+            Expr::Neg(expr) => {
+                write!(f, "-")?;
+                let parens =
+                    matches!(**expr, Expr::Bin(..)) || matches!(**expr, Expr::Num(n) if n < 0);
+                self.operand(f, expr, parens)
+            }
+            Expr::Bin(op, lhs, rhs) => {
+                // operators missing from the table are treated as binding
+                // loosest and get parenthesized everywhere
+                let parent = self.precedence.get(*op).unwrap_or((0, Assoc::Left));
+                let parens = |child: &Expr, left| match self.precedence(child) {
+                    None if matches!(child, Expr::Bin(..)) => true,
+                    _ => self.needs_parens(child, parent, left),
+                };
+                self.operand(f, lhs, parens(lhs, true))?;
+                write!(f, " {} ", op.symbol())?;
+                self.operand(f, rhs, parens(rhs, false))
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn flat() -> Precedence {
+        Precedence::new()
+            .op(BinOp::Add, 1, Assoc::Left)
+            .op(BinOp::Mul, 1, Assoc::Left)
+    }
+
+    fn eval(input: &str, precedence: &Precedence) -> Result<i64> {
+        parse(input, precedence)?.eval()
+    }
+
+    #[test]
+    fn test_precedence() -> Result<()> {
+        assert_eq!(eval("1 + 2 * 3 + 4 * 5 + 6", &flat())?, 71);
+        assert_eq!(eval("1 + 2 * 3 + 4 * 5 + 6", &Precedence::standard())?, 33);
+        assert_eq!(eval("10 - 4 - 3", &Precedence::standard())?, 3);
+        let right = Precedence::new().op(BinOp::Sub, 1, Assoc::Right);
+        assert_eq!(eval("10 - 4 - 3", &right)?, 9);
+        assert_eq!(eval("-(2 + 3) * --4", &Precedence::standard())?, -20);
+        assert_eq!(eval("2 * -3", &Precedence::standard())?, -6);
+        // the whole range of precedences
+        let extremes = Precedence::new()
+            .op(BinOp::Add, u8::MAX, Assoc::Left)
+            .op(BinOp::Sub, 0, Assoc::Right)
+            .op(BinOp::Mul, 0, Assoc::Left);
+        assert_eq!(eval("2 * 3 + 4", &extremes)?, 14);
+        assert_eq!(eval("10 - 4 - 3 + 1", &extremes)?, 10);
+        Ok(())
+    }
+
+    #[test]
+    fn test_rational() -> Result<()> {
+        let expr = parse("1 / 3 + 1 / 6 - 2", &Precedence::standard())?;
+        assert_eq!(expr.eval::<Rational>()?, Rational::new(-3, 2)?);
+        assert_eq!(expr.eval::<Rational>()?.to_string(), "-3/2");
+        assert_eq!(expr.eval::<i64>()?, -2);
+        assert!(parse("1 / (2 - 2)", &Precedence::standard())?
+            .eval::<Rational>()
+            .is_err());
+        assert!(Rational::new(1, 0).is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_overflow() -> Result<()> {
+        let standard = Precedence::standard();
+        let err = |input: &str| -> Result<String> {
+            Ok(parse(input, &standard)?
+                .eval::<i64>()
+                .unwrap_err()
+                .to_string())
+        };
+        assert_eq!(err("9223372036854775807 + 1")?, "overflow");
+        assert_eq!(err("-9223372036854775807 - 2")?, "overflow");
+        assert_eq!(err("4294967296 * 4294967296")?, "overflow");
+        assert_eq!(err("(-9223372036854775807 - 1) / -1")?, "overflow");
+        assert_eq!(err("-(-9223372036854775807 - 1)")?, "overflow");
+        assert_eq!(err("1 / 0")?, "division by zero");
+        let expr = parse("4294967296 * 4294967296 / 4294967296", &standard)?;
+        assert!(expr.eval::<Rational>().is_err());
+        let expr = parse("1 / 4294967296 * 4294967296", &standard)?;
+        assert_eq!(expr.eval::<Rational>()?, Rational::from(1));
+        Ok(())
+    }
+
+    #[test]
+    fn test_errors() {
+        let err = |input: &str| parse(input, &flat()).unwrap_err().to_string();
+        assert_eq!(
+            err("1 + (2 * 3"),
+            "column 11: expected `)` to close column 5, found end of input"
+        );
+        assert_eq!(err("1 - 2"), "column 3: operator `-` not supported");
+        assert_eq!(
+            err("1 + * 2"),
+            "column 5: expected a number or `(`, found `*`"
+        );
+        assert_eq!(err("1 2"), "column 3: unexpected `2`");
+        assert_eq!(err("1 % 2"), "column 3: unexpected character `%`");
+    }
+
+    #[test]
+    fn test_display() -> Result<()> {
+        let standard = Precedence::standard();
+        for (input, expected) in &[
+            ("((1 + 2)) * 3", "(1 + 2) * 3"),
+            ("(1 * 2) + 3", "1 * 2 + 3"),
+            ("(1 - 2) - 3", "1 - 2 - 3"),
+            ("1 - (2 - 3)", "1 - (2 - 3)"),
+            ("-(1 + 2) + -3", "-(1 + 2) + -3"),
+        ] {
+            let expr = parse(input, &standard)?;
+            let printed = expr.display(&standard).to_string();
+            assert_eq!(printed, *expected);
+            assert_eq!(parse(&printed, &standard)?, expr);
+        }
+        // same precedence, different associativity: `a - b * c` would group
+        // as `a - (b * c)`
+        let mixed =
+            Precedence::new()
+                .op(BinOp::Sub, 1, Assoc::Right)
+                .op(BinOp::Mul, 1, Assoc::Left);
+        for (input, expected) in &[
+            ("(1 - 2) * 3", "(1 - 2) * 3"),
+            ("1 - 2 * 3", "1 - 2 * 3"),
+            ("(1 * 2) - 3", "1 * 2 - 3"),
+            ("1 * (2 - 3)", "1 * (2 - 3)"),
+        ] {
+            let expr = parse(input, &mixed)?;
+            let printed = expr.display(&mixed).to_string();
+            assert_eq!(printed, *expected);
+            assert_eq!(parse(&printed, &mixed)?, expr);
+        }
+        // the same tree needs different parentheses under other rules
+        let expr = parse("1 + 2 * 3", &flat())?;
+        assert_eq!(expr.display(&flat()).to_string(), "1 + 2 * 3");
+        assert_eq!(expr.display(&standard).to_string(), "(1 + 2) * 3");
+        Ok(())
+    }
+}
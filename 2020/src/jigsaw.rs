@@ -0,0 +1,488 @@
+// square tiles that have to be fitted together into a square picture, each
+// tile turned or flipped so its edges line up with its neighbours'. along
+// with the 8 ways to orient a bitmap (the symmetries of a square), and
+// finding a pattern in a bitmap whichever way round it is
+use crate::coor::Coor;
+use anyhow::{bail, Result};
+use std::collections::{HashMap, HashSet};
+use std::fmt;
+
+// mirrored left to right first, then turned clockwise a quarter at a time
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct Orientation {
+    pub flipped: bool,
+    pub rotations: u8,
+}
+
+impl Orientation {
+    pub fn all() -> impl Iterator<Item = Orientation> {
+        [false, true]
+            .iter()
+            .flat_map(|&flipped| (0..4).map(move |rotations| Orientation { flipped, rotations }))
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Side {
+    Top,
+    Right,
+    Bottom,
+    Left,
+}
+
+#[derive(Clone, PartialEq, Eq, Hash)]
+pub struct Bitmap {
+    width: usize,
+    height: usize,
+    data: Vec<bool>,
+}
+
+impl Bitmap {
+    // `data` is row by row, and callers make sure there is exactly enough
+    fn new(width: usize, height: usize, data: Vec<bool>) -> Self {
+        assert_eq!(data.len(), width * height, "bitmap size mismatch");
+        Bitmap {
+            width,
+            height,
+            data,
+        }
+    }
+
+    // `#` is set, and `.` or ` ` is not
+    pub fn parse(input: &str) -> Result<Self> {
+        let rows: Vec<&str> = input.lines().filter(|l| !l.is_empty()).collect();
+        let width = rows.first().map_or(0, |r| r.len());
+        let mut data = vec![];
+        for (y, row) in rows.iter().enumerate() {
+            if row.len() != width {
+                bail!("row {} is {} wide, expected {}", y, row.len(), width);
+            }
+            for c in row.chars() {
+                data.push(match c {
+                    '#' => true,
+                    '.' | ' ' => false,
+                    _ => bail!("invalid pixel `{}` in row {}", c, y),
+                });
+            }
+        }
+        Ok(Bitmap::new(width, rows.len(), data))
+    }
+
+    pub fn width(&self) -> usize {
+        self.width
+    }
+
+    pub fn height(&self) -> usize {
+        self.height
+    }
+
+    pub fn get(&self, x: usize, y: usize) -> bool {
+        self.data[y * self.width + x]
+    }
+
+    pub fn count(&self) -> usize {
+        self.data.iter().filter(|&&b| b).count()
+    }
+
+    pub fn transform(&self, orientation: Orientation) -> Bitmap {
+        let mut image = self.clone();
+        if orientation.flipped {
+            image = Bitmap::from_fn(self.width, self.height, |x, y| {
+                self.get(self.width - 1 - x, y)
+            });
+        }
+        for _ in 0..orientation.rotations % 4 {
+            let prev = image;
+            image = Bitmap::from_fn(prev.height, prev.width, |x, y| {
+                prev.get(y, prev.height - 1 - x)
+            });
+        }
+        image
+    }
+
+    pub fn from_fn(width: usize, height: usize, f: impl Fn(usize, usize) -> bool) -> Bitmap {
+        let data = (0..height)
+            .flat_map(|y| (0..width).map(move |x| (x, y)))
+            .map(|(x, y)| f(x, y))
+            .collect();
+        Bitmap::new(width, height, data)
+    }
+
+    // the pixels along one side, left to right or top to bottom
+    pub fn edge(&self, side: Side) -> Vec<bool> {
+        let (w, h) = (self.width, self.height);
+        match side {
+            Side::Top => (0..w).map(|x| self.get(x, 0)).collect(),
+            Side::Bottom => (0..w).map(|x| self.get(x, h - 1)).collect(),
+            Side::Left => (0..h).map(|y| self.get(0, y)).collect(),
+            Side::Right => (0..h).map(|y| self.get(w - 1, y)).collect(),
+        }
+    }
+
+    // without its outermost rows and columns
+    pub fn inner(&self) -> Bitmap {
+        Bitmap::from_fn(
+            self.width.saturating_sub(2),
+            self.height.saturating_sub(2),
+            |x, y| self.get(x + 1, y + 1),
+        )
+    }
+
+    // top left corners of where `mask`'s set pixels are all set here
+    pub fn find(&self, mask: &Bitmap) -> Vec<Coor> {
+        if mask.width > self.width || mask.height > self.height {
+            return vec![];
+        }
+        let set: Vec<(usize, usize)> = (0..mask.height)
+            .flat_map(|y| (0..mask.width).map(move |x| (x, y)))
+            .filter(|&(x, y)| mask.get(x, y))
+            .collect();
+        let mut found = vec![];
+        for y in 0..=self.height - mask.height {
+            for x in 0..=self.width - mask.width {
+                if set.iter().all(|&(dx, dy)| self.get(x + dx, y + dy)) {
+                    found.push(Coor::new(x as i64, y as i64));
+                }
+            }
+        }
+        found
+    }
+
+    // the first orientation of `mask` that's found here at all, with the
+    // pixels its matches cover
+    pub fn find_any_orientation(&self, mask: &Bitmap) -> Option<(Orientation, HashSet<Coor>)> {
+        Orientation::all().find_map(|orientation| {
+            let mask = mask.transform(orientation);
+            let found = self.find(&mask);
+            if found.is_empty() {
+                return None;
+            }
+            let covered = found
+                .iter()
+                .flat_map(|&at| {
+                    (0..mask.height)
+                        .flat_map(|y| (0..mask.width).map(move |x| (x, y)))
+                        .filter(|&(x, y)| mask.get(x, y))
+                        .map(move |(x, y)| at + Coor::new(x as i64, y as i64))
+                })
+                .collect();
+            Some((orientation, covered))
+        })
+    }
+}
+
+impl fmt::Display for Bitmap {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for row in self.data.chunks(self.width.max(1)) {
+            let row: String = row.iter().map(|&b| if b { '#' } else { '.' }).collect();
+            writeln!(f, "{}", row)?;
+        }
+        Ok(())
+    }
+}
+
+impl fmt::Debug for Bitmap {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "\n{}", self)
+    }
+}
+
+// an edge as a number, reading its pixels as bits
+pub fn signature(edge: &[bool]) -> u64 {
+    edge.iter().fold(0, |acc, &b| (acc << 1) | b as u64)
+}
+
+// the same for an edge and its reverse, as neighbouring tiles can be flipped
+pub fn canonical_signature(edge: &[bool]) -> u64 {
+    let reversed: Vec<bool> = edge.iter().rev().copied().collect();
+    signature(edge).min(signature(&reversed))
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Placement {
+    // index into the tiles
+    pub tile: usize,
+    pub orientation: Orientation,
+}
+
+// the tiles laid out in a square, in reading order
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Assembly {
+    pub side: usize,
+    pub grid: Vec<Placement>,
+}
+
+struct Assembler<'a> {
+    side: usize,
+    // edge signatures for each tile in each orientation, by `Side`
+    edges: Vec<Vec<(Orientation, [u64; 4])>>,
+    // tiles with an edge matching this canonical signature
+    index: HashMap<u64, Vec<usize>>,
+    tiles: &'a [Bitmap],
+}
+
+impl Assembler<'_> {
+    fn fits(&self, grid: &[Placement], edges: &[u64; 4]) -> bool {
+        let pos = grid.len();
+        let edges_of = |p: &Placement| {
+            self.edges[p.tile]
+                .iter()
+                .find(|(o, _)| *o == p.orientation)
+                .expect("all orientations are indexed")
+                .1
+        };
+        let left_ok = pos.is_multiple_of(self.side)
+            || edges_of(&grid[pos - 1])[Side::Right as usize] == edges[Side::Left as usize];
+        let top_ok = pos < self.side
+            || edges_of(&grid[pos - self.side])[Side::Bottom as usize] == edges[Side::Top as usize];
+        left_ok && top_ok
+    }
+
+    fn candidates(&self, grid: &[Placement]) -> Vec<usize> {
+        let pos = grid.len();
+        let neighbour = if !pos.is_multiple_of(self.side) {
+            Some((&grid[pos - 1], Side::Right))
+        } else if pos >= self.side {
+            Some((&grid[pos - self.side], Side::Bottom))
+        } else {
+            None
+        };
+        match neighbour {
+            None => (0..self.tiles.len()).collect(),
+            Some((placement, side)) => {
+                let edge = self.tiles[placement.tile]
+                    .transform(placement.orientation)
+                    .edge(side);
+                self.index
+                    .get(&canonical_signature(&edge))
+                    .cloned()
+                    .unwrap_or_default()
+            }
+        }
+    }
+
+    fn search(&self, grid: &mut Vec<Placement>, used: &mut [bool]) -> bool {
+        if grid.len() == self.tiles.len() {
+            return true;
+        }
+        for tile in self.candidates(grid) {
+            if used[tile] {
+                continue;
+            }
+            for (orientation, edges) in &self.edges[tile] {
+                if !self.fits(grid, edges) {
+                    continue;
+                }
+                used[tile] = true;
+                grid.push(Placement {
+                    tile,
+                    orientation: *orientation,
+                });
+                if self.search(grid, used) {
+                    return true;
+                }
+                grid.pop();
+                used[tile] = false;
+            }
+        }
+        false
+    }
+}
+
+// fits square tiles of the same size into a square, backtracking whenever a
+// tile doesn't fit any way round
+pub fn assemble(tiles: &[Bitmap]) -> Result<Assembly> {
+    let side = (1..=tiles.len())
+        .find(|s| s * s >= tiles.len())
+        .unwrap_or(0);
+    if side == 0 || side * side != tiles.len() {
+        bail!("{} tiles can't make a square", tiles.len());
+    }
+    let size = tiles[0].width;
+    if tiles.iter().any(|t| t.width != size || t.height != size) {
+        bail!("tiles must all be {}x{}", size, size);
+    }
+    if size > 64 {
+        bail!("tiles can be at most 64 wide, not {}", size);
+    }
+
+    let sides = [Side::Top, Side::Right, Side::Bottom, Side::Left];
+    let mut index: HashMap<u64, Vec<usize>> = HashMap::new();
+    let mut edges = vec![];
+    for (i, tile) in tiles.iter().enumerate() {
+        for side in &sides {
+            let entry = index
+                .entry(canonical_signature(&tile.edge(*side)))
+                .or_default();
+            if !entry.contains(&i) {
+                entry.push(i);
+            }
+        }
+        edges.push(
+            Orientation::all()
+                .map(|orientation| {
+                    let image = tile.transform(orientation);
+                    let mut signatures = [0; 4];
+                    for side in &sides {
+                        signatures[*side as usize] = signature(&image.edge(*side));
+                    }
+                    (orientation, signatures)
+                })
+                .collect(),
+        );
+    }
+
+    let assembler = Assembler {
+        side,
+        edges,
+        index,
+        tiles,
+    };
+    let mut grid = vec![];
+    if !assembler.search(&mut grid, &mut vec![false; tiles.len()]) {
+        bail!("the tiles don't fit together");
+    }
+    Ok(Assembly { side, grid })
+}
+
+impl Assembly {
+    pub fn corners(&self) -> [Placement; 4] {
+        let last = self.grid.len() - 1;
+        [
+            self.grid[0],
+            self.grid[self.side - 1],
+            self.grid[last + 1 - self.side],
+            self.grid[last],
+        ]
+    }
+
+    // the whole picture, with each tile's border taken off
+    pub fn image(&self, tiles: &[Bitmap]) -> Bitmap {
+        let inner: Vec<Bitmap> = self
+            .grid
+            .iter()
+            .map(|p| tiles[p.tile].transform(p.orientation).inner())
+            .collect();
+        let size = inner[0].width;
+        let width = size * self.side;
+        Bitmap::from_fn(width, width, |x, y| {
+            inner[(y / size) * self.side + x / size].get(x % size, y % size)
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SAMPLE: &str = include_str!("bin/day20.sample");
+
+    fn sample_tiles() -> Vec<Bitmap> {
+        SAMPLE
+            .split("\n\n")
+            .filter(|t| !t.trim().is_empty())
+            .map(|t| Bitmap::parse(t.split_once('\n').unwrap().1).unwrap())
+            .collect()
+    }
+
+    #[test]
+    fn test_transform() -> Result<()> {
+        let image = Bitmap::parse("##.\n...\n#..")?;
+        assert_eq!(
+            image.transform(Orientation {
+                flipped: false,
+                rotations: 1
+            }),
+            Bitmap::parse("#.#\n..#\n...")?
+        );
+        assert_eq!(
+            image.transform(Orientation {
+                flipped: true,
+                rotations: 0
+            }),
+            Bitmap::parse(".##\n...\n..#")?
+        );
+        let all: HashSet<Bitmap> = Orientation::all().map(|o| image.transform(o)).collect();
+        assert_eq!(all.len(), 8);
+        let turned = image.transform(Orientation {
+            flipped: true,
+            rotations: 3,
+        });
+        assert_eq!(
+            turned.transform(Orientation {
+                flipped: false,
+                rotations: 1
+            }),
+            image.transform(Orientation {
+                flipped: true,
+                rotations: 0
+            })
+        );
+        // not square
+        let wide = Bitmap::parse("#..")?;
+        assert_eq!(
+            wide.transform(Orientation {
+                flipped: false,
+                rotations: 1
+            }),
+            Bitmap::parse("#\n.\n.")?
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_edges() -> Result<()> {
+        let image = Bitmap::parse("#.#.\n...#\n#...\n##..")?;
+        assert_eq!(signature(&image.edge(Side::Top)), 0b1010);
+        assert_eq!(signature(&image.edge(Side::Right)), 0b0100);
+        assert_eq!(signature(&image.edge(Side::Bottom)), 0b1100);
+        assert_eq!(signature(&image.edge(Side::Left)), 0b1011);
+        assert_eq!(canonical_signature(&image.edge(Side::Top)), 0b0101);
+        assert_eq!(canonical_signature(&image.edge(Side::Left)), 0b1011);
+        assert_eq!(signature(&[false, true, false, false]), 4);
+        assert_eq!(image.inner(), Bitmap::parse("..\n..")?);
+        Ok(())
+    }
+
+    #[test]
+    fn test_assemble() -> Result<()> {
+        let tiles = sample_tiles();
+        let assembly = assemble(&tiles)?;
+        assert_eq!(assembly.side, 3);
+        for (pos, placement) in assembly.grid.iter().enumerate() {
+            let tile = tiles[placement.tile].transform(placement.orientation);
+            if !pos.is_multiple_of(3) {
+                let left = &assembly.grid[pos - 1];
+                let left = tiles[left.tile].transform(left.orientation);
+                assert_eq!(left.edge(Side::Right), tile.edge(Side::Left));
+            }
+            if pos >= 3 {
+                let above = &assembly.grid[pos - 3];
+                let above = tiles[above.tile].transform(above.orientation);
+                assert_eq!(above.edge(Side::Bottom), tile.edge(Side::Top));
+            }
+        }
+        let image = assembly.image(&tiles);
+        assert_eq!((image.width(), image.height()), (24, 24));
+
+        assert!(assemble(&tiles[..8]).is_err());
+        let mut mismatched = tiles.clone();
+        mismatched[0] = Bitmap::from_fn(10, 10, |_, _| true);
+        assert!(assemble(&mismatched).is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_find() -> Result<()> {
+        let image = Bitmap::parse("....\n.#..\n.##.\n....")?;
+        let mask = Bitmap::parse("#.\n##")?;
+        assert_eq!(image.find(&mask), vec![Coor::new(1, 1)]);
+        let turned = Bitmap::parse(".#\n##")?;
+        assert!(image.find(&turned).is_empty());
+        let (orientation, covered) = image.find_any_orientation(&turned).unwrap();
+        assert_eq!(turned.transform(orientation), mask);
+        assert_eq!(covered.len(), 3);
+        assert!(image.find_any_orientation(&Bitmap::parse("###")?).is_none());
+        Ok(())
+    }
+}
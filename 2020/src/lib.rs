@@ -1,5 +1,8 @@
 // pub mod cpu;
 pub mod coor;
+pub mod expression;
+pub mod jigsaw;
 pub mod mod_arith;
+pub mod repair;
 pub mod shared;
 pub use crate::shared::dispatch;
@@ -0,0 +1,171 @@
+// finds a single-instruction change that makes a looping program terminate.
+// rather than re-running the program once per candidate, work out which
+// instructions can reach the end at all (walking the control flow graph
+// backwards from it), then look along the original path for an instruction
+// whose mutation jumps into that set
+use machine::{Flow, Instruction};
+use std::collections::{HashSet, VecDeque};
+
+// for instructions whose control flow doesn't depend on the machine state
+pub trait StaticFlow: Instruction {
+    fn flow(&self) -> Flow;
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Repair<I> {
+    pub index: usize,
+    pub original: I,
+    pub replacement: I,
+    pub program: Vec<I>,
+}
+
+// where control goes after the instruction at `pc`. `Flow::Halt` counts as
+// running off the end, ie terminating
+fn next(pc: usize, flow: Flow, len: usize) -> Option<usize> {
+    let next = match flow {
+        Flow::Next => pc as i64 + 1,
+        Flow::Jump(offset) => pc as i64 + offset,
+        Flow::Halt => len as i64,
+    };
+    if next >= 0 && next <= len as i64 {
+        Some(next as usize)
+    } else {
+        None
+    }
+}
+
+// all instructions from which the unmodified program runs to exactly the end
+fn reaches_end<I: StaticFlow>(program: &[I]) -> HashSet<usize> {
+    let len = program.len();
+    let mut predecessors = vec![vec![]; len + 1];
+    for (pc, instruction) in program.iter().enumerate() {
+        if let Some(next) = next(pc, instruction.flow(), len) {
+            predecessors[next].push(pc);
+        }
+    }
+    let mut seen: HashSet<_> = vec![len].into_iter().collect();
+    let mut queue: VecDeque<_> = vec![len].into_iter().collect();
+    while let Some(pc) = queue.pop_front() {
+        for &previous in &predecessors[pc] {
+            if seen.insert(previous) {
+                queue.push_back(previous);
+            }
+        }
+    }
+    seen
+}
+
+// `mutations` lists the allowed replacements for an instruction. returns
+// `None` if the program already terminates, or no single change fixes it
+pub fn repair<I, F>(program: &[I], mutations: F) -> Option<Repair<I>>
+where
+    I: StaticFlow + Clone,
+    F: Fn(&I) -> Vec<I>,
+{
+    let len = program.len();
+    let good = reaches_end(program);
+    if good.contains(&0) {
+        return None;
+    }
+    // every instruction on the original path is outside `good`, so a jump
+    // into `good` can't lead back to the changed instruction
+    let mut seen = HashSet::new();
+    let mut pc = 0;
+    while pc < len && seen.insert(pc) {
+        for replacement in mutations(&program[pc]) {
+            if let Some(target) = next(pc, replacement.flow(), len) {
+                if good.contains(&target) {
+                    let mut repaired = program.to_vec();
+                    repaired[pc] = replacement.clone();
+                    return Some(Repair {
+                        index: pc,
+                        original: program[pc].clone(),
+                        replacement,
+                        program: repaired,
+                    });
+                }
+            }
+        }
+        pc = next(pc, program[pc].flow(), len)?;
+    }
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[derive(Debug, Clone, PartialEq)]
+    enum Op {
+        Step,
+        Jump(i64),
+        Halt,
+    }
+
+    impl Instruction for Op {
+        type State = ();
+
+        fn execute(&self, _: &mut ()) -> Flow {
+            self.flow()
+        }
+    }
+
+    impl StaticFlow for Op {
+        fn flow(&self) -> Flow {
+            match self {
+                Op::Step => Flow::Next,
+                Op::Jump(offset) => Flow::Jump(*offset),
+                Op::Halt => Flow::Halt,
+            }
+        }
+    }
+
+    // a jump may become a step, and a step a jump by 1 or 2
+    fn mutations(op: &Op) -> Vec<Op> {
+        match op {
+            Op::Step => vec![Op::Jump(1), Op::Jump(2)],
+            Op::Jump(_) => vec![Op::Step],
+            Op::Halt => vec![],
+        }
+    }
+
+    #[test]
+    fn test_terminates() {
+        assert_eq!(
+            repair(&[Op::Step, Op::Jump(2), Op::Jump(-2)], mutations),
+            None
+        );
+        assert_eq!(repair(&[Op::Halt, Op::Jump(0)], mutations), None);
+    }
+
+    #[test]
+    fn test_unfixable() {
+        // turning either jump into a step still loops back to the first one
+        assert_eq!(repair(&[Op::Jump(0), Op::Jump(-1)], mutations), None);
+        // or nothing may change at all
+        assert_eq!(repair(&[Op::Jump(0)], |_: &Op| vec![]), None);
+    }
+
+    #[test]
+    fn test_repair() {
+        // both changing the step at 0 and the jump at 1 fix it. the first
+        // instruction along the original path wins, with its first
+        // replacement that works
+        let program = [Op::Step, Op::Jump(-1), Op::Halt];
+        assert_eq!(
+            repair(&program, mutations),
+            Some(Repair {
+                index: 0,
+                original: Op::Step,
+                replacement: Op::Jump(2),
+                program: vec![Op::Jump(2), Op::Jump(-1), Op::Halt],
+            })
+        );
+        // without mutations of steps, only the jump can change
+        let jumps_only = |op: &Op| match op {
+            Op::Jump(_) => vec![Op::Step],
+            _ => vec![],
+        };
+        assert_eq!(repair(&program, jumps_only).map(|r| r.index), Some(1));
+    }
+}
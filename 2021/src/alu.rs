@@ -0,0 +1,641 @@
+use anyhow::{bail, Context, Error, Result};
+use std::fmt;
+use std::rc::Rc;
+use std::str::FromStr;
+
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum Reg {
+    W,
+    X,
+    Y,
+    Z,
+}
+
+impl Reg {
+    fn index(self) -> usize {
+        match self {
+            Reg::W => 0,
+            Reg::X => 1,
+            Reg::Y => 2,
+            Reg::Z => 3,
+        }
+    }
+}
+
+impl FromStr for Reg {
+    type Err = Error;
+    fn from_str(s: &str) -> Result<Self> {
+        Ok(match s {
+            "w" => Reg::W,
+            "x" => Reg::X,
+            "y" => Reg::Y,
+            "z" => Reg::Z,
+            _ => bail!("invalid register: {}", s),
+        })
+    }
+}
+
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum Operand {
+    Reg(Reg),
+    Lit(i64),
+}
+
+impl FromStr for Operand {
+    type Err = Error;
+    fn from_str(s: &str) -> Result<Self> {
+        match s.parse() {
+            Ok(value) => Ok(Operand::Lit(value)),
+            Err(_) => Ok(Operand::Reg(s.parse()?)),
+        }
+    }
+}
+
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum BinOp {
+    Add,
+    Mul,
+    Div,
+    Mod,
+    Eql,
+}
+
+impl BinOp {
+    fn apply(self, a: i64, b: i64) -> Result<i64> {
+        Ok(match self {
+            BinOp::Add => a + b,
+            BinOp::Mul => a * b,
+            BinOp::Div if b == 0 => bail!("division by zero"),
+            BinOp::Div => a / b,
+            BinOp::Mod if a < 0 || b <= 0 => bail!("invalid mod: {} % {}", a, b),
+            BinOp::Mod => a % b,
+            BinOp::Eql => (a == b) as i64,
+        })
+    }
+}
+
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum Instruction {
+    Inp(Reg),
+    Bin(BinOp, Reg, Operand),
+}
+
+impl FromStr for Instruction {
+    type Err = Error;
+    fn from_str(s: &str) -> Result<Self> {
+        let parts: Vec<_> = s.split_whitespace().collect();
+        let op = match parts.as_slice() {
+            ["inp", a] => return Ok(Instruction::Inp(a.parse()?)),
+            [op, _, _] => match *op {
+                "add" => BinOp::Add,
+                "mul" => BinOp::Mul,
+                "div" => BinOp::Div,
+                "mod" => BinOp::Mod,
+                "eql" => BinOp::Eql,
+                _ => bail!("invalid instruction: {}", s),
+            },
+            _ => bail!("invalid instruction: {}", s),
+        };
+        Ok(Instruction::Bin(op, parts[1].parse()?, parts[2].parse()?))
+    }
+}
+
+pub fn parse(s: &str) -> Result<Vec<Instruction>> {
+    s.lines()
+        .map(|line| line.trim())
+        .filter(|line| !line.is_empty())
+        .enumerate()
+        .map(|(number, line)| line.parse().with_context(|| format!("line {}", number + 1)))
+        .collect()
+}
+
+pub fn run(program: &[Instruction], input: &[i64]) -> Result<[i64; 4]> {
+    let mut registers = [0; 4];
+    let mut input = input.iter();
+    for instruction in program {
+        match *instruction {
+            Instruction::Inp(reg) => {
+                registers[reg.index()] = *input.next().context("out of input")?;
+            }
+            Instruction::Bin(op, reg, operand) => {
+                let b = match operand {
+                    Operand::Reg(other) => registers[other.index()],
+                    Operand::Lit(value) => value,
+                };
+                registers[reg.index()] = op.apply(registers[reg.index()], b)?;
+            }
+        }
+    }
+    Ok(registers)
+}
+
+// symbolic values for analysing one `inp` block at a time: `Z` is z at the
+// start of the block, `Input` the digit it reads, and `Prev` any other
+// register carried over from the previous block
+#[derive(Debug, PartialEq, Eq, Clone)]
+enum Expr {
+    Lit(i64),
+    Input,
+    Z,
+    Prev(Reg),
+    Bin(BinOp, Rc<Expr>, Rc<Expr>),
+}
+
+const Z_MAX: i64 = 1 << 40;
+
+impl Expr {
+    fn interval(&self) -> (i64, i64) {
+        match self {
+            Expr::Lit(value) => (*value, *value),
+            Expr::Input => (1, 9),
+            Expr::Z => (0, Z_MAX),
+            Expr::Prev(_) => (i64::MIN / 4, i64::MAX / 4),
+            Expr::Bin(op, a, b) => {
+                let (a_lo, a_hi) = a.interval();
+                let (b_lo, b_hi) = b.interval();
+                match op {
+                    BinOp::Add => (a_lo.saturating_add(b_lo), a_hi.saturating_add(b_hi)),
+                    BinOp::Mul => {
+                        let products = [
+                            a_lo.saturating_mul(b_lo),
+                            a_lo.saturating_mul(b_hi),
+                            a_hi.saturating_mul(b_lo),
+                            a_hi.saturating_mul(b_hi),
+                        ];
+                        (
+                            *products.iter().min().unwrap(),
+                            *products.iter().max().unwrap(),
+                        )
+                    }
+                    BinOp::Div if b_lo > 0 && a_lo >= 0 => (a_lo / b_hi, a_hi / b_lo),
+                    BinOp::Mod if b_lo > 0 && a_lo >= 0 => (0, a_hi.min(b_hi - 1)),
+                    BinOp::Eql => (0, 1),
+                    _ => (i64::MIN / 4, i64::MAX / 4),
+                }
+            }
+        }
+    }
+
+    fn contains(&self, other: &Expr) -> bool {
+        if self == other {
+            return true;
+        }
+        match self {
+            Expr::Bin(_, a, b) => a.contains(other) || b.contains(other),
+            _ => false,
+        }
+    }
+
+    fn replace(self: &Rc<Self>, target: &Expr, value: &Rc<Expr>) -> Rc<Expr> {
+        if **self == *target {
+            return value.clone();
+        }
+        match &**self {
+            Expr::Bin(op, a, b) => bin(*op, a.replace(target, value), b.replace(target, value)),
+            _ => self.clone(),
+        }
+    }
+
+    fn find(&self, predicate: &impl Fn(&Expr) -> bool) -> Option<&Expr> {
+        if predicate(self) {
+            return Some(self);
+        }
+        match self {
+            Expr::Bin(_, a, b) => a.find(predicate).or_else(|| b.find(predicate)),
+            _ => None,
+        }
+    }
+}
+
+impl fmt::Display for Expr {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Expr::Lit(value) => write!(f, "{}", value),
+            Expr::Input => write!(f, "w"),
+            Expr::Z => write!(f, "z"),
+            Expr::Prev(reg) => write!(f, "{:?}", reg),
+            Expr::Bin(op, a, b) => {
+                let symbol = match op {
+                    BinOp::Add => "+",
+                    BinOp::Mul => "*",
+                    BinOp::Div => "/",
+                    BinOp::Mod => "%",
+                    BinOp::Eql => "==",
+                };
+                write!(f, "({} {} {})", a, symbol, b)
+            }
+        }
+    }
+}
+
+// builds `a op b`, simplifying where the operands (or their intervals) allow
+fn bin(op: BinOp, a: Rc<Expr>, b: Rc<Expr>) -> Rc<Expr> {
+    if let (Expr::Lit(a), Expr::Lit(b)) = (&*a, &*b) {
+        if let Ok(value) = op.apply(*a, *b) {
+            return Rc::new(Expr::Lit(value));
+        }
+    }
+    let (a_lo, a_hi) = a.interval();
+    let (b_lo, b_hi) = b.interval();
+    match (op, &*a, &*b) {
+        (BinOp::Add, Expr::Lit(0), _) => return b,
+        (BinOp::Add, _, Expr::Lit(0)) => return a,
+        (BinOp::Mul, Expr::Lit(0), _) | (BinOp::Mul, _, Expr::Lit(0)) => {
+            return Rc::new(Expr::Lit(0))
+        }
+        (BinOp::Mul, Expr::Lit(1), _) => return b,
+        (BinOp::Mul, _, Expr::Lit(1)) => return a,
+        (BinOp::Div, _, Expr::Lit(1)) => return a,
+        (BinOp::Div, _, Expr::Lit(d)) if *d > 0 && a_lo >= 0 && a_hi < *d => {
+            return Rc::new(Expr::Lit(0))
+        }
+        (BinOp::Mod, _, Expr::Lit(m)) if *m > 0 && a_lo >= 0 && a_hi < *m => return a,
+        (BinOp::Eql, _, _) if a_hi < b_lo || b_hi < a_lo => return Rc::new(Expr::Lit(0)),
+        _ => {}
+    }
+    Rc::new(Expr::Bin(op, a, b))
+}
+
+fn lit(value: i64) -> Rc<Expr> {
+    Rc::new(Expr::Lit(value))
+}
+
+// what one `inp` block does to z, in terms of z at the start of the block and
+// the digit `w` it reads
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum Block {
+    // z = z * base + w + offset
+    Push { base: i64, offset: i64 },
+    // if z % base + check == w { z = z / base } else { z = z / base * base + w + offset }
+    Pop { base: i64, check: i64, offset: i64 },
+}
+
+fn symbolic_block(block: &[Instruction]) -> Result<Rc<Expr>> {
+    let mut registers = [
+        Rc::new(Expr::Prev(Reg::W)),
+        Rc::new(Expr::Prev(Reg::X)),
+        Rc::new(Expr::Prev(Reg::Y)),
+        Rc::new(Expr::Z),
+    ];
+    for instruction in block {
+        match *instruction {
+            Instruction::Inp(reg) => registers[reg.index()] = Rc::new(Expr::Input),
+            Instruction::Bin(op, reg, operand) => {
+                let b = match operand {
+                    Operand::Reg(other) => registers[other.index()].clone(),
+                    Operand::Lit(value) => lit(value),
+                };
+                registers[reg.index()] = bin(op, registers[reg.index()].clone(), b);
+            }
+        }
+    }
+    let z = registers[Reg::Z.index()].clone();
+    for reg in [Reg::W, Reg::X, Reg::Y] {
+        if z.contains(&Expr::Prev(reg)) {
+            bail!("z depends on {:?} from the previous block: {}", reg, z);
+        }
+    }
+    Ok(z)
+}
+
+// matches `z * base + w + offset`, in any order of the additions
+fn match_push(z: &Expr, start: &Expr) -> Option<(i64, i64)> {
+    let mut terms = vec![];
+    let mut stack = vec![z];
+    while let Some(expr) = stack.pop() {
+        match expr {
+            Expr::Bin(BinOp::Add, a, b) => {
+                stack.push(a);
+                stack.push(b);
+            }
+            _ => terms.push(expr),
+        }
+    }
+    let mut base = None;
+    let mut offset = 0;
+    let mut inputs = 0;
+    for term in terms {
+        match term {
+            Expr::Lit(value) => offset += value,
+            Expr::Input => inputs += 1,
+            Expr::Bin(BinOp::Mul, a, b) if **a == *start || **b == *start => {
+                let other = if **a == *start { b } else { a };
+                match **other {
+                    Expr::Lit(value) if base.is_none() => base = Some(value),
+                    _ => return None,
+                }
+            }
+            _ => return None,
+        }
+    }
+    match (base, inputs) {
+        (Some(base), 1) => Some((base, offset)),
+        _ => None,
+    }
+}
+
+pub fn analyse_block(block: &[Instruction]) -> Result<Block> {
+    let z = symbolic_block(block)?;
+    if let Some((base, offset)) = match_push(&z, &Expr::Z) {
+        return Ok(Block::Push { base, offset });
+    }
+
+    // find `z % base + check == w`, the condition for the block to pop
+    let condition = z
+        .find(&|expr| match expr {
+            Expr::Bin(BinOp::Eql, a, b) => **a == Expr::Input || **b == Expr::Input,
+            _ => false,
+        })
+        .with_context(|| format!("unrecognized block: {}", z))?
+        .clone();
+    let compared = match &condition {
+        Expr::Bin(_, a, b) if **b == Expr::Input => a.clone(),
+        Expr::Bin(_, a, _) => a.clone(),
+        _ => unreachable!(),
+    };
+    let (base, check) = match &*compared {
+        Expr::Bin(BinOp::Add, a, b) => match (&**a, &**b) {
+            (Expr::Bin(BinOp::Mod, z, m), Expr::Lit(check))
+            | (Expr::Lit(check), Expr::Bin(BinOp::Mod, z, m))
+                if **z == Expr::Z =>
+            {
+                match **m {
+                    Expr::Lit(base) => (base, *check),
+                    _ => bail!("unrecognized condition: {}", condition),
+                }
+            }
+            _ => bail!("unrecognized condition: {}", condition),
+        },
+        _ => bail!("unrecognized condition: {}", condition),
+    };
+
+    let popped = bin(BinOp::Div, Rc::new(Expr::Z), lit(base));
+    let when_equal = z.replace(&condition, &lit(1));
+    if *when_equal != *popped {
+        bail!("block doesn't pop when {}: {}", condition, when_equal);
+    }
+    let when_different = z.replace(&condition, &lit(0));
+    match match_push(&when_different, &popped) {
+        Some((b, offset)) if b == base => Ok(Block::Pop {
+            base,
+            check,
+            offset,
+        }),
+        _ => bail!(
+            "block doesn't push when not {}: {}",
+            condition,
+            when_different
+        ),
+    }
+}
+
+pub fn blocks(program: &[Instruction]) -> Result<Vec<Block>> {
+    let mut starts: Vec<_> = program
+        .iter()
+        .enumerate()
+        .filter(|(_, instruction)| matches!(instruction, Instruction::Inp(_)))
+        .map(|(index, _)| index)
+        .collect();
+    if starts.first() != Some(&0) {
+        bail!("program doesn't start with inp");
+    }
+    starts.push(program.len());
+    starts
+        .windows(2)
+        .enumerate()
+        .map(|(index, window)| {
+            analyse_block(&program[window[0]..window[1]])
+                .with_context(|| format!("block {}", index))
+        })
+        .collect()
+}
+
+// `digits[second] == digits[first] + delta`
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub struct Constraint {
+    pub first: usize,
+    pub second: usize,
+    pub delta: i64,
+}
+
+// z acts as a stack of base 26 (or whatever base) digits: pushing blocks can
+// never pop (their check is out of range of a digit), so for z to end at 0
+// every popping block must match the value pushed by its partner
+pub fn constraints(blocks: &[Block]) -> Result<Vec<Constraint>> {
+    let mut stack = vec![];
+    let mut constraints = vec![];
+    for (index, block) in blocks.iter().enumerate() {
+        match *block {
+            Block::Push { base, offset } => {
+                if offset < 0 || offset + 9 >= base {
+                    bail!(
+                        "block {} pushes {} out of range for base {}",
+                        index,
+                        offset,
+                        base
+                    );
+                }
+                stack.push((index, offset, base));
+            }
+            Block::Pop {
+                base,
+                check,
+                offset: _,
+            } => {
+                let (first, offset, pushed_base) = stack
+                    .pop()
+                    .with_context(|| format!("block {} pops empty z", index))?;
+                if pushed_base != base {
+                    bail!(
+                        "block {} pops base {}, pushed as {}",
+                        index,
+                        base,
+                        pushed_base
+                    );
+                }
+                constraints.push(Constraint {
+                    first,
+                    second: index,
+                    delta: offset + check,
+                });
+            }
+        }
+    }
+    if !stack.is_empty() {
+        bail!("{} values left on z", stack.len());
+    }
+    constraints.sort_by_key(|c| c.first);
+    Ok(constraints)
+}
+
+fn solve_with(program: &[Instruction], pick: impl Fn(i64) -> (i64, i64)) -> Result<i64> {
+    let blocks = blocks(program)?;
+    let mut digits = vec![0; blocks.len()];
+    for constraint in constraints(&blocks)? {
+        let (first, second) = pick(constraint.delta);
+        if !(1..=9).contains(&first) || !(1..=9).contains(&second) {
+            bail!("no digits satisfy {:?}", constraint);
+        }
+        digits[constraint.first] = first;
+        digits[constraint.second] = second;
+    }
+    let z = run(program, &digits)?[Reg::Z.index()];
+    if z != 0 {
+        bail!("solution {:?} gives z = {}", digits, z);
+    }
+    Ok(digits.iter().fold(0, |acc, d| acc * 10 + d))
+}
+
+pub fn largest(program: &[Instruction]) -> Result<i64> {
+    solve_with(program, |delta| {
+        let first = 9.min(9 - delta);
+        (first, first + delta)
+    })
+}
+
+pub fn smallest(program: &[Instruction]) -> Result<i64> {
+    solve_with(program, |delta| {
+        let first = 1.max(1 - delta);
+        (first, first + delta)
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn block(zdiv: i64, xadd: i64, yadd: i64) -> String {
+        format!(
+            "inp w
+mul x 0
+add x z
+mod x 26
+div z {}
+add x {}
+eql x w
+eql x 0
+mul y 0
+add y 25
+mul y x
+add y 1
+mul z y
+mul y 0
+add y w
+add y {}
+mul y x
+add z y
+",
+            zdiv, xadd, yadd
+        )
+    }
+
+    fn monad(params: &[(i64, i64, i64)]) -> String {
+        params.iter().map(|&(a, b, c)| block(a, b, c)).collect()
+    }
+
+    const PARAMS: [(i64, i64, i64); 14] = [
+        (1, 12, 4),
+        (1, 11, 11),
+        (1, 13, 5),
+        (1, 11, 11),
+        (1, 14, 14),
+        (26, -10, 7),
+        (1, 11, 11),
+        (26, -9, 4),
+        (26, -3, 6),
+        (1, 13, 5),
+        (26, -5, 9),
+        (26, -10, 12),
+        (26, -4, 14),
+        (26, -5, 14),
+    ];
+
+    #[test]
+    fn test_run() -> Result<()> {
+        let program = parse("inp x\nmul x -1")?;
+        assert_eq!(run(&program, &[3])?, [0, -3, 0, 0]);
+        let program = parse("inp z\ninp x\nmul z 3\neql z x")?;
+        assert_eq!(run(&program, &[2, 6])?[3], 1);
+        assert!(run(&program, &[2]).is_err());
+        assert!(parse("inp q").is_err());
+        assert!(run(&parse("div x 0")?, &[]).is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_analyse_block() -> Result<()> {
+        assert_eq!(
+            analyse_block(&parse(&block(1, 12, 4))?)?,
+            Block::Push {
+                base: 26,
+                offset: 4
+            }
+        );
+        assert_eq!(
+            analyse_block(&parse(&block(26, -10, 7))?)?,
+            Block::Pop {
+                base: 26,
+                check: -10,
+                offset: 7
+            }
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_brute_force() -> Result<()> {
+        let program = parse(&monad(&[(1, 12, 4), (1, 11, 3), (26, -6, 2), (26, -8, 1)]))?;
+        let valid: Vec<i64> = (1111..=9999)
+            .filter(|n| {
+                let digits: Vec<i64> = n.to_string().bytes().map(|b| (b - b'0') as i64).collect();
+                !digits.contains(&0) && run(&program, &digits).unwrap()[3] == 0
+            })
+            .collect();
+        assert_eq!(largest(&program)?, *valid.iter().max().unwrap());
+        assert_eq!(smallest(&program)?, *valid.iter().min().unwrap());
+        Ok(())
+    }
+
+    #[test]
+    fn test_solve() -> Result<()> {
+        let program = parse(&monad(&PARAMS))?;
+        assert_eq!(largest(&program)?, 92915979999498);
+        assert_eq!(smallest(&program)?, 21611513911181);
+        Ok(())
+    }
+
+    #[test]
+    fn test_reordered() -> Result<()> {
+        // the same blocks with independent instructions shuffled and y and x swapped
+        let program: String = PARAMS
+            .iter()
+            .map(|&(zdiv, xadd, yadd)| {
+                format!(
+                    "inp w
+mul x 0
+add x 25
+mul y 0
+add y z
+mod y 26
+add y {}
+div z {}
+eql y w
+eql y 0
+mul x y
+add x 1
+mul z x
+mul x 0
+add x {}
+add x w
+mul x y
+add z x
+",
+                    xadd, zdiv, yadd
+                )
+            })
+            .collect();
+        let program = parse(&program)?;
+        assert_eq!(largest(&program)?, 92915979999498);
+        Ok(())
+    }
+}
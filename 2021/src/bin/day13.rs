@@ -119,12 +119,23 @@ fn part1(input: &str) -> Result<usize> {
     Ok(instructions.dots.len())
 }
 
-fn part2(input: &str) -> Result<String> {
+fn fold_all(input: &str) -> Result<Instructions> {
     let mut instructions: Instructions = input.parse()?;
     while !instructions.folds.is_empty() {
         instructions.fold();
     }
-    instructions.print()
+    Ok(instructions)
+}
+
+fn part2(input: &str) -> Result<String> {
+    let instructions = fold_all(input)?;
+    let dots = &instructions.dots;
+    ocr::read_points(dots.iter().map(|c| (c.x, c.y))).with_context(|| {
+        format!(
+            "couldn't read the folded paper:\n{}",
+            instructions.print().unwrap_or_default()
+        )
+    })
 }
 
 #[cfg(test)]
@@ -160,9 +171,9 @@ fold along x=5";
     }
 
     #[test]
-    fn test_part2() -> Result<()> {
+    fn test_print() -> Result<()> {
         assert_eq!(
-            part2(TEST_INPUT)?,
+            fold_all(TEST_INPUT)?.print()?,
             "#####
 #   #
 #   #
@@ -170,6 +181,9 @@ fold along x=5";
 #####
 "
         );
+        // a square isn't a letter, so the error shows what was drawn
+        let err = part2(TEST_INPUT).unwrap_err().to_string();
+        assert!(err.starts_with("couldn't read the folded paper:\n#####\n#   #\n"));
         Ok(())
     }
 }
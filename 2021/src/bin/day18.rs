@@ -1,348 +1,367 @@
-use anyhow::{Context, Error, Result};
+use anyhow::{Context, Result};
 use aoc2021::dispatch;
-use std::fmt::Write;
-use std::str::FromStr;
+use aoc2021::snailfish::SnailNumber;
 
 fn main() -> Result<()> {
     dispatch(part1, part2)
 }
 
-#[derive(Debug, PartialEq, Clone, Copy)]
-enum Element {
-    Number(i32),
-    LParen,
-    RParen,
+fn parse_numbers(input: &str) -> Result<Vec<SnailNumber>> {
+    input.trim().lines().map(str::parse).collect()
 }
 
-impl Element {
-    fn _print(&self) -> String {
-        use Element::*;
-        match self {
-            Number(n) => format!("{}", n),
-            LParen => "[".to_string(),
-            RParen => "]".to_string(),
+fn part1(input: &str) -> Result<u32> {
+    let list = parse_numbers(input)?;
+    let sum = list
+        .into_iter()
+        .reduce(|acc, number| acc + number)
+        .context("no snailfish numbers")?;
+    Ok(sum.magnitude())
+}
+
+fn part2(input: &str) -> Result<u32> {
+    let list = parse_numbers(input)?;
+    let mut max = 0;
+    for (i, a) in list.iter().enumerate() {
+        for (j, b) in list.iter().enumerate() {
+            if i != j {
+                max = max.max((a.clone() + b.clone()).magnitude());
+            }
         }
     }
+    Ok(max)
 }
 
-impl FromStr for Element {
-    type Err = Error;
-    fn from_str(s: &str) -> Result<Self> {
-        use Element::*;
-        Ok(match s {
-            "[" => LParen,
-            "]" => RParen,
-            n => Number(n.parse().context(format!("failed to parse `{}`", n))?),
-        })
+// the original flat-token implementation, kept as a reference to check the
+// tree against
+#[cfg(test)]
+mod flat {
+    use anyhow::{Context, Error, Result};
+    use std::fmt::Write;
+    use std::str::FromStr;
+
+    #[derive(Debug, PartialEq, Clone, Copy)]
+    pub enum Element {
+        Number(i32),
+        LParen,
+        RParen,
     }
-}
 
-fn parse(input: &str) -> Result<Vec<Element>> {
-    let mut res = vec![];
-    for c in input.trim().chars() {
-        if c == ',' {
-            continue;
+    impl Element {
+        fn _print(&self) -> String {
+            use Element::*;
+            match self {
+                Number(n) => format!("{}", n),
+                LParen => "[".to_string(),
+                RParen => "]".to_string(),
+            }
         }
-        res.push(c.to_string().parse()?)
     }
-    Ok(res)
-}
 
-fn _print(pair: &[Element]) -> String {
-    use Element::*;
-    let mut output = String::new();
-
-    for entry in pair.windows(2) {
-        let comma = match (entry[0], entry[1]) {
-            (Number(_), Number(_)) => true,
-            (Number(_), LParen) => true,
-            (Number(_), RParen) => false,
-
-            (LParen, Number(_)) => false,
-            (LParen, LParen) => false,
-            (LParen, RParen) => false,
-
-            (RParen, Number(_)) => true,
-            (RParen, LParen) => true,
-            (RParen, RParen) => false,
-        };
-        write!(
-            &mut output,
-            "{}{}",
-            entry[0]._print(),
-            if comma { "," } else { "" }
-        )
-        .expect("print fail");
-    }
-    write!(&mut output, "{}", pair[pair.len() - 1]._print()).expect("print last fail");
-    output
-}
+    impl FromStr for Element {
+        type Err = Error;
+        fn from_str(s: &str) -> Result<Self> {
+            use Element::*;
+            Ok(match s {
+                "[" => LParen,
+                "]" => RParen,
+                n => Number(n.parse().context(format!("failed to parse `{}`", n))?),
+            })
+        }
+    }
 
-fn find_explode_position(pair: &[Element]) -> Option<(usize, usize)> {
-    use Element::*;
-    let mut depth = 0;
-    let mut start = None;
-    let mut inside = false;
-    for (idx, element) in pair.iter().enumerate() {
-        match element {
-            Number(_) => {}
-            LParen => {
-                if !inside && depth == 4 {
-                    start = Some(idx);
-                    inside = true;
-                }
-                depth += 1;
-            }
-            RParen => {
-                depth -= 1;
-                if inside && depth == 4 {
-                    return Some((start.expect("must find start before end"), idx));
-                }
+    pub fn parse(input: &str) -> Result<Vec<Element>> {
+        let mut res = vec![];
+        for c in input.trim().chars() {
+            if c == ',' {
+                continue;
             }
+            res.push(c.to_string().parse()?)
         }
+        Ok(res)
     }
-    None
-}
 
-fn index_of_last_number_before(pair: &[Element], pos: usize) -> Option<usize> {
-    use Element::*;
-    let mut last = None;
-    for (idx, element) in pair[..pos].iter().enumerate() {
-        if let Number(_) = element {
-            last = Some(idx);
+    pub fn _print(pair: &[Element]) -> String {
+        use Element::*;
+        let mut output = String::new();
+
+        for entry in pair.windows(2) {
+            let comma = match (entry[0], entry[1]) {
+                (Number(_), Number(_)) => true,
+                (Number(_), LParen) => true,
+                (Number(_), RParen) => false,
+
+                (LParen, Number(_)) => false,
+                (LParen, LParen) => false,
+                (LParen, RParen) => false,
+
+                (RParen, Number(_)) => true,
+                (RParen, LParen) => true,
+                (RParen, RParen) => false,
+            };
+            write!(
+                &mut output,
+                "{}{}",
+                entry[0]._print(),
+                if comma { "," } else { "" }
+            )
+            .expect("print fail");
         }
+        write!(&mut output, "{}", pair[pair.len() - 1]._print()).expect("print last fail");
+        output
     }
-    last
-}
 
-fn index_of_first_number_after(pair: &[Element], pos: usize) -> Option<usize> {
-    use Element::*;
-    let start = pos + 1;
-    for (idx, element) in pair[pos + 1..].iter().enumerate() {
-        if let Number(_) = element {
-            return Some(idx + start);
+    pub fn find_explode_position(pair: &[Element]) -> Option<(usize, usize)> {
+        use Element::*;
+        let mut depth = 0;
+        let mut start = None;
+        let mut inside = false;
+        for (idx, element) in pair.iter().enumerate() {
+            match element {
+                Number(_) => {}
+                LParen => {
+                    if !inside && depth == 4 {
+                        start = Some(idx);
+                        inside = true;
+                    }
+                    depth += 1;
+                }
+                RParen => {
+                    depth -= 1;
+                    if inside && depth == 4 {
+                        return Some((start.expect("must find start before end"), idx));
+                    }
+                }
+            }
         }
+        None
     }
-    None
-}
 
-fn left_value(pair: &[Element]) -> i32 {
-    use Element::*;
-    for element in pair {
-        if let Number(n) = element {
-            return *n;
+    pub fn index_of_last_number_before(pair: &[Element], pos: usize) -> Option<usize> {
+        use Element::*;
+        let mut last = None;
+        for (idx, element) in pair[..pos].iter().enumerate() {
+            if let Number(_) = element {
+                last = Some(idx);
+            }
         }
+        last
     }
-    unreachable!();
-}
 
-fn right_value(pair: &[Element]) -> i32 {
-    use Element::*;
-    for element in pair.iter().rev() {
-        if let Number(n) = element {
-            return *n;
+    pub fn index_of_first_number_after(pair: &[Element], pos: usize) -> Option<usize> {
+        use Element::*;
+        let start = pos + 1;
+        for (idx, element) in pair[pos + 1..].iter().enumerate() {
+            if let Number(_) = element {
+                return Some(idx + start);
+            }
         }
+        None
     }
-    unreachable!();
-}
 
-fn get_number(element: Element) -> i32 {
-    use Element::*;
-    match element {
-        Number(n) => n,
-        _ => unreachable!(),
+    fn left_value(pair: &[Element]) -> i32 {
+        use Element::*;
+        for element in pair {
+            if let Number(n) = element {
+                return *n;
+            }
+        }
+        unreachable!();
     }
-}
 
-fn explode(pair: &[Element]) -> Option<Vec<Element>> {
-    use Element::*;
-    let mut res = vec![];
-    if let Some((start, end)) = find_explode_position(pair) {
-        let left_val = left_value(&pair[start..=end]);
-        let right_val = right_value(&pair[start..=end]);
-        if let Some(last) = index_of_last_number_before(pair, start) {
-            for element in &pair[..last] {
-                res.push(*element);
+    fn right_value(pair: &[Element]) -> i32 {
+        use Element::*;
+        for element in pair.iter().rev() {
+            if let Number(n) = element {
+                return *n;
             }
-            res.push(Number(get_number(pair[last]) + left_val));
+        }
+        unreachable!();
+    }
 
-            for element in &pair[last + 1..start] {
-                res.push(*element);
-            }
-        } else {
-            for element in &pair[..start] {
-                res.push(*element);
-            }
+    fn get_number(element: Element) -> i32 {
+        use Element::*;
+        match element {
+            Number(n) => n,
+            _ => unreachable!(),
         }
+    }
 
-        res.push(Number(0));
+    pub fn explode(pair: &[Element]) -> Option<Vec<Element>> {
+        use Element::*;
+        let mut res = vec![];
+        if let Some((start, end)) = find_explode_position(pair) {
+            let left_val = left_value(&pair[start..=end]);
+            let right_val = right_value(&pair[start..=end]);
+            if let Some(last) = index_of_last_number_before(pair, start) {
+                for element in &pair[..last] {
+                    res.push(*element);
+                }
+                res.push(Number(get_number(pair[last]) + left_val));
 
-        if let Some(first) = index_of_first_number_after(pair, end) {
-            for element in &pair[end + 1..first] {
-                res.push(*element);
+                for element in &pair[last + 1..start] {
+                    res.push(*element);
+                }
+            } else {
+                for element in &pair[..start] {
+                    res.push(*element);
+                }
             }
-            res.push(Number(get_number(pair[first]) + right_val));
 
-            for element in &pair[first + 1..] {
-                res.push(*element);
+            res.push(Number(0));
+
+            if let Some(first) = index_of_first_number_after(pair, end) {
+                for element in &pair[end + 1..first] {
+                    res.push(*element);
+                }
+                res.push(Number(get_number(pair[first]) + right_val));
+
+                for element in &pair[first + 1..] {
+                    res.push(*element);
+                }
+            } else {
+                for element in &pair[end + 1..] {
+                    res.push(*element);
+                }
             }
+            Some(res)
         } else {
-            for element in &pair[end + 1..] {
-                res.push(*element);
-            }
+            None
         }
-        Some(res)
-    } else {
-        None
     }
-}
 
-fn split(pair: &[Element]) -> Option<Vec<Element>> {
-    use Element::*;
-    let mut res = vec![];
-    let mut done = false;
-    for element in pair {
-        match element {
-            Number(n) if *n >= 10 => {
-                if done {
-                    res.push(Number(*n))
-                } else {
-                    res.push(LParen);
-                    res.push(Number(n / 2));
-                    res.push(Number((n + 1) / 2));
-                    res.push(RParen);
-                    done = true;
+    fn split(pair: &[Element]) -> Option<Vec<Element>> {
+        use Element::*;
+        let mut res = vec![];
+        let mut done = false;
+        for element in pair {
+            match element {
+                Number(n) if *n >= 10 => {
+                    if done {
+                        res.push(Number(*n))
+                    } else {
+                        res.push(LParen);
+                        res.push(Number(n / 2));
+                        res.push(Number((n + 1) / 2));
+                        res.push(RParen);
+                        done = true;
+                    }
                 }
+                e => res.push(*e),
             }
-            e => res.push(*e),
-        }
-    }
-    if done {
-        Some(res)
-    } else {
-        None
-    }
-}
-
-fn reduce(pair: &[Element]) -> Vec<Element> {
-    let mut pair = pair.to_vec();
-    loop {
-        let mut done = true;
-        while let Some(next) = explode(&pair) {
-            done = false;
-            pair = next;
-        }
-        if let Some(next) = split(&pair) {
-            done = false;
-            pair = next;
         }
         if done {
-            break pair;
+            Some(res)
+        } else {
+            None
         }
     }
-}
 
-fn add(left: &[Element], right: &[Element]) -> Vec<Element> {
-    use Element::*;
-    let mut res = vec![LParen];
-    for el in left {
-        res.push(*el);
-    }
-    for el in right {
-        res.push(*el);
-    }
-    res.push(RParen);
-    reduce(&res)
-}
-
-fn end(pair: &[Element]) -> usize {
-    use Element::*;
-    let mut depth = 0;
-    for (idx, element) in pair.iter().enumerate() {
-        match element {
-            Number(_) => {}
-            LParen => {
-                depth += 1;
+    fn reduce(pair: &[Element]) -> Vec<Element> {
+        let mut pair = pair.to_vec();
+        loop {
+            let mut done = true;
+            while let Some(next) = explode(&pair) {
+                done = false;
+                pair = next;
             }
-            RParen => {
-                depth -= 1;
-                if depth == 0 {
-                    return idx;
-                }
+            if let Some(next) = split(&pair) {
+                done = false;
+                pair = next;
+            }
+            if done {
+                break pair;
             }
         }
     }
-    unreachable!();
-}
 
-fn sum(pair: &[Element]) -> i32 {
-    use Element::*;
-    let mut total = 0;
-    assert_eq!(pair[0], LParen);
-    assert_eq!(pair[pair.len() - 1], RParen);
-    let inside = &pair[1..pair.len() - 1];
-
-    let mut pos = 0;
-    match inside[pos] {
-        Number(n) => {
-            total += 3 * n;
-            pos += 1;
+    pub fn add(left: &[Element], right: &[Element]) -> Vec<Element> {
+        use Element::*;
+        let mut res = vec![LParen];
+        for el in left {
+            res.push(*el);
         }
-        LParen => {
-            pos = end(inside) + 1;
-            total += 3 * sum(&inside[..pos]);
+        for el in right {
+            res.push(*el);
         }
-        RParen => unreachable!(),
+        res.push(RParen);
+        reduce(&res)
     }
 
-    match inside[pos] {
-        Number(n) => {
-            total += 2 * n;
-        }
-        LParen => {
-            total += 2 * sum(&inside[pos..]);
+    pub fn end(pair: &[Element]) -> usize {
+        use Element::*;
+        let mut depth = 0;
+        for (idx, element) in pair.iter().enumerate() {
+            match element {
+                Number(_) => {}
+                LParen => {
+                    depth += 1;
+                }
+                RParen => {
+                    depth -= 1;
+                    if depth == 0 {
+                        return idx;
+                    }
+                }
+            }
         }
-        RParen => unreachable!(),
+        unreachable!();
     }
-    total
-}
 
-fn add_list(list: &[Vec<Element>]) -> Vec<Element> {
-    let mut it = list.iter();
-    let mut acc: Vec<Element> = it.next().unwrap().to_vec();
-    for el in it {
-        acc = add(&acc, el);
-    }
-    acc
-}
-
-fn parse_list(input: &str) -> Result<Vec<Vec<Element>>> {
-    input.trim().lines().map(parse).collect::<Result<Vec<_>>>()
-}
+    pub fn sum(pair: &[Element]) -> i32 {
+        use Element::*;
+        let mut total = 0;
+        assert_eq!(pair[0], LParen);
+        assert_eq!(pair[pair.len() - 1], RParen);
+        let inside = &pair[1..pair.len() - 1];
+
+        let mut pos = 0;
+        match inside[pos] {
+            Number(n) => {
+                total += 3 * n;
+                pos += 1;
+            }
+            LParen => {
+                pos = end(inside) + 1;
+                total += 3 * sum(&inside[..pos]);
+            }
+            RParen => unreachable!(),
+        }
 
-fn part1(input: &str) -> Result<i32> {
-    let list: Vec<Vec<Element>> = parse_list(input)?;
-    let res = add_list(&list);
-    Ok(sum(&res))
-}
+        match inside[pos] {
+            Number(n) => {
+                total += 2 * n;
+            }
+            LParen => {
+                total += 2 * sum(&inside[pos..]);
+            }
+            RParen => unreachable!(),
+        }
+        total
+    }
 
-fn part2(input: &str) -> Result<i32> {
-    let list: Vec<Vec<Element>> = parse_list(input)?;
-    let mut max = 0;
-    for i in 0..list.len() {
-        for j in i + 1..list.len() {
-            max = max.max(sum(&add(&list[i], &list[j])));
-            max = max.max(sum(&add(&list[j], &list[i])));
+    pub fn add_list(list: &[Vec<Element>]) -> Vec<Element> {
+        let mut it = list.iter();
+        let mut acc: Vec<Element> = it.next().unwrap().to_vec();
+        for el in it {
+            acc = add(&acc, el);
         }
+        acc
+    }
+
+    pub fn parse_list(input: &str) -> Result<Vec<Vec<Element>>> {
+        input.trim().lines().map(parse).collect::<Result<Vec<_>>>()
     }
-    Ok(max)
 }
 
 #[cfg(test)]
 mod tests {
-    use super::_print as print;
+    use super::flat::_print as print;
+    use super::flat::*;
     use super::*;
 
+    use aoc2021::rng::Rng;
+
     #[test]
     fn test_part1() -> Result<()> {
         assert_eq!(
@@ -541,4 +560,45 @@ mod tests {
         );
         Ok(())
     }
+
+    // a random reduced-input-shaped number with single digit regulars, so the
+    // flat parser can read it too
+    fn random_number(rng: &mut Rng, depth: usize) -> String {
+        if depth > 0 && (depth >= 4 || rng.below(3) == 0) {
+            rng.below(10).to_string()
+        } else {
+            format!(
+                "[{},{}]",
+                random_number(rng, depth + 1),
+                random_number(rng, depth + 1)
+            )
+        }
+    }
+
+    #[test]
+    fn test_tree_matches_flat() -> Result<()> {
+        let mut rng = Rng::new(0x5eed_f00d);
+        for _ in 0..500 {
+            let (a, b) = (random_number(&mut rng, 0), random_number(&mut rng, 0));
+            let tree = a.parse::<SnailNumber>()? + b.parse::<SnailNumber>()?;
+            let flat = add(&parse(&a)?, &parse(&b)?);
+            assert_eq!(tree.to_string(), print(&flat), "{} + {}", a, b);
+            assert_eq!(tree.magnitude() as i32, sum(&flat), "{} + {}", a, b);
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn test_tree_matches_flat_lists() -> Result<()> {
+        let mut rng = Rng::new(0xdead_beef);
+        for _ in 0..50 {
+            let input = (0..2 + rng.below(8))
+                .map(|_| random_number(&mut rng, 0))
+                .collect::<Vec<_>>()
+                .join("\n");
+            let flat = add_list(&parse_list(&input)?);
+            assert_eq!(part1(&input)? as i32, sum(&flat), "{}", input);
+        }
+        Ok(())
+    }
 }
@@ -1,124 +1,38 @@
-use anyhow::{Context, Error, Result};
+use anyhow::{bail, Context, Error, Result};
 use aoc2021::dispatch;
-use itertools::Itertools;
 use std::cmp::Ordering;
 use std::collections::{BinaryHeap, HashMap};
 use std::str::FromStr;
 
-const ROOMS: usize = 4;
-const HALLS: usize = 11;
-
 fn main() -> Result<()> {
     dispatch(part1, part2)
 }
 
+// `A` is 0, and belongs in the leftmost room
 type Amphipod = usize;
-type Row = [Option<usize>; 4];
-
-#[derive(PartialEq, Eq, Clone, Hash, PartialOrd, Ord)]
-struct Position<const LEVELS: usize> {
-    rooms: [[Option<Amphipod>; ROOMS]; LEVELS],
-    hallway: [Option<Amphipod>; HALLS],
-}
-
-impl<const LEVELS: usize> std::fmt::Debug for Position<LEVELS> {
-    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::result::Result<(), std::fmt::Error> {
-        writeln!(f)?;
-        for _ in 0..(2 + HALLS) {
-            write!(f, "#")?;
-        }
-        writeln!(f)?;
-        write!(f, "#")?;
-        for h in self.hallway {
-            if let Some(h) = h {
-                write!(f, "{}", (b'A' + h as u8) as char)?;
-            } else {
-                write!(f, ".")?;
-            }
-        }
-        writeln!(f, "#")?;
-        write!(f, "###")?;
-        for r in self.rooms[0] {
-            if let Some(r) = r {
-                write!(f, "{}#", (b'A' + r as u8) as char)?;
-            } else {
-                write!(f, ".#")?;
-            }
-        }
-        writeln!(f, "##")?;
-
-        for level in 1..LEVELS {
-            write!(f, "  #")?;
-            for r in self.rooms[level] {
-                if let Some(r) = r {
-                    write!(f, "{}#", (b'A' + r as u8) as char)?;
-                } else {
-                    write!(f, ".#")?;
-                }
-            }
-            writeln!(f, " ")?;
-        }
-        writeln!(f, "  #########  ")?;
 
-        Ok(())
-    }
+fn energy(amphipod: Amphipod) -> usize {
+    10_usize.pow(amphipod as u32)
 }
 
-fn above(room: usize) -> usize {
-    assert!(room < 4);
-    2 + room * 2
+fn distance(a: usize, b: usize) -> usize {
+    a.max(b) - a.min(b)
 }
 
-fn room_to_hall(from: usize, to: usize) -> (std::ops::RangeInclusive<usize>, usize) {
-    let above = above(from);
-    if to < above {
-        (to..=above, above - to + 1)
-    } else {
-        (above..=to, to - above + 1)
-    }
+fn amphipod_char(amphipod: Amphipod) -> char {
+    (b'A' + amphipod as u8) as char
 }
 
-fn hall_to_room(from: usize, to: usize) -> (std::ops::RangeInclusive<usize>, usize) {
-    let above = above(to);
-    if from < above {
-        (from..=above, above - from + 1)
-    } else {
-        (above..=from, from - above + 1)
-    }
+#[derive(Debug, PartialEq, Eq, Clone, Hash, PartialOrd, Ord)]
+struct Position {
+    // rooms[level][room], with level 0 just below the hallway
+    rooms: Vec<Vec<Option<Amphipod>>>,
+    hallway: Vec<Option<Amphipod>>,
 }
 
-impl<const LEVELS: usize> Position<LEVELS> {
-    fn num_correct(&self) -> usize {
-        self.rooms
-            .map(|r| r.iter().zip(0..3).filter(|(a, b)| **a == Some(*b)).count())
-            .iter()
-            .sum()
-    }
-
-    fn room_to_hall_len(&self, from: usize, to: usize) -> Option<usize> {
-        let (path, path_len) = room_to_hall(from, to);
-        if path.into_iter().all(|h| self.hallway[h].is_none()) {
-            return Some(path_len);
-        }
-        None
-    }
-
-    fn hall_to_room_len(&self, from: usize, to: usize) -> Option<usize> {
-        let (path, path_len) = hall_to_room(from, to);
-        if path
-            .into_iter()
-            .all(|h| h == from || self.hallway[h].is_none())
-        {
-            return Some(path_len);
-        }
-        None
-    }
-
-    fn room_to_room_len(&self, from: usize, to: usize) -> Option<usize> {
-        let above = above(to);
-        self.room_to_hall_len(from, above)
-    }
-
+impl Position {
+    // the level an amphipod would move into, if only amphipods that belong
+    // there are in the room
     fn room_free(&self, room: usize) -> Option<usize> {
         if self
             .rooms
@@ -128,14 +42,13 @@ impl<const LEVELS: usize> Position<LEVELS> {
         {
             return None;
         }
-        return self
-            .rooms
+        self.rooms
             .iter()
             .map(|row| row[room])
             .enumerate()
             .filter(|(_, occupant)| occupant.is_none())
             .map(|(level, _)| level)
-            .last();
+            .next_back()
     }
 
     fn top_occupant(&self, room: usize) -> Option<(usize, Amphipod)> {
@@ -146,240 +59,349 @@ impl<const LEVELS: usize> Position<LEVELS> {
             .find_map(|(level, occupant)| occupant.map(|o| (level, o)))
     }
 
-    fn done(&self, room: usize) -> bool {
-        self.rooms
+    // whether the amphipod at `level` and all those below it belong here
+    fn settled(&self, room: usize, level: usize) -> bool {
+        self.rooms[level..]
             .iter()
-            .map(|row| row[room])
-            .all(|a| a == Some(room))
+            .all(|row| row[room] == Some(room))
+    }
+
+    fn done(&self) -> bool {
+        self.hallway.iter().all(Option::is_none)
+            && (0..self.rooms[0].len()).all(|room| self.settled(room, 0))
+    }
+}
+
+// any diagram: a hallway along one row, and rooms of equal depth hanging
+// below it. amphipods never stop in the hallway right outside a room
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct Burrow {
+    // the diagram with every open cell empty
+    diagram: Vec<Vec<char>>,
+    // (row, column) of each hallway cell, left to right
+    hall: Vec<(usize, usize)>,
+    // (row, column) of each room cell, as rooms[level][room]
+    rooms: Vec<Vec<(usize, usize)>>,
+    // the hallway index right outside each room
+    doors: Vec<usize>,
+}
+
+impl Burrow {
+    fn parse(input: &str) -> Result<(Burrow, Position)> {
+        let mut diagram: Vec<Vec<char>> = input
+            .lines()
+            .filter(|l| !l.trim().is_empty())
+            .map(|l| l.chars().collect())
+            .collect();
+        let is_open = |c: char| c == '.' || c.is_ascii_uppercase();
+        let hall_row = diagram
+            .iter()
+            .position(|row| row.iter().any(|&c| is_open(c)))
+            .context("no hallway")?;
+        let hall: Vec<(usize, usize)> = (0..diagram[hall_row].len())
+            .filter(|&col| is_open(diagram[hall_row][col]))
+            .map(|col| (hall_row, col))
+            .collect();
+
+        let mut columns: Vec<usize> = diagram[hall_row + 1..]
+            .iter()
+            .flat_map(|row| (0..row.len()).filter(move |&col| is_open(row[col])))
+            .collect();
+        columns.sort_unstable();
+        columns.dedup();
+        if columns.is_empty() {
+            bail!("no rooms");
+        }
+        let mut doors = vec![];
+        let mut room_cells = vec![];
+        for &col in &columns {
+            let door = hall
+                .iter()
+                .position(|&(_, c)| c == col)
+                .with_context(|| format!("room at column {} doesn't open onto the hallway", col))?;
+            doors.push(door);
+            let cells: Vec<(usize, usize)> = (hall_row + 1..diagram.len())
+                .take_while(|&row| diagram[row].get(col).is_some_and(|&c| is_open(c)))
+                .map(|row| (row, col))
+                .collect();
+            room_cells.push(cells);
+        }
+        let depth = room_cells[0].len();
+        if room_cells.iter().any(|cells| cells.len() != depth) {
+            bail!("rooms must all be {} deep", depth);
+        }
+        let rooms: Vec<Vec<(usize, usize)>> = (0..depth)
+            .map(|level| room_cells.iter().map(|cells| cells[level]).collect())
+            .collect();
+
+        let mut counts = vec![0; columns.len()];
+        let mut occupant = |(row, col): (usize, usize)| -> Result<Option<Amphipod>> {
+            let c = diagram[row][col];
+            diagram[row][col] = '.';
+            if c == '.' {
+                return Ok(None);
+            }
+            let amphipod = c as usize - 'A' as usize;
+            *counts
+                .get_mut(amphipod)
+                .with_context(|| format!("no room for amphipod `{}`", c))? += 1;
+            Ok(Some(amphipod))
+        };
+        let position = Position {
+            rooms: rooms
+                .iter()
+                .map(|row| row.iter().map(|&cell| occupant(cell)).collect())
+                .collect::<Result<_>>()?,
+            hallway: hall
+                .iter()
+                .map(|&cell| occupant(cell))
+                .collect::<Result<_>>()?,
+        };
+        if let Some(amphipod) = counts.iter().position(|&count| count != depth) {
+            bail!(
+                "expected {} of each amphipod, found {} `{}`",
+                depth,
+                counts[amphipod],
+                amphipod_char(amphipod)
+            );
+        }
+        let burrow = Burrow {
+            diagram,
+            hall,
+            rooms,
+            doors,
+        };
+        Ok((burrow, position))
+    }
+
+    fn is_door(&self, hall: usize) -> bool {
+        self.doors.contains(&hall)
     }
 
-    fn next(&self) -> Vec<(Position<LEVELS>, usize)> {
+    // whether the hallway from `from` to `to` is empty, other than `from`
+    fn clear(&self, position: &Position, from: usize, to: usize) -> bool {
+        let (low, high) = (from.min(to), from.max(to));
+        (low..=high).all(|h| h == from || position.hallway[h].is_none())
+    }
+
+    // each position one move away, with the energy it takes
+    fn moves(&self, position: &Position) -> Vec<(Position, usize)> {
         let mut res = vec![];
 
-        for room in 0..ROOMS {
-            if self.done(room) {
+        for room in 0..self.doors.len() {
+            let (level, amphipod) = match position.top_occupant(room) {
+                Some(top) => top,
+                None => continue,
+            };
+            if position.settled(room, level) {
                 continue;
             }
-            if let Some((level, amphipod)) = self.top_occupant(room) {
-                for hall in 0..HALLS {
-                    if hall == 2 || hall == 4 || hall == 6 || hall == 8 {
-                        continue;
-                    }
-                    if let Some(path_len) = self.room_to_hall_len(room, hall) {
-                        let mut new_state = self.clone();
-                        new_state.rooms[level][room] = None;
-                        new_state.hallway[hall] = Some(amphipod);
-                        let energy = (path_len + level) * 10_usize.pow(amphipod as u32);
-                        res.push((new_state, energy));
-                    }
-                }
-
-                let destination = amphipod;
-                if destination == room {
+            let door = self.doors[room];
+            // stepping into the hallway
+            for hall in 0..self.hall.len() {
+                if self.is_door(hall) || position.hallway[door].is_some() {
                     continue;
-                };
-                if let Some(destination_level) = self.room_free(destination) {
-                    if let Some(path_len) = self.room_to_room_len(room, destination) {
-                        let mut new_state = self.clone();
-                        new_state.rooms[level][room] = None;
-                        new_state.rooms[destination_level][destination] = Some(amphipod);
-                        let energy = (path_len + level + destination_level + 1)
-                            * 10_usize.pow(amphipod as u32);
-                        res.push((new_state, energy));
-                    }
+                }
+                if self.clear(position, door, hall) {
+                    let mut next = position.clone();
+                    next.rooms[level][room] = None;
+                    next.hallway[hall] = Some(amphipod);
+                    let steps = level + 1 + distance(door, hall);
+                    res.push((next, steps * energy(amphipod)));
+                }
+            }
+            // or straight into its own room
+            let destination = self.doors[amphipod];
+            if let Some(destination_level) = position.room_free(amphipod) {
+                if position.hallway[door].is_none() && self.clear(position, door, destination) {
+                    let mut next = position.clone();
+                    next.rooms[level][room] = None;
+                    next.rooms[destination_level][amphipod] = Some(amphipod);
+                    let steps = level + 1 + distance(door, destination) + destination_level + 1;
+                    res.push((next, steps * energy(amphipod)));
                 }
             }
         }
-        for hall in 0..HALLS {
-            if let Some(amphipod) = self.hallway[hall] {
-                let destination = amphipod;
-                if let Some(destination_level) = self.room_free(destination) {
-                    if let Some(path_len) = self.hall_to_room_len(hall, destination) {
-                        let mut new_state = self.clone();
-                        new_state.hallway[hall] = None;
-                        new_state.rooms[destination_level][destination] = Some(amphipod);
-                        let energy = (path_len + destination_level) * 10_usize.pow(amphipod as u32);
-                        res.push((new_state, energy));
+
+        for hall in 0..self.hall.len() {
+            if let Some(amphipod) = position.hallway[hall] {
+                let destination = self.doors[amphipod];
+                if let Some(destination_level) = position.room_free(amphipod) {
+                    if self.clear(position, hall, destination) {
+                        let mut next = position.clone();
+                        next.hallway[hall] = None;
+                        next.rooms[destination_level][amphipod] = Some(amphipod);
+                        let steps = distance(hall, destination) + destination_level + 1;
+                        res.push((next, steps * energy(amphipod)));
                     }
                 }
             }
         }
 
-        res.sort_by_key(|t| t.1);
         res
     }
-}
 
-fn parse_line(s: &str) -> [Option<usize>; 4] {
-    let amphipod_index = |c| match c {
-        'A'..='D' => Some(c as usize - 'A' as usize),
-        _ => None,
-    };
-    [
-        s.chars().nth(3).map(amphipod_index).unwrap(),
-        s.chars().nth(5).map(amphipod_index).unwrap(),
-        s.chars().nth(7).map(amphipod_index).unwrap(),
-        s.chars().nth(9).map(amphipod_index).unwrap(),
-    ]
-}
-
-fn parse_input(s: &str) -> Result<(Row, Row)> {
-    let lines = s.trim().lines().skip(2).take(4);
-    let (top, bottom) = lines.tuple_windows().next().unwrap();
-
-    Ok((parse_line(top), parse_line(bottom)))
-}
+    // a lower bound on the energy still needed: every amphipod walks straight
+    // home ignoring the others, those that have to leave their own room step
+    // out and back, and the ones going into a room fill it from the top down
+    fn estimate(&self, position: &Position) -> usize {
+        let mut cost = 0;
+        let mut entering = vec![0_usize; self.doors.len()];
+        for (hall, occupant) in position.hallway.iter().enumerate() {
+            if let Some(amphipod) = *occupant {
+                cost += (distance(hall, self.doors[amphipod]) + 1) * energy(amphipod);
+                entering[amphipod] += 1;
+            }
+        }
+        for (level, row) in position.rooms.iter().enumerate() {
+            for (room, occupant) in row.iter().enumerate() {
+                if let Some(amphipod) = *occupant {
+                    if position.settled(room, level) {
+                        continue;
+                    }
+                    let across = if amphipod == room {
+                        2
+                    } else {
+                        distance(self.doors[room], self.doors[amphipod])
+                    };
+                    cost += (level + 1 + across + 1) * energy(amphipod);
+                    entering[amphipod] += 1;
+                }
+            }
+        }
+        for (amphipod, &count) in entering.iter().enumerate() {
+            cost += count * count.saturating_sub(1) / 2 * energy(amphipod);
+        }
+        cost
+    }
 
-impl FromStr for Position<2> {
-    type Err = Error;
-    fn from_str(s: &str) -> Result<Self> {
-        let hallway = [None; HALLS];
-        let (rooms_0, rooms_1) = parse_input(s)?;
+    fn render(&self, position: &Position) -> String {
+        let mut diagram = self.diagram.clone();
+        let cells = self.hall.iter().zip(&position.hallway).chain(
+            self.rooms
+                .iter()
+                .flatten()
+                .zip(position.rooms.iter().flatten()),
+        );
+        for (&(row, col), occupant) in cells {
+            if let Some(amphipod) = occupant {
+                diagram[row][col] = amphipod_char(*amphipod);
+            }
+        }
+        diagram
+            .iter()
+            .map(|row| row.iter().collect::<String>() + "\n")
+            .collect()
+    }
 
-        Ok(Position {
-            hallway,
-            rooms: [rooms_0, rooms_1],
-        })
+    // every move of a solution, each with the diagram after it
+    fn describe(&self, solution: &Solution) -> String {
+        let mut res = self.render(&solution.steps[0].0);
+        for (number, (position, energy)) in solution.steps.iter().enumerate().skip(1) {
+            res += &format!("\nmove {}, {} energy so far\n", number, energy);
+            res += &self.render(position);
+        }
+        res
     }
-}
 
-impl FromStr for Position<4> {
-    type Err = Error;
-    fn from_str(s: &str) -> Result<Self> {
-        let hallway = [None; HALLS];
-        let (rooms_0, rooms_3) = parse_input(s)?;
-        // #D#C#B#A#
-        // #D#B#A#C#
-        let rooms_1 = [Some(3), Some(2), Some(1), Some(0)];
-        let rooms_2 = [Some(3), Some(1), Some(0), Some(2)];
-
-        Ok(Position {
-            hallway,
-            rooms: [rooms_0, rooms_1, rooms_2, rooms_3],
-        })
+    fn solve(&self, start: Position) -> Option<Solution> {
+        shortest_path(self, start)
     }
 }
 
-#[allow(dead_code)]
-fn parse4(s: &str) -> Result<Position<4>> {
-    let top = s.trim().lines().nth(1).unwrap();
-
-    let amphipod_index = |c| match c {
-        'A'..='D' => Some(c as usize - 'A' as usize),
-        _ => None,
-    };
-
-    let hallway = [
-        top.chars().nth(1).map(amphipod_index).unwrap(),
-        top.chars().nth(2).map(amphipod_index).unwrap(),
-        top.chars().nth(3).map(amphipod_index).unwrap(),
-        top.chars().nth(4).map(amphipod_index).unwrap(),
-        top.chars().nth(5).map(amphipod_index).unwrap(),
-        top.chars().nth(6).map(amphipod_index).unwrap(),
-        top.chars().nth(7).map(amphipod_index).unwrap(),
-        top.chars().nth(8).map(amphipod_index).unwrap(),
-        top.chars().nth(9).map(amphipod_index).unwrap(),
-        top.chars().nth(10).map(amphipod_index).unwrap(),
-        top.chars().nth(11).map(amphipod_index).unwrap(),
-    ];
-
-    let lines = s.trim().lines().skip(2).take(4);
-    let (r0, r1, r2, r3) = lines.tuple_windows().next().unwrap();
-
-    Ok(Position {
-        hallway,
-        rooms: [
-            parse_line(r0),
-            parse_line(r1),
-            parse_line(r2),
-            parse_line(r3),
-        ],
-    })
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct Solution {
+    energy: usize,
+    // every position from the start to the end, with the energy spent
+    // getting there
+    steps: Vec<(Position, usize)>,
 }
 
-// https://doc.rust-lang.org/std/collections/binary_heap/index.html
-
 #[derive(Clone, Eq, PartialEq)]
-struct State<const LEVELS: usize> {
-    position: Position<LEVELS>,
+struct State {
+    // the energy spent, plus the estimate of what's left
+    priority: usize,
     cost: usize,
+    position: Position,
 }
+
 // The priority queue depends on `Ord`.
 // Explicitly implement the trait so the queue becomes a min-heap
 // instead of a max-heap.
-impl<const LEVELS: usize> Ord for State<LEVELS> {
+impl Ord for State {
     fn cmp(&self, other: &Self) -> Ordering {
-        // Notice that the we flip the ordering on costs.
+        // Notice that the we flip the ordering on priorities.
         // In case of a tie we compare positions - this step is necessary
         // to make implementations of `PartialEq` and `Ord` consistent.
-        other.cost.cmp(&self.cost).then_with(|| {
-            (self.position.num_correct(), &self.position)
-                .cmp(&(other.position.num_correct(), &other.position))
-        })
+        other
+            .priority
+            .cmp(&self.priority)
+            .then_with(|| self.cost.cmp(&other.cost))
+            .then_with(|| self.position.cmp(&other.position))
     }
 }
 
 // `PartialOrd` needs to be implemented as well.
-impl<const LEVELS: usize> PartialOrd for State<LEVELS> {
+impl PartialOrd for State {
     fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
         Some(self.cmp(other))
     }
 }
 
-// Dijkstra's shortest path algorithm.
-
-// Start at `start` and use `dist` to track the current shortest distance
-// to each node. This implementation isn't memory-efficient as it may leave duplicate
-// nodes in the queue. It also uses `usize::MAX` as a sentinel value,
-// for a simpler implementation.
-fn shortest_path<const LEVELS: usize>(
-    start: Position<LEVELS>,
-    goal: Position<LEVELS>,
-) -> Option<usize> {
-    // dist[node] = current shortest distance from `start` to `node`
-    let mut dist = HashMap::new();
+// A*: Dijkstra's shortest path, but visiting positions in order of the
+// energy spent plus a lower bound on the energy still to spend. As the bound
+// never overestimates, the first time the goal comes off the heap it's been
+// reached the cheapest way.
+fn shortest_path(burrow: &Burrow, start: Position) -> Option<Solution> {
+    // dist[node] = current lowest energy from `start` to `node`, and the
+    // position it came from
+    let mut dist: HashMap<Position, (usize, Option<Position>)> = HashMap::new();
 
     let mut heap = BinaryHeap::new();
 
-    // We're at `start`, with a zero cost
-    dist.insert(start.clone(), 0);
+    dist.insert(start.clone(), (0, None));
     heap.push(State {
-        position: start,
+        priority: burrow.estimate(&start),
         cost: 0,
+        position: start,
     });
 
-    // Examine the frontier with lower cost nodes first (min-heap)
-    while let Some(State { cost, position }) = heap.pop() {
-        // dbg!(cost, &position);
-        // Alternatively we could have continued to find all shortest paths
-        if position == goal {
-            return Some(cost);
+    while let Some(State { cost, position, .. }) = heap.pop() {
+        if position.done() {
+            let mut steps = vec![];
+            let mut current = Some(position);
+            while let Some(position) = current {
+                let (cost, previous) = dist[&position].clone();
+                steps.push((position, cost));
+                current = previous;
+            }
+            steps.reverse();
+            return Some(Solution {
+                energy: cost,
+                steps,
+            });
         }
 
         // Important as we may have already found a better way
-        if let Some(&dist) = dist.get(&position) {
+        if let Some(&(dist, _)) = dist.get(&position) {
             if cost > dist {
                 continue;
             }
         }
 
-        // For each node we can reach, see if we can find a way with
-        // a lower cost going through this node
-        for (next, edge_cost) in position.next() {
-            let next = State {
-                cost: cost + edge_cost,
-                position: next,
-            };
-
-            let better = match dist.get(&next.position) {
+        for (next, edge_cost) in burrow.moves(&position) {
+            let next_cost = cost + edge_cost;
+            let better = match dist.get(&next) {
                 None => true,
-                Some(&dist) => next.cost < dist,
+                Some(&(dist, _)) => next_cost < dist,
             };
-            // If so, add it to the frontier and continue
             if better {
-                heap.push(next.clone());
-                // Relaxation, we have now found a better way
-                dist.insert(next.position, next.cost);
+                dist.insert(next.clone(), (next_cost, Some(position.clone())));
+                heap.push(State {
+                    priority: next_cost + burrow.estimate(&next),
+                    cost: next_cost,
+                    position: next,
+                });
             }
         }
     }
@@ -388,23 +410,38 @@ fn shortest_path<const LEVELS: usize>(
     None
 }
 
+impl FromStr for Burrow {
+    type Err = Error;
+    fn from_str(s: &str) -> Result<Self> {
+        Ok(Burrow::parse(s)?.0)
+    }
+}
+
+// prints every move of the solution, drawn out, to stderr, so the answer on
+// stdout stays on its own
+fn solve(input: &str) -> Result<usize> {
+    let (burrow, start) = Burrow::parse(input)?;
+    let solution = burrow
+        .solve(start)
+        .context("no way to sort the amphipods")?;
+    eprint!("{}", burrow.describe(&solution));
+    Ok(solution.energy)
+}
+
+// the folded-out part of the diagram, below the first row of the rooms
+fn unfold(input: &str) -> String {
+    let mut lines: Vec<&str> = input.lines().filter(|l| !l.trim().is_empty()).collect();
+    let at = 3.min(lines.len());
+    lines.splice(at..at, ["  #D#C#B#A#", "  #D#B#A#C#"]);
+    lines.join("\n")
+}
+
 fn part1(input: &str) -> Result<usize> {
-    let state: Position<2> = input.parse()?;
-    let goal = Position {
-        hallway: [None; HALLS],
-        rooms: [[Some(0), Some(1), Some(2), Some(3)]; 2],
-    };
-    shortest_path(state, goal).context("no path")
+    solve(input)
 }
 
 fn part2(input: &str) -> Result<usize> {
-    let state: Position<4> = input.parse()?;
-    let goal = Position {
-        hallway: [None; HALLS],
-        rooms: [[Some(0), Some(1), Some(2), Some(3)]; 4],
-    };
-
-    shortest_path(state, goal).context("no path")
+    solve(&unfold(input))
 }
 
 #[cfg(test)]
@@ -417,25 +454,46 @@ mod tests {
   #A#D#C#A#
   #########";
 
+    fn burrow() -> Burrow {
+        TEST_INPUT.parse().unwrap()
+    }
+
     #[test]
     fn test_parse() -> Result<()> {
-        let state: Position<2> = TEST_INPUT.parse()?;
+        let (burrow, state) = Burrow::parse(TEST_INPUT)?;
         assert_eq!(
             state,
             Position {
-                hallway: [None; HALLS],
-                rooms: [
-                    [Some(1), Some(2), Some(1), Some(3)],
-                    [Some(0), Some(3), Some(2), Some(0)]
+                hallway: vec![None; 11],
+                rooms: vec![
+                    vec![Some(1), Some(2), Some(1), Some(3)],
+                    vec![Some(0), Some(3), Some(2), Some(0)]
                 ],
             }
         );
+        assert_eq!(burrow.doors, vec![2, 4, 6, 8]);
+        assert_eq!(burrow.render(&state), TEST_INPUT.to_string() + "\n");
+
+        let err = |s: &str| Burrow::parse(s).unwrap_err().to_string();
+        assert_eq!(
+            err("#######\n#.....#\n###B#B#\n  #####"),
+            "expected 1 of each amphipod, found 0 `A`"
+        );
+        assert_eq!(
+            err("#######\n#.....#\n###B#A#\n  #.###\n  ###"),
+            "rooms must all be 2 deep"
+        );
+        assert_eq!(
+            err("#######\n#.....#\n###C#A#\n  #####"),
+            "no room for amphipod `C`"
+        );
         Ok(())
     }
 
     #[test]
     fn test_move2() -> Result<()> {
-        let mut hallway = [None; HALLS];
+        let burrow = burrow();
+        let mut hallway = vec![None; 11];
         hallway[3] = Some(1);
         // #############
         // #...B.......#
@@ -444,9 +502,9 @@ mod tests {
         //   #########
         let state = Position {
             hallway,
-            rooms: [
-                [Some(1), None, Some(2), Some(3)],
-                [Some(0), Some(3), Some(2), Some(0)],
+            rooms: vec![
+                vec![Some(1), None, Some(2), Some(3)],
+                vec![Some(0), Some(3), Some(2), Some(0)],
             ],
         };
 
@@ -455,18 +513,18 @@ mod tests {
         // ###B#.#C#D###
         //   #A#.#C#A#
         //   #########
-        let mut hallway = [None; HALLS];
+        let mut hallway = vec![None; 11];
         hallway[3] = Some(1);
         hallway[5] = Some(3);
         let mid = Position {
             hallway,
-            rooms: [
-                [Some(1), None, Some(2), Some(3)],
-                [Some(0), None, Some(2), Some(0)],
+            rooms: vec![
+                vec![Some(1), None, Some(2), Some(3)],
+                vec![Some(0), None, Some(2), Some(0)],
             ],
         };
 
-        if let Some((_p, cost)) = state.next().iter().find(|&p| p.0 == mid) {
+        if let Some((_p, cost)) = burrow.moves(&state).iter().find(|&p| p.0 == mid) {
             assert_eq!(*cost, 3000);
         } else {
             panic!();
@@ -477,37 +535,34 @@ mod tests {
         // ###B#.#C#D###
         //   #A#B#C#A#
         //   #########
-        let mut hallway = [None; HALLS];
+        let mut hallway = vec![None; 11];
         hallway[5] = Some(3);
         let target = Position {
             hallway,
-            rooms: [
-                [Some(1), None, Some(2), Some(3)],
-                [Some(0), Some(1), Some(2), Some(0)],
+            rooms: vec![
+                vec![Some(1), None, Some(2), Some(3)],
+                vec![Some(0), Some(1), Some(2), Some(0)],
             ],
         };
-        assert!(mid.next().iter().find(|&p| p.0 == target).is_some());
-        if let Some((_p, cost)) = mid.next().iter().find(|&p| p.0 == target) {
+        if let Some((_p, cost)) = burrow.moves(&mid).iter().find(|&p| p.0 == target) {
             assert_eq!(*cost, 30);
         } else {
             panic!();
         }
 
-        // assert!(false);
-
         // #############
         // #...B.......#
         // ###B#C#.#D###
         //   #A#D#C#A#
         //   #########
 
-        let mut hallway = [None; HALLS];
+        let mut hallway = vec![None; 11];
         hallway[3] = Some(1);
         let before = Position {
             hallway,
-            rooms: [
-                [Some(1), Some(2), None, Some(3)],
-                [Some(0), Some(3), Some(2), Some(0)],
+            rooms: vec![
+                vec![Some(1), Some(2), None, Some(3)],
+                vec![Some(0), Some(3), Some(2), Some(0)],
             ],
         };
 
@@ -516,31 +571,38 @@ mod tests {
         // ###B#.#C#D###
         //   #A#D#C#A#
         //   #########
-        let mut hallway = [None; HALLS];
+        let mut hallway = vec![None; 11];
         hallway[3] = Some(1);
         let after = Position {
             hallway,
-            rooms: [
-                [Some(1), None, Some(2), Some(3)],
-                [Some(0), Some(3), Some(2), Some(0)],
+            rooms: vec![
+                vec![Some(1), None, Some(2), Some(3)],
+                vec![Some(0), Some(3), Some(2), Some(0)],
             ],
         };
-        if let Some((_p, cost)) = before.next().iter().find(|&p| p.0 == after) {
+        if let Some((_p, cost)) = burrow.moves(&before).iter().find(|&p| p.0 == after) {
             assert_eq!(*cost, 400);
         } else {
             panic!();
         }
 
+        // nothing stops right outside a room
+        for (next, _) in burrow.moves(&before) {
+            for &door in &burrow.doors {
+                assert_eq!(next.hallway[door], None);
+            }
+        }
+
         Ok(())
     }
 
     #[test]
     fn test_room_free_for() -> Result<()> {
         let state = Position {
-            hallway: [None; HALLS],
-            rooms: [
-                [None, None, Some(2), None],
-                [None, Some(1), Some(2), Some(0)],
+            hallway: vec![None; 11],
+            rooms: vec![
+                vec![None, None, Some(2), None],
+                vec![None, Some(1), Some(2), Some(0)],
             ],
         };
         assert_eq!(state.room_free(0), Some(1));
@@ -553,10 +615,10 @@ mod tests {
     #[test]
     fn test_top_occupant() -> Result<()> {
         let state = Position {
-            hallway: [None; HALLS],
-            rooms: [
-                [None, None, Some(1), None],
-                [None, Some(2), Some(1), Some(4)],
+            hallway: vec![None; 11],
+            rooms: vec![
+                vec![None, None, Some(1), None],
+                vec![None, Some(2), Some(1), Some(4)],
             ],
         };
         assert_eq!(state.top_occupant(0), None);
@@ -566,6 +628,68 @@ mod tests {
         Ok(())
     }
 
+    #[test]
+    fn test_estimate() -> Result<()> {
+        let (burrow, start) = Burrow::parse(TEST_INPUT)?;
+        let solution = burrow.solve(start).context("no solution")?;
+        for (position, spent) in &solution.steps {
+            assert!(burrow.estimate(position) <= solution.energy - spent);
+        }
+        assert_eq!(burrow.estimate(&solution.steps.last().unwrap().0), 0);
+        Ok(())
+    }
+
+    #[test]
+    fn test_solution() -> Result<()> {
+        let (burrow, start) = Burrow::parse(TEST_INPUT)?;
+        let solution = burrow.solve(start.clone()).context("no solution")?;
+        assert_eq!(solution.energy, 12521);
+        assert_eq!(solution.steps[0], (start, 0));
+        for pair in solution.steps.windows(2) {
+            let (from, spent) = &pair[0];
+            let (to, total) = &pair[1];
+            assert!(burrow.moves(from).contains(&(to.clone(), total - spent)));
+        }
+        let description = burrow.describe(&solution);
+        assert!(description.ends_with(&format!(
+            "move {}, 12521 energy so far
+#############
+#...........#
+###A#B#C#D###
+  #A#B#C#D#
+  #########
+",
+            solution.steps.len() - 1
+        )));
+        Ok(())
+    }
+
+    #[test]
+    fn test_other_layouts() -> Result<()> {
+        // two rooms, one deep: A waits past B's room so B can cross straight over
+        let (burrow, start) = Burrow::parse(
+            "#######
+#.....#
+###B#A#
+  #####",
+        )?;
+        assert_eq!(burrow.solve(start).context("no solution")?.energy, 46);
+
+        // three rooms, three deep, with one already waiting in the hallway
+        let (burrow, start) = Burrow::parse(
+            "###########
+#.A.......#
+###B#C#A###
+  #C#B#A#
+  #B#C#.#
+  #######",
+        )?;
+        let solution = burrow.solve(start).context("no solution")?;
+        assert!(solution.steps.last().unwrap().0.done());
+        assert_eq!(solve("###\n#.#\n#A#\n###")?, 0);
+        Ok(())
+    }
+
     #[test]
     fn test_part1() -> Result<()> {
         assert_eq!(part1(TEST_INPUT)?, 12521);
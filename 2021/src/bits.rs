@@ -0,0 +1,541 @@
+// the BITS packet format: decoding from and encoding to hex, evaluation, and
+// a textual s-expression form for writing packets by hand
+use anyhow::{bail, Context, Error, Result};
+use std::fmt;
+use std::iter::Peekable;
+use std::str::FromStr;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Op {
+    Sum,
+    Product,
+    Min,
+    Max,
+    GreaterThan,
+    LessThan,
+    EqualTo,
+}
+
+const OPS: [Op; 7] = [
+    Op::Sum,
+    Op::Product,
+    Op::Min,
+    Op::Max,
+    Op::GreaterThan,
+    Op::LessThan,
+    Op::EqualTo,
+];
+
+const LITERAL_TYPE_ID: usize = 4;
+
+impl Op {
+    fn type_id(self) -> usize {
+        match self {
+            Op::Sum => 0,
+            Op::Product => 1,
+            Op::Min => 2,
+            Op::Max => 3,
+            Op::GreaterThan => 5,
+            Op::LessThan => 6,
+            Op::EqualTo => 7,
+        }
+    }
+
+    fn name(self) -> &'static str {
+        match self {
+            Op::Sum => "sum",
+            Op::Product => "product",
+            Op::Min => "min",
+            Op::Max => "max",
+            Op::GreaterThan => "gt",
+            Op::LessThan => "lt",
+            Op::EqualTo => "eq",
+        }
+    }
+}
+
+impl TryFrom<usize> for Op {
+    type Error = Error;
+    fn try_from(n: usize) -> Result<Op> {
+        OPS.iter()
+            .find(|op| op.type_id() == n)
+            .copied()
+            .with_context(|| format!("unknown op type {}", n))
+    }
+}
+
+// how an operator packet says where its sub-packets end
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Length {
+    // length type 0: the total number of bits
+    Bits,
+    // length type 1: the number of sub-packets
+    Count,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Value {
+    Literal(usize),
+    Op(Op, Length, Vec<Packet>),
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Packet {
+    pub version: usize,
+    pub value: Value,
+}
+
+impl Packet {
+    pub fn literal(version: usize, value: usize) -> Self {
+        Packet {
+            version,
+            value: Value::Literal(value),
+        }
+    }
+
+    pub fn op(version: usize, op: Op, length: Length, packets: Vec<Packet>) -> Self {
+        Packet {
+            version,
+            value: Value::Op(op, length, packets),
+        }
+    }
+
+    pub fn version_sum(&self) -> usize {
+        self.version
+            + match &self.value {
+                Value::Literal(_) => 0,
+                Value::Op(_, _, packets) => packets.iter().map(|p| p.version_sum()).sum(),
+            }
+    }
+
+    pub fn value(&self) -> Result<usize> {
+        let (op, packets) = match &self.value {
+            Value::Literal(val) => return Ok(*val),
+            Value::Op(op, _, packets) => (op, packets),
+        };
+        let values = packets
+            .iter()
+            .map(Packet::value)
+            .collect::<Result<Vec<_>>>()?;
+        let pair = || match values.as_slice() {
+            [a, b] => Ok((*a, *b)),
+            _ => bail!("{} needs 2 sub-packets, got {}", op.name(), values.len()),
+        };
+        Ok(match op {
+            Op::Sum => values.iter().sum(),
+            Op::Product => values.iter().product(),
+            Op::Min => *values.iter().min().context("no packets for min")?,
+            Op::Max => *values.iter().max().context("no packets for max")?,
+            Op::GreaterThan => pair().map(|(a, b)| (a > b) as usize)?,
+            Op::LessThan => pair().map(|(a, b)| (a < b) as usize)?,
+            Op::EqualTo => pair().map(|(a, b)| (a == b) as usize)?,
+        })
+    }
+
+    pub fn decode(hex: &str) -> Result<Packet> {
+        let bits = hex
+            .trim()
+            .chars()
+            .map(hex_to_bits)
+            .collect::<Result<Vec<_>>>()?
+            .into_iter()
+            .flatten()
+            .collect();
+        let mut stream = BitStream::new(bits);
+        let packet = stream.packet()?;
+        if stream.remaining().iter().any(|&bit| bit != 0) {
+            bail!("trailing data after packet");
+        }
+        Ok(packet)
+    }
+
+    // literals use as few groups as possible, and the result is padded with
+    // zeros to a whole number of hex digits
+    pub fn encode(&self) -> Result<String> {
+        let mut bits = vec![];
+        self.write(&mut bits)?;
+        while bits.len() % 4 != 0 {
+            bits.push(0);
+        }
+        Ok(bits
+            .chunks(4)
+            .map(|nibble| {
+                std::char::from_digit(to_number(nibble) as u32, 16)
+                    .unwrap()
+                    .to_ascii_uppercase()
+            })
+            .collect())
+    }
+
+    fn write(&self, bits: &mut Vec<u8>) -> Result<()> {
+        push_number(bits, self.version, 3).context("version too large")?;
+        match &self.value {
+            Value::Literal(value) => {
+                push_number(bits, LITERAL_TYPE_ID, 3)?;
+                let mut groups = vec![];
+                let mut rest = *value;
+                loop {
+                    groups.push(rest & 0xf);
+                    rest >>= 4;
+                    if rest == 0 {
+                        break;
+                    }
+                }
+                for (index, group) in groups.iter().enumerate().rev() {
+                    bits.push((index > 0) as u8);
+                    push_number(bits, *group, 4)?;
+                }
+            }
+            Value::Op(op, length, packets) => {
+                push_number(bits, op.type_id(), 3)?;
+                let mut sub_bits = vec![];
+                for packet in packets {
+                    packet.write(&mut sub_bits)?;
+                }
+                match length {
+                    Length::Bits => {
+                        bits.push(0);
+                        push_number(bits, sub_bits.len(), 15).context("sub-packets too long")?;
+                    }
+                    Length::Count => {
+                        bits.push(1);
+                        push_number(bits, packets.len(), 11).context("too many sub-packets")?;
+                    }
+                }
+                bits.extend(sub_bits);
+            }
+        }
+        Ok(())
+    }
+
+    // the s-expression form, with one sub-packet per line
+    pub fn pretty(&self) -> String {
+        let mut out = String::new();
+        self.pretty_into(&mut out, 0);
+        out
+    }
+
+    fn pretty_into(&self, out: &mut String, indent: usize) {
+        match &self.value {
+            Value::Literal(value) => out.push_str(&format!("(lit v{} {})", self.version, value)),
+            Value::Op(op, length, packets) => {
+                out.push_str(&format!(
+                    "({} v{} {}",
+                    op.name(),
+                    self.version,
+                    length_name(*length)
+                ));
+                for packet in packets {
+                    out.push('\n');
+                    out.push_str(&" ".repeat(indent + 2));
+                    packet.pretty_into(out, indent + 2);
+                }
+                out.push(')');
+            }
+        }
+    }
+}
+
+fn length_name(length: Length) -> &'static str {
+    match length {
+        Length::Bits => "bits",
+        Length::Count => "count",
+    }
+}
+
+fn push_number(bits: &mut Vec<u8>, value: usize, width: usize) -> Result<()> {
+    if width < usize::BITS as usize && value >> width != 0 {
+        bail!("{} doesn't fit in {} bits", value, width);
+    }
+    bits.extend((0..width).rev().map(|shift| (value >> shift & 1) as u8));
+    Ok(())
+}
+
+fn hex_to_bits(c: char) -> Result<Vec<u8>> {
+    let decimal = c
+        .to_digit(16)
+        .with_context(|| format!("invalid hex digit `{}`", c))? as u8;
+    Ok(vec![
+        decimal >> 3 & 1,
+        decimal >> 2 & 1,
+        decimal >> 1 & 1,
+        decimal & 1,
+    ])
+}
+
+fn to_number<'a>(bits: impl IntoIterator<Item = &'a u8>) -> usize {
+    bits.into_iter().fold(0, |acc, d| acc * 2 + *d as usize)
+}
+
+struct BitStream {
+    data: Vec<u8>,
+    pos: usize,
+}
+
+impl BitStream {
+    fn new(data: Vec<u8>) -> Self {
+        Self { data, pos: 0 }
+    }
+
+    fn read(&mut self, n: usize) -> Result<&[u8]> {
+        if self.pos + n > self.data.len() {
+            bail!("unexpected end of packet at bit {}", self.data.len());
+        }
+        let res = &self.data[self.pos..self.pos + n];
+        self.pos += n;
+        Ok(res)
+    }
+
+    fn number(&mut self, n: usize) -> Result<usize> {
+        Ok(to_number(self.read(n)?))
+    }
+
+    fn remaining(&self) -> &[u8] {
+        &self.data[self.pos..]
+    }
+
+    fn packet(&mut self) -> Result<Packet> {
+        let version = self.number(3)?;
+        let type_id = self.number(3)?;
+        let value = if type_id == LITERAL_TYPE_ID {
+            let mut value: usize = 0;
+            loop {
+                let chunk = self.read(5)?;
+                let more = chunk[0] == 1;
+                value =
+                    value.checked_mul(16).context("literal too large")? + to_number(&chunk[1..]);
+                if !more {
+                    break Value::Literal(value);
+                }
+            }
+        } else {
+            let op = Op::try_from(type_id)?;
+            let mut packets = vec![];
+            if self.read(1)?[0] == 0 {
+                let sub_bits = self.number(15)?;
+                let end = self.pos + sub_bits;
+                while self.pos < end {
+                    packets.push(self.packet()?);
+                }
+                if self.pos != end {
+                    bail!("sub-packets overran their length");
+                }
+                Value::Op(op, Length::Bits, packets)
+            } else {
+                let count = self.number(11)?;
+                while packets.len() < count {
+                    packets.push(self.packet()?);
+                }
+                Value::Op(op, Length::Count, packets)
+            }
+        };
+        Ok(Packet { version, value })
+    }
+}
+
+impl fmt::Display for Packet {
+    // the s-expression form on one line
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match &self.value {
+            Value::Literal(value) => write!(f, "(lit v{} {})", self.version, value),
+            Value::Op(op, length, packets) => {
+                write!(
+                    f,
+                    "({} v{} {}",
+                    op.name(),
+                    self.version,
+                    length_name(*length)
+                )?;
+                for packet in packets {
+                    write!(f, " {}", packet)?;
+                }
+                write!(f, ")")
+            }
+        }
+    }
+}
+
+#[derive(Debug, PartialEq)]
+enum Token<'a> {
+    Open,
+    Close,
+    Atom(&'a str),
+}
+
+fn tokenize(s: &str) -> Vec<Token<'_>> {
+    let mut tokens = vec![];
+    let mut start = None;
+    for (index, c) in s.char_indices() {
+        if c == '(' || c == ')' || c.is_whitespace() {
+            if let Some(start) = start.take() {
+                tokens.push(Token::Atom(&s[start..index]));
+            }
+            match c {
+                '(' => tokens.push(Token::Open),
+                ')' => tokens.push(Token::Close),
+                _ => {}
+            }
+        } else if start.is_none() {
+            start = Some(index);
+        }
+    }
+    if let Some(start) = start {
+        tokens.push(Token::Atom(&s[start..]));
+    }
+    tokens
+}
+
+type Tokens<'a> = Peekable<std::vec::IntoIter<Token<'a>>>;
+
+fn parse_sexpr(tokens: &mut Tokens<'_>) -> Result<Packet> {
+    match tokens.next() {
+        Some(Token::Open) => {}
+        other => bail!("expected `(`, found {:?}", other),
+    }
+    let name = match tokens.next() {
+        Some(Token::Atom(name)) => name,
+        other => bail!("expected a packet type, found {:?}", other),
+    };
+    let version = match tokens.next() {
+        Some(Token::Atom(version)) => version
+            .strip_prefix('v')
+            .and_then(|v| v.parse().ok())
+            .with_context(|| format!("invalid version `{}`", version))?,
+        other => bail!("expected a version, found {:?}", other),
+    };
+    if name == "lit" {
+        let value = match tokens.next() {
+            Some(Token::Atom(value)) => value
+                .parse()
+                .with_context(|| format!("invalid literal `{}`", value))?,
+            other => bail!("expected a literal value, found {:?}", other),
+        };
+        return match tokens.next() {
+            Some(Token::Close) => Ok(Packet::literal(version, value)),
+            other => bail!("expected `)`, found {:?}", other),
+        };
+    }
+    let op = *OPS
+        .iter()
+        .find(|op| op.name() == name)
+        .with_context(|| format!("unknown packet type `{}`", name))?;
+    let length = match tokens.next() {
+        Some(Token::Atom("bits")) => Length::Bits,
+        Some(Token::Atom("count")) => Length::Count,
+        other => bail!("expected `bits` or `count`, found {:?}", other),
+    };
+    let mut packets = vec![];
+    loop {
+        match tokens.peek() {
+            Some(Token::Close) => {
+                tokens.next();
+                return Ok(Packet::op(version, op, length, packets));
+            }
+            Some(_) => packets.push(parse_sexpr(tokens)?),
+            None => bail!("expected `)`, found end of input"),
+        }
+    }
+}
+
+impl FromStr for Packet {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        let mut tokens = tokenize(s).into_iter().peekable();
+        let packet = parse_sexpr(&mut tokens)?;
+        if let Some(token) = tokens.next() {
+            bail!("unexpected {:?} after packet", token);
+        }
+        Ok(packet)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::rng::Rng;
+
+    fn random_packet(rng: &mut Rng, depth: usize) -> Packet {
+        let mut below = |n: usize| rng.below(n as u64) as usize;
+        let version = below(8);
+        if depth == 0 || below(3) == 0 {
+            let bits = below(40);
+            return Packet::literal(version, below(usize::MAX) >> (63 - bits));
+        }
+        let op = OPS[below(OPS.len())];
+        let length = if below(2) == 0 {
+            Length::Bits
+        } else {
+            Length::Count
+        };
+        let count = below(4);
+        let packets = (0..count).map(|_| random_packet(rng, depth - 1)).collect();
+        Packet::op(version, op, length, packets)
+    }
+
+    #[test]
+    fn test_decode() -> Result<()> {
+        assert_eq!(Packet::decode("D2FE28")?, Packet::literal(6, 2021));
+        assert_eq!(
+            Packet::decode("38006F45291200")?,
+            Packet::op(
+                1,
+                Op::LessThan,
+                Length::Bits,
+                vec![Packet::literal(6, 10), Packet::literal(2, 20)]
+            )
+        );
+        assert_eq!(
+            Packet::decode("EE00D40C823060")?.to_string(),
+            "(max v7 count (lit v2 1) (lit v4 2) (lit v1 3))"
+        );
+        assert!(Packet::decode("D2FE").is_err());
+        assert!(Packet::decode("D2FE2G").is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_encode() -> Result<()> {
+        for hex in &["D2FE28", "38006F45291200", "EE00D40C823060", "C200B40A82"] {
+            let packet = Packet::decode(hex)?;
+            assert_eq!(
+                packet.encode()?.trim_end_matches('0'),
+                hex.trim_end_matches('0')
+            );
+        }
+        assert!(Packet::literal(8, 1).encode().is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_sexpr() -> Result<()> {
+        let packet: Packet =
+            "(sum v1 bits (lit v6 10) (product v0 count (lit v2 3) (lit v3 4)))".parse()?;
+        assert_eq!(packet.value()?, 22);
+        assert_eq!(
+            packet.pretty(),
+            "(sum v1 bits
+  (lit v6 10)
+  (product v0 count
+    (lit v2 3)
+    (lit v3 4)))"
+        );
+        assert!("(lit v1)".parse::<Packet>().is_err());
+        assert!("(foo v1 bits)".parse::<Packet>().is_err());
+        assert!("(sum v1 bits (lit v1 2)".parse::<Packet>().is_err());
+        assert!("(lit v1 2) (lit v1 2)".parse::<Packet>().is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_round_trip() -> Result<()> {
+        let mut rng = Rng::new(0x2021_0016);
+        for _ in 0..500 {
+            let packet = random_packet(&mut rng, 4);
+            assert_eq!(Packet::decode(&packet.encode()?)?, packet);
+            assert_eq!(packet.to_string().parse::<Packet>()?, packet);
+            assert_eq!(packet.pretty().parse::<Packet>()?, packet);
+        }
+        Ok(())
+    }
+}
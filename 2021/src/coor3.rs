@@ -51,6 +51,18 @@ impl Coor3 {
     pub fn manhattan(&self) -> i64 {
         self.x + self.y + self.z
     }
+
+    pub fn dot(&self, other: Coor3) -> i64 {
+        self.x * other.x + self.y * other.y + self.z * other.z
+    }
+
+    pub fn cross(&self, other: Coor3) -> Coor3 {
+        Coor3::new(
+            -self.z * other.y + self.y * other.z,
+            self.z * other.x - self.x * other.z,
+            -self.y * other.x + self.x * other.y,
+        )
+    }
 }
 impl fmt::Debug for Coor3 {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
@@ -1,6 +1,12 @@
+pub mod alu;
+pub mod bits;
 pub mod coor;
 pub mod coor3;
 pub mod cpu;
 // pub mod mod_arith;
+#[cfg(any(test, feature = "test-util"))]
+pub mod rng;
+pub mod scanner;
 pub mod shared;
+pub mod snailfish;
 pub use crate::shared::dispatch;
@@ -0,0 +1,23 @@
+// deterministic xorshift, for tests that generate their own inputs: the same
+// seed always gives the same numbers, so failures can be reproduced. only
+// built for tests, or for other crates' tests with the `test-util` feature
+pub struct Rng(u64);
+
+impl Rng {
+    // the seed must not be 0, which xorshift never leaves
+    pub fn new(seed: u64) -> Self {
+        assert!(seed != 0, "xorshift can't start from 0");
+        Rng(seed)
+    }
+
+    pub fn next_u64(&mut self) -> u64 {
+        self.0 ^= self.0 << 13;
+        self.0 ^= self.0 >> 7;
+        self.0 ^= self.0 << 17;
+        self.0
+    }
+
+    pub fn below(&mut self, n: u64) -> u64 {
+        self.next_u64() % n
+    }
+}
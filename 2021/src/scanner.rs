@@ -0,0 +1,357 @@
+// works out where a set of scanners are, and which way they face, from the
+// beacons they can each see in their own coordinates. two scanners are
+// aligned by finding the rotation and offset that makes enough of their
+// beacons coincide. the distances between a scanner's beacons don't depend
+// on how it's placed, so comparing those first rules out most pairs cheaply
+use crate::coor3::Coor3;
+use anyhow::{bail, Result};
+use std::collections::{HashMap, HashSet, VecDeque};
+
+// a rotation without mirroring, as where it takes the x, y and z axes
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct Rotation {
+    pub x: Coor3,
+    pub y: Coor3,
+    pub z: Coor3,
+}
+
+impl Rotation {
+    pub const IDENTITY: Rotation = Rotation {
+        x: Coor3::new(1, 0, 0),
+        y: Coor3::new(0, 1, 0),
+        z: Coor3::new(0, 0, 1),
+    };
+
+    // the 24 ways a scanner can face: x along any of the 6 axis directions,
+    // y along any of the 4 perpendicular to it, and z to keep it right-handed
+    pub fn all() -> Vec<Rotation> {
+        let units = [
+            Coor3::new(1, 0, 0),
+            Coor3::new(-1, 0, 0),
+            Coor3::new(0, 1, 0),
+            Coor3::new(0, -1, 0),
+            Coor3::new(0, 0, 1),
+            Coor3::new(0, 0, -1),
+        ];
+        let mut res = vec![];
+        for &x in &units {
+            for &y in units.iter().filter(|y| y.dot(x) == 0) {
+                res.push(Rotation {
+                    x,
+                    y,
+                    z: x.cross(y),
+                });
+            }
+        }
+        res
+    }
+
+    pub fn apply(&self, coor: Coor3) -> Coor3 {
+        coor.x * self.x + coor.y * self.y + coor.z * self.z
+    }
+
+    // `inner` first, then this one
+    pub fn compose(&self, inner: Rotation) -> Rotation {
+        Rotation {
+            x: self.apply(inner.x),
+            y: self.apply(inner.y),
+            z: self.apply(inner.z),
+        }
+    }
+
+    pub fn inverse(&self) -> Rotation {
+        let Rotation { x, y, z } = *self;
+        Rotation {
+            x: Coor3::new(x.x, y.x, z.x),
+            y: Coor3::new(x.y, y.y, z.y),
+            z: Coor3::new(x.z, y.z, z.z),
+        }
+    }
+}
+
+// where a scanner is, and which way it faces, relative to some other frame
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Pose {
+    pub position: Coor3,
+    pub rotation: Rotation,
+}
+
+impl Pose {
+    pub const ORIGIN: Pose = Pose {
+        position: Coor3::new(0, 0, 0),
+        rotation: Rotation::IDENTITY,
+    };
+
+    // a point seen by the scanner, in the other frame
+    pub fn apply(&self, coor: Coor3) -> Coor3 {
+        self.rotation.apply(coor) + self.position
+    }
+
+    // `inner` is relative to this pose's scanner
+    pub fn compose(&self, inner: Pose) -> Pose {
+        Pose {
+            position: self.apply(inner.position),
+            rotation: self.rotation.compose(inner.rotation),
+        }
+    }
+}
+
+fn squared_distance(a: Coor3, b: Coor3) -> i64 {
+    let d = a - b;
+    d.dot(d)
+}
+
+// the squared distances between each pair of beacons, with the pairs
+pub fn fingerprint(beacons: &[Coor3]) -> HashMap<i64, Vec<(usize, usize)>> {
+    let mut res: HashMap<i64, Vec<(usize, usize)>> = HashMap::new();
+    for i in 0..beacons.len() {
+        for j in i + 1..beacons.len() {
+            res.entry(squared_distance(beacons[i], beacons[j]))
+                .or_default()
+                .push((i, j));
+        }
+    }
+    res
+}
+
+struct Scan<'a> {
+    beacons: &'a [Coor3],
+    fingerprint: HashMap<i64, Vec<(usize, usize)>>,
+}
+
+impl<'a> Scan<'a> {
+    fn new(beacons: &'a [Coor3]) -> Self {
+        Scan {
+            beacons,
+            fingerprint: fingerprint(beacons),
+        }
+    }
+
+    // `other`'s pose relative to this scanner, if at least `min_overlap`
+    // beacons line up
+    fn align(&self, other: &Scan, min_overlap: usize) -> Option<Pose> {
+        // n shared beacons share n(n-1)/2 distances, and only beacons with
+        // a shared distance can be among them
+        let mut shared_pairs = 0;
+        let mut candidates = HashSet::new();
+        let mut other_candidates = HashSet::new();
+        for (distance, pairs) in &self.fingerprint {
+            if let Some(other_pairs) = other.fingerprint.get(distance) {
+                shared_pairs += pairs.len().min(other_pairs.len());
+                candidates.extend(pairs.iter().flat_map(|&(i, j)| [i, j]));
+                other_candidates.extend(other_pairs.iter().flat_map(|&(i, j)| [i, j]));
+            }
+        }
+        if shared_pairs < min_overlap * min_overlap.saturating_sub(1) / 2 {
+            return None;
+        }
+
+        let beacons: HashSet<Coor3> = self.beacons.iter().copied().collect();
+        for rotation in Rotation::all() {
+            let mut votes: HashMap<Coor3, usize> = HashMap::new();
+            for &i in &candidates {
+                for &j in &other_candidates {
+                    let offset = self.beacons[i] - rotation.apply(other.beacons[j]);
+                    *votes.entry(offset).or_default() += 1;
+                }
+            }
+            for (position, count) in votes {
+                if count < min_overlap {
+                    continue;
+                }
+                let pose = Pose { position, rotation };
+                let overlap = other
+                    .beacons
+                    .iter()
+                    .filter(|&&b| beacons.contains(&pose.apply(b)))
+                    .count();
+                if overlap >= min_overlap {
+                    return Some(pose);
+                }
+            }
+        }
+        None
+    }
+}
+
+// `other`'s pose relative to `reference`, if at least `min_overlap` of their
+// beacons can be made to coincide
+pub fn align(reference: &[Coor3], other: &[Coor3], min_overlap: usize) -> Option<Pose> {
+    Scan::new(reference).align(&Scan::new(other), min_overlap)
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Map {
+    // every scanner's pose relative to the first one
+    pub poses: Vec<Pose>,
+    // all the beacons, relative to the first scanner
+    pub beacons: HashSet<Coor3>,
+}
+
+impl Map {
+    // aligns each scanner with one already placed, starting from the first,
+    // adding its beacons as it goes
+    pub fn build(scans: &[Vec<Coor3>], min_overlap: usize) -> Result<Self> {
+        if scans.is_empty() {
+            bail!("no scanners");
+        }
+        let fingerprinted: Vec<Scan> = scans.iter().map(|s| Scan::new(s)).collect();
+        let mut poses = vec![None; scans.len()];
+        poses[0] = Some(Pose::ORIGIN);
+        let mut beacons: HashSet<Coor3> = scans[0].iter().copied().collect();
+        let mut queue = VecDeque::from(vec![0]);
+        while let Some(placed) = queue.pop_front() {
+            let placed_pose = poses[placed].unwrap();
+            for other in 0..scans.len() {
+                if poses[other].is_some() {
+                    continue;
+                }
+                if let Some(pose) = fingerprinted[placed].align(&fingerprinted[other], min_overlap)
+                {
+                    let pose = placed_pose.compose(pose);
+                    beacons.extend(scans[other].iter().map(|&b| pose.apply(b)));
+                    poses[other] = Some(pose);
+                    queue.push_back(other);
+                }
+            }
+        }
+        let unplaced: Vec<String> = poses
+            .iter()
+            .enumerate()
+            .filter(|(_, p)| p.is_none())
+            .map(|(i, _)| i.to_string())
+            .collect();
+        if !unplaced.is_empty() {
+            bail!(
+                "scanners {} don't overlap the others by {} beacons",
+                unplaced.join(", "),
+                min_overlap
+            );
+        }
+        Ok(Map {
+            poses: poses.into_iter().map(Option::unwrap).collect(),
+            beacons,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::rng::Rng;
+
+    // scanners in a row, each seeing the beacons within 1000 along each axis
+    fn world(positions: &[i64]) -> (Vec<Pose>, Vec<Vec<Coor3>>) {
+        let mut rng = Rng::new(0x2021_1219);
+        let rotations = Rotation::all();
+        let beacons: Vec<Coor3> = (0..300)
+            .map(|_| {
+                Coor3::new(
+                    rng.below(6400) as i64 - 1000,
+                    rng.below(2000) as i64 - 1000,
+                    rng.below(2000) as i64 - 1000,
+                )
+            })
+            .collect();
+        let poses: Vec<Pose> = positions
+            .iter()
+            .map(|&x| Pose {
+                position: Coor3::new(x, 0, 0),
+                rotation: rotations[rng.below(24) as usize],
+            })
+            .collect();
+        let scans = poses
+            .iter()
+            .map(|pose| {
+                let inverse = pose.rotation.inverse();
+                beacons
+                    .iter()
+                    .map(|&b| b - pose.position)
+                    .filter(|d| d.x.abs() <= 1000 && d.y.abs() <= 1000 && d.z.abs() <= 1000)
+                    .map(|d| inverse.apply(d))
+                    .collect()
+            })
+            .collect();
+        (poses, scans)
+    }
+
+    #[test]
+    fn test_rotations() {
+        let all = Rotation::all();
+        assert_eq!(all.len(), 24);
+        assert_eq!(all.iter().collect::<HashSet<_>>().len(), 24);
+        assert!(all.contains(&Rotation::IDENTITY));
+        let point = Coor3::new(1, 2, 3);
+        let images: HashSet<Coor3> = all.iter().map(|r| r.apply(point)).collect();
+        assert_eq!(images.len(), 24);
+        for r in &all {
+            assert_eq!(r.compose(r.inverse()), Rotation::IDENTITY);
+            for s in &all {
+                assert!(all.contains(&r.compose(*s)));
+                assert_eq!(r.compose(*s).apply(point), r.apply(s.apply(point)));
+            }
+        }
+    }
+
+    #[test]
+    fn test_align() {
+        let (poses, scans) = world(&[0, 1100]);
+        let pose = align(&scans[0], &scans[1], 12).unwrap();
+        let expected = Pose {
+            position: poses[0].rotation.inverse().apply(poses[1].position),
+            rotation: poses[0].rotation.inverse().compose(poses[1].rotation),
+        };
+        assert_eq!(pose, expected);
+        // far apart, nothing in common
+        let (_, scans) = world(&[0, 2500]);
+        assert_eq!(align(&scans[0], &scans[1], 12), None);
+    }
+
+    #[test]
+    fn test_min_overlap() {
+        let (_, scans) = world(&[0, 1100]);
+        let shared = align(&scans[0], &scans[1], 3)
+            .map(|pose| {
+                let first: HashSet<Coor3> = scans[0].iter().copied().collect();
+                scans[1]
+                    .iter()
+                    .filter(|&&b| first.contains(&pose.apply(b)))
+                    .count()
+            })
+            .unwrap();
+        assert!(align(&scans[0], &scans[1], shared).is_some());
+        assert_eq!(align(&scans[0], &scans[1], shared + 1), None);
+    }
+
+    #[test]
+    fn test_build() -> Result<()> {
+        // only neighbours overlap, and the first scanner is in the middle
+        let (poses, scans) = world(&[2200, 0, 4400, 1100, 3300]);
+        let map = Map::build(&scans, 12)?;
+        let to_first = poses[0].rotation.inverse();
+        for (pose, expected) in map.poses.iter().zip(&poses) {
+            assert_eq!(
+                pose.position,
+                to_first.apply(expected.position - poses[0].position)
+            );
+            assert_eq!(pose.rotation, to_first.compose(expected.rotation));
+        }
+        let first = poses[0].position;
+        let seen: HashSet<Coor3> = scans
+            .iter()
+            .zip(&poses)
+            .flat_map(|(scan, pose)| {
+                scan.iter()
+                    .map(move |&b| to_first.apply(pose.apply(b) - first))
+            })
+            .collect();
+        assert_eq!(map.beacons, seen);
+
+        let (_, scans) = world(&[0, 1100, 5000]);
+        assert_eq!(
+            Map::build(&scans, 12).unwrap_err().to_string(),
+            "scanners 2 don't overlap the others by 12 beacons"
+        );
+        Ok(())
+    }
+}
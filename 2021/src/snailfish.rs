@@ -0,0 +1,277 @@
+use anyhow::{bail, Context, Error, Result};
+use std::fmt;
+use std::ops::Add;
+use std::str::FromStr;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum SnailNumber {
+    Regular(u32),
+    Pair(Box<SnailNumber>, Box<SnailNumber>),
+}
+
+use SnailNumber::*;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Action {
+    Explode,
+    Split,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Step {
+    pub action: Action,
+    // the number after the action
+    pub number: SnailNumber,
+}
+
+impl SnailNumber {
+    pub fn pair(left: SnailNumber, right: SnailNumber) -> Self {
+        Pair(Box::new(left), Box::new(right))
+    }
+
+    pub fn magnitude(&self) -> u32 {
+        match self {
+            Regular(n) => *n,
+            Pair(left, right) => 3 * left.magnitude() + 2 * right.magnitude(),
+        }
+    }
+
+    // the single reduction actions, in order, until the number is reduced
+    pub fn reduction(self) -> Reduction {
+        Reduction { number: self }
+    }
+
+    pub fn reduce(self) -> SnailNumber {
+        let mut number = self;
+        while number.reduce_step().is_some() {}
+        number
+    }
+
+    fn reduce_step(&mut self) -> Option<Action> {
+        if self.explode(0).is_some() {
+            Some(Action::Explode)
+        } else if self.split() {
+            Some(Action::Split)
+        } else {
+            None
+        }
+    }
+
+    fn add_leftmost(&mut self, value: u32) {
+        match self {
+            Regular(n) => *n += value,
+            Pair(left, _) => left.add_leftmost(value),
+        }
+    }
+
+    fn add_rightmost(&mut self, value: u32) {
+        match self {
+            Regular(n) => *n += value,
+            Pair(_, right) => right.add_rightmost(value),
+        }
+    }
+
+    // explodes the leftmost pair nested inside four others. returns the values
+    // still to be added to the nearest regular numbers on either side
+    fn explode(&mut self, depth: usize) -> Option<(Option<u32>, Option<u32>)> {
+        let (left, right) = match self {
+            Regular(_) => return None,
+            Pair(left, right) => (left, right),
+        };
+        if depth >= 4 {
+            if let (Regular(l), Regular(r)) = (&**left, &**right) {
+                let carry = (Some(*l), Some(*r));
+                *self = Regular(0);
+                return Some(carry);
+            }
+        }
+        if let Some((carry_left, carry_right)) = left.explode(depth + 1) {
+            if let Some(value) = carry_right {
+                right.add_leftmost(value);
+            }
+            return Some((carry_left, None));
+        }
+        if let Some((carry_left, carry_right)) = right.explode(depth + 1) {
+            if let Some(value) = carry_left {
+                left.add_rightmost(value);
+            }
+            return Some((None, carry_right));
+        }
+        None
+    }
+
+    // splits the leftmost regular number of 10 or more
+    fn split(&mut self) -> bool {
+        match self {
+            Regular(n) if *n >= 10 => {
+                *self = SnailNumber::pair(Regular(*n / 2), Regular(n.div_ceil(2)));
+                true
+            }
+            Regular(_) => false,
+            Pair(left, right) => left.split() || right.split(),
+        }
+    }
+}
+
+pub struct Reduction {
+    number: SnailNumber,
+}
+
+impl Iterator for Reduction {
+    type Item = Step;
+
+    fn next(&mut self) -> Option<Step> {
+        let action = self.number.reduce_step()?;
+        Some(Step {
+            action,
+            number: self.number.clone(),
+        })
+    }
+}
+
+impl Add for SnailNumber {
+    type Output = SnailNumber;
+
+    fn add(self, other: SnailNumber) -> SnailNumber {
+        SnailNumber::pair(self, other).reduce()
+    }
+}
+
+impl fmt::Display for SnailNumber {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Regular(n) => write!(f, "{}", n),
+            Pair(left, right) => write!(f, "[{},{}]", left, right),
+        }
+    }
+}
+
+struct Parser<'a> {
+    bytes: &'a [u8],
+    pos: usize,
+}
+
+impl Parser<'_> {
+    fn expect(&mut self, expected: u8) -> Result<()> {
+        match self.bytes.get(self.pos) {
+            Some(&b) if b == expected => {
+                self.pos += 1;
+                Ok(())
+            }
+            Some(&b) => bail!(
+                "expected `{}` at column {}, found `{}`",
+                expected as char,
+                self.pos + 1,
+                b as char
+            ),
+            None => bail!("expected `{}` at end of input", expected as char),
+        }
+    }
+
+    fn number(&mut self) -> Result<SnailNumber> {
+        if self.bytes.get(self.pos) == Some(&b'[') {
+            self.pos += 1;
+            let left = self.number()?;
+            self.expect(b',')?;
+            let right = self.number()?;
+            self.expect(b']')?;
+            return Ok(SnailNumber::pair(left, right));
+        }
+        let start = self.pos;
+        while self.bytes.get(self.pos).is_some_and(u8::is_ascii_digit) {
+            self.pos += 1;
+        }
+        if start == self.pos {
+            bail!("expected a number or `[` at column {}", start + 1);
+        }
+        let digits = std::str::from_utf8(&self.bytes[start..self.pos])?;
+        Ok(Regular(digits.parse().with_context(|| {
+            format!("number too large at column {}", start + 1)
+        })?))
+    }
+}
+
+impl FromStr for SnailNumber {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        let s = s.trim();
+        let mut parser = Parser {
+            bytes: s.as_bytes(),
+            pos: 0,
+        };
+        let number = parser.number()?;
+        if parser.pos != s.len() {
+            bail!("unexpected input at column {}", parser.pos + 1);
+        }
+        Ok(number)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn n(s: &str) -> SnailNumber {
+        s.parse().unwrap()
+    }
+
+    #[test]
+    fn test_parse() {
+        let input = "[[[[[9,8],1],2],3],4]";
+        assert_eq!(n(input).to_string(), input);
+        assert_eq!(n("[10,2]"), SnailNumber::pair(Regular(10), Regular(2)));
+        let err = |s: &str| s.parse::<SnailNumber>().unwrap_err().to_string();
+        assert_eq!(err("[1,2"), "expected `]` at end of input");
+        assert_eq!(err("[1;2]"), "expected `,` at column 3, found `;`");
+        assert_eq!(err("[1,2]]"), "unexpected input at column 6");
+        assert_eq!(err("[,2]"), "expected a number or `[` at column 2");
+    }
+
+    #[test]
+    fn test_reduction() {
+        assert_eq!(
+            (n("[[[[4,3],4],4],[7,[[8,4],9]]]") + n("[1,1]")).to_string(),
+            "[[[[0,7],4],[[7,8],[6,0]]],[8,1]]"
+        );
+
+        let steps: Vec<_> = SnailNumber::pair(n("[[[[4,3],4],4],[7,[[8,4],9]]]"), n("[1,1]"))
+            .reduction()
+            .map(|step| (step.action, step.number.to_string()))
+            .collect();
+        assert_eq!(
+            steps,
+            vec![
+                (
+                    Action::Explode,
+                    "[[[[0,7],4],[7,[[8,4],9]]],[1,1]]".to_string()
+                ),
+                (
+                    Action::Explode,
+                    "[[[[0,7],4],[15,[0,13]]],[1,1]]".to_string()
+                ),
+                (
+                    Action::Split,
+                    "[[[[0,7],4],[[7,8],[0,13]]],[1,1]]".to_string()
+                ),
+                (
+                    Action::Split,
+                    "[[[[0,7],4],[[7,8],[0,[6,7]]]],[1,1]]".to_string()
+                ),
+                (
+                    Action::Explode,
+                    "[[[[0,7],4],[[7,8],[6,0]]],[8,1]]".to_string()
+                ),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_magnitude() {
+        assert_eq!(n("[[1,2],[[3,4],5]]").magnitude(), 143);
+        assert_eq!(
+            n("[[[[8,7],[7,7]],[[8,6],[7,7]]],[[[0,7],[6,6]],[8,7]]]").magnitude(),
+            3488
+        );
+    }
+}
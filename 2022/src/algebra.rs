@@ -0,0 +1,338 @@
+// symbolic arithmetic over rationals: expression trees with simplification,
+// substitution, and solving equations that are linear (or a ratio of linear
+// expressions) in one unknown
+use anyhow::{bail, Result};
+use std::collections::BTreeSet;
+use std::fmt;
+use std::ops::{Add, Div, Mul, Sub};
+
+pub type F = fraction::Fraction;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BinOp {
+    Add,
+    Sub,
+    Mul,
+    Div,
+}
+
+impl BinOp {
+    fn symbol(self) -> &'static str {
+        match self {
+            BinOp::Add => "+",
+            BinOp::Sub => "-",
+            BinOp::Mul => "*",
+            BinOp::Div => "/",
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Expr {
+    Num(F),
+    Var(String),
+    Bin(BinOp, Box<Expr>, Box<Expr>),
+}
+
+fn zero() -> F {
+    F::from(0)
+}
+
+fn one() -> F {
+    F::from(1)
+}
+
+fn divide(a: F, b: F) -> Result<F> {
+    if b == zero() {
+        bail!("division by zero");
+    }
+    Ok(a / b)
+}
+
+impl Expr {
+    pub fn num(n: impl Into<F>) -> Self {
+        Expr::Num(n.into())
+    }
+
+    pub fn var(name: &str) -> Self {
+        Expr::Var(name.into())
+    }
+
+    pub fn bin(op: BinOp, lhs: Expr, rhs: Expr) -> Self {
+        Expr::Bin(op, Box::new(lhs), Box::new(rhs))
+    }
+
+    pub fn variables(&self) -> BTreeSet<&str> {
+        match self {
+            Expr::Num(_) => BTreeSet::new(),
+            Expr::Var(name) => vec![name.as_str()].into_iter().collect(),
+            Expr::Bin(_, lhs, rhs) => &lhs.variables() | &rhs.variables(),
+        }
+    }
+
+    pub fn eval(&self) -> Result<F> {
+        Ok(match self {
+            Expr::Num(n) => *n,
+            Expr::Var(name) => bail!("unbound variable `{}`", name),
+            Expr::Bin(op, lhs, rhs) => {
+                let (lhs, rhs) = (lhs.eval()?, rhs.eval()?);
+                match op {
+                    BinOp::Add => lhs + rhs,
+                    BinOp::Sub => lhs - rhs,
+                    BinOp::Mul => lhs * rhs,
+                    BinOp::Div => divide(lhs, rhs)?,
+                }
+            }
+        })
+    }
+
+    pub fn substitute(&self, name: &str, value: &Expr) -> Expr {
+        match self {
+            Expr::Var(var) if var == name => value.clone(),
+            Expr::Num(_) | Expr::Var(_) => self.clone(),
+            Expr::Bin(op, lhs, rhs) => Expr::bin(
+                *op,
+                lhs.substitute(name, value),
+                rhs.substitute(name, value),
+            ),
+        }
+    }
+
+    // folds constant subexpressions and removes identities like `x + 0` and
+    // `x * 1`. division by a constant zero is left alone so `eval` can report it
+    pub fn simplify(&self) -> Expr {
+        let (op, lhs, rhs) = match self {
+            Expr::Bin(op, lhs, rhs) => (*op, lhs.simplify(), rhs.simplify()),
+            _ => return self.clone(),
+        };
+        use BinOp::*;
+        match (op, &lhs, &rhs) {
+            (Div, _, Expr::Num(n)) if *n == zero() => Expr::bin(op, lhs, rhs),
+            (_, Expr::Num(_), Expr::Num(_)) => Expr::Num(Expr::bin(op, lhs, rhs).eval().unwrap()),
+            (Add, Expr::Num(n), _) if *n == zero() => rhs,
+            (Add | Sub, _, Expr::Num(n)) if *n == zero() => lhs,
+            (Mul, Expr::Num(n), _) | (Mul, _, Expr::Num(n)) if *n == zero() => Expr::num(0),
+            (Mul, Expr::Num(n), _) if *n == one() => rhs,
+            (Mul | Div, _, Expr::Num(n)) if *n == one() => lhs,
+            (Sub, _, _) if lhs == rhs => Expr::num(0),
+            _ => Expr::bin(op, lhs, rhs),
+        }
+    }
+
+    // `self` as p(x) / q(x) for polynomials p and q in `unknown`. a constant q
+    // is folded into p after every step so long chains of divisions don't
+    // grow the coefficients
+    fn ratio(&self, unknown: &str) -> Result<(Poly, Poly)> {
+        let (p, q) = match self {
+            Expr::Num(n) => (Poly::constant(*n), Poly::constant(one())),
+            Expr::Var(name) if name == unknown => {
+                (Poly(vec![zero(), one()]), Poly::constant(one()))
+            }
+            Expr::Var(name) => bail!("unbound variable `{}`", name),
+            Expr::Bin(op, lhs, rhs) => {
+                let ((a, b), (c, d)) = (lhs.ratio(unknown)?, rhs.ratio(unknown)?);
+                match op {
+                    BinOp::Add => (a.mul(&d).add(&c.mul(&b), one()), b.mul(&d)),
+                    BinOp::Sub => (a.mul(&d).add(&c.mul(&b), -one()), b.mul(&d)),
+                    BinOp::Mul => (a.mul(&c), b.mul(&d)),
+                    BinOp::Div => {
+                        if c.is_zero() {
+                            bail!("division by zero");
+                        }
+                        (a.mul(&d), b.mul(&c))
+                    }
+                }
+            }
+        };
+        Ok(match q.degree() {
+            0 if !q.is_zero() => (p.scale(one() / q.coefficient(0)), Poly::constant(one())),
+            _ => (p, q),
+        })
+    }
+}
+
+// coefficients, lowest power first
+#[derive(Debug, Clone, PartialEq)]
+struct Poly(Vec<F>);
+
+impl Poly {
+    fn constant(n: F) -> Self {
+        Poly(vec![n]).trimmed()
+    }
+
+    fn trimmed(mut self) -> Self {
+        while self.0.last() == Some(&zero()) {
+            self.0.pop();
+        }
+        self
+    }
+
+    fn is_zero(&self) -> bool {
+        self.0.is_empty()
+    }
+
+    fn degree(&self) -> usize {
+        self.0.len().saturating_sub(1)
+    }
+
+    fn coefficient(&self, power: usize) -> F {
+        self.0.get(power).copied().unwrap_or_else(zero)
+    }
+
+    // self + scale * other
+    fn add(&self, other: &Poly, scale: F) -> Poly {
+        let len = self.0.len().max(other.0.len());
+        Poly(
+            (0..len)
+                .map(|i| self.coefficient(i) + scale * other.coefficient(i))
+                .collect(),
+        )
+        .trimmed()
+    }
+
+    fn scale(&self, factor: F) -> Poly {
+        Poly(self.0.iter().map(|c| *c * factor).collect()).trimmed()
+    }
+
+    fn mul(&self, other: &Poly) -> Poly {
+        if self.is_zero() || other.is_zero() {
+            return Poly(vec![]);
+        }
+        let mut result = vec![zero(); self.0.len() + other.0.len() - 1];
+        for (i, a) in self.0.iter().enumerate() {
+            for (j, b) in other.0.iter().enumerate() {
+                result[i + j] += *a * *b;
+            }
+        }
+        Poly(result).trimmed()
+    }
+
+    fn eval(&self, x: F) -> F {
+        self.0.iter().rev().fold(zero(), |acc, c| acc * x + *c)
+    }
+}
+
+// solves `lhs = rhs` for `unknown`, where every other variable has already
+// been substituted away
+pub fn solve(lhs: &Expr, rhs: &Expr, unknown: &str) -> Result<F> {
+    let (a, b) = lhs.ratio(unknown)?;
+    let (c, d) = rhs.ratio(unknown)?;
+    // a/b = c/d  =>  ad - cb = 0
+    let equation = a.mul(&d).add(&c.mul(&b), -one());
+    let x = match equation.degree() {
+        _ if equation.is_zero() => bail!("every value of `{}` is a solution", unknown),
+        0 => bail!("no value of `{}` is a solution", unknown),
+        1 => -equation.coefficient(0) / equation.coefficient(1),
+        _ => bail!("`{}` appears non-linearly", unknown),
+    };
+    if b.eval(x) == zero() || d.eval(x) == zero() {
+        bail!("`{}` = {} makes a denominator zero", unknown, x);
+    }
+    Ok(x)
+}
+
+impl fmt::Display for Expr {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Expr::Num(n) => write!(f, "{}", n),
+            Expr::Var(name) => write!(f, "{}", name),
+            Expr::Bin(op, lhs, rhs) => write!(f, "({} {} {})", lhs, op.symbol(), rhs),
+        }
+    }
+}
+
+macro_rules! impl_op {
+    ($trait:ident, $method:ident, $op:expr) => {
+        impl $trait for Expr {
+            type Output = Expr;
+
+            fn $method(self, other: Expr) -> Expr {
+                Expr::bin($op, self, other)
+            }
+        }
+    };
+}
+
+impl_op!(Add, add, BinOp::Add);
+impl_op!(Sub, sub, BinOp::Sub);
+impl_op!(Mul, mul, BinOp::Mul);
+impl_op!(Div, div, BinOp::Div);
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn x() -> Expr {
+        Expr::var("x")
+    }
+
+    fn n(value: i64) -> Expr {
+        Expr::num(value)
+    }
+
+    #[test]
+    fn test_simplify() {
+        assert_eq!((n(2) * n(3) + x() * n(1)).simplify(), n(6) + x());
+        assert_eq!(((x() - x()) * n(5) + n(0)).simplify(), n(0));
+        assert_eq!((x() / (n(1) - n(1))).simplify(), x() / n(0));
+        assert_eq!(
+            (n(1) / n(3) + n(1) / n(6)).simplify(),
+            Expr::Num(F::new(1u64, 2u64))
+        );
+    }
+
+    #[test]
+    fn test_substitute() -> Result<()> {
+        let expr = (x() + Expr::var("y")) * x();
+        assert_eq!(expr.variables(), vec!["x", "y"].into_iter().collect());
+        let expr = expr.substitute("x", &n(3)).substitute("y", &n(4));
+        assert_eq!(expr.to_string(), "((3 + 4) * 3)");
+        assert_eq!(expr.eval()?, F::from(21));
+        assert!(Expr::var("y").eval().is_err());
+        assert!((n(1) / n(0)).eval().is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_solve() -> Result<()> {
+        // 2x + 3 = 11
+        assert_eq!(solve(&(n(2) * x() + n(3)), &n(11), "x")?, F::from(4));
+        // (x - 3) / 4 = 2 - x
+        assert_eq!(
+            solve(&((x() - n(3)) / n(4)), &(n(2) - x()), "x")?,
+            F::new(11u64, 5u64)
+        );
+        // 10 / (x + 2) = 5
+        assert_eq!(solve(&(n(10) / (x() + n(2))), &n(5), "x")?, F::from(0));
+        // x * x - x * x + x = 7 is still linear
+        assert_eq!(
+            solve(&(x() * x() - x() * x() + x()), &n(7), "x")?,
+            F::from(7)
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_solve_deep_chain() -> Result<()> {
+        // each step is (e * 4 + 8) / 4 - 1 = e + 1, with a division the
+        // denominator must not keep track of
+        let chain = (0..70).fold(x(), |e, _| (e * n(4) + n(8)) / n(4) - n(1));
+        assert_eq!(solve(&chain, &n(100), "x")?, F::from(30));
+        assert_eq!(solve(&n(100), &chain, "x")?, F::from(30));
+        Ok(())
+    }
+
+    #[test]
+    fn test_solve_errors() {
+        let err = |lhs: Expr, rhs: Expr| solve(&lhs, &rhs, "x").unwrap_err().to_string();
+        assert_eq!(err(x() * x(), n(4)), "`x` appears non-linearly");
+        assert_eq!(err(x() - x(), n(4)), "no value of `x` is a solution");
+        assert_eq!(
+            err(x() + n(1), n(1) + x()),
+            "every value of `x` is a solution"
+        );
+        assert_eq!(err(x() + Expr::var("y"), n(1)), "unbound variable `y`");
+        assert_eq!(err(x() / x(), n(1)), "every value of `x` is a solution");
+        assert_eq!(err(x() / x(), n(0)), "`x` = 0 makes a denominator zero");
+    }
+}
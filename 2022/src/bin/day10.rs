@@ -1,6 +1,6 @@
-use anyhow::{bail, Context, Error, Result};
+use anyhow::{bail, Error, Result};
 use aoc2022::dispatch;
-use std::fmt::Write;
+use machine::{self, parse_program, Flow, Machine};
 
 fn main() -> Result<()> {
     dispatch(part1, part2)
@@ -8,7 +8,7 @@ fn main() -> Result<()> {
 
 enum Instruction {
     Noop,
-    AddX(i32),
+    AddX(i64),
 }
 
 impl std::str::FromStr for Instruction {
@@ -24,11 +24,30 @@ impl std::str::FromStr for Instruction {
     }
 }
 
-fn parse(input: &str) -> Result<Vec<Instruction>> {
-    input.split('\n').map(|l| l.parse()).collect()
+impl machine::Instruction for Instruction {
+    // the x register
+    type State = i64;
+
+    fn execute(&self, x: &mut i64) -> Flow {
+        if let Instruction::AddX(value) = self {
+            *x += value;
+        }
+        Flow::Next
+    }
+
+    fn cycles(&self) -> usize {
+        match self {
+            Instruction::Noop => 1,
+            Instruction::AddX(_) => 2,
+        }
+    }
+}
+
+fn machine(input: &str) -> Result<Machine<Instruction>> {
+    Ok(Machine::new(parse_program(input)?, 1))
 }
 
-fn strength(cycle: i32, x: i32) -> i32 {
+fn strength(cycle: i64, x: i64) -> i64 {
     if (cycle - 20) % 40 == 0 {
         x * cycle
     } else {
@@ -36,52 +55,34 @@ fn strength(cycle: i32, x: i32) -> i32 {
     }
 }
 
-fn part1(input: &str) -> Result<i32> {
-    let instructions = parse(input)?;
-    let mut cycle = 1;
-    let mut x = 1;
+fn part1(input: &str) -> Result<i64> {
     let mut total_signal = 0;
-    for instruction in instructions {
-        total_signal += strength(cycle, x);
-        cycle += 1;
-        if let Instruction::AddX(value) = instruction {
-            total_signal += strength(cycle, x);
-            cycle += 1;
-            x += value;
-        }
-    }
+    machine(input)?.run_with(|tick| total_signal += strength(tick.cycle as i64, *tick.state));
     Ok(total_signal)
 }
 
-fn draw(buf: &mut String, cycle: i32, x: i32) -> Result<()> {
+fn draw(buf: &mut String, cycle: i64, x: i64) {
     let sprite_pos = (cycle - 1) % 40;
     if sprite_pos == 0 {
-        writeln!(buf)?;
+        buf.push('\n');
     }
     if (x - sprite_pos).abs() <= 1 {
-        write!(buf, "#").context("write failed")
+        buf.push('#');
     } else {
-        write!(buf, ".").context("write failed")
+        buf.push('.');
     }
 }
 
-fn part2(input: &str) -> Result<String> {
-    let instructions = parse(input)?;
+fn render(input: &str) -> Result<String> {
     let mut buf = String::new();
-    let mut cycle = 1;
-    let mut x = 1;
-    for instruction in &instructions {
-        draw(&mut buf, cycle, x)?;
-        cycle += 1;
-        if let Instruction::AddX(value) = instruction {
-            draw(&mut buf, cycle, x)?;
-            cycle += 1;
-            x += value;
-        }
-    }
+    machine(input)?.run_with(|tick| draw(&mut buf, tick.cycle as i64, *tick.state));
     Ok(buf)
 }
 
+fn part2(input: &str) -> Result<String> {
+    Ok(ocr::read(&render(input)?)?)
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -240,9 +241,9 @@ noop";
     }
 
     #[test]
-    fn test_part2() -> Result<()> {
+    fn test_render() -> Result<()> {
         assert_eq!(
-            part2(TEST_INPUT)?,
+            render(TEST_INPUT)?,
             "
 ##..##..##..##..##..##..##..##..##..##..
 ###...###...###...###...###...###...###.
@@ -1,6 +1,6 @@
-use anyhow::{Context, Error, Result};
+use anyhow::{bail, Context, Error, Result};
 use aoc2022::dispatch;
-use std::collections::{BTreeSet, HashMap, HashSet, VecDeque};
+use std::collections::{HashMap, VecDeque};
 
 fn main() -> Result<()> {
     dispatch(part1, part2)
@@ -39,372 +39,245 @@ impl<'a> TryFrom<&'a str> for Reading<'a> {
     }
 }
 
-#[derive(Debug, Clone)]
-struct State<'a> {
-    pos: &'a str,
-    steps: usize,
-    visited: HashSet<&'a str>,
-    open: HashSet<&'a str>,
-    released: i64,
-    rates: Vec<(usize, i64)>,
-    path: Vec<&'a str>,
-}
+const START: &str = "AA";
 
-impl State<'_> {
-    fn flow_rate(&self, map: &HashMap<&str, &Reading>) -> i64 {
-        self.open.iter().map(|s| map.get(s).unwrap().rate).sum()
-    }
+// more than this and the tables indexed by sets of valves get too big
+const MAX_VALVES: usize = 20;
 
-    fn total(&self) -> i64 {
-        let mut prev = 0;
-        let mut last = 0;
-        let mut total = 0;
-        for &(step, rate) in &self.rates {
-            total += (step - prev) as i64 * rate;
-            prev = step;
-            last = rate;
-        }
-        total += (29 - prev) as i64 * last;
-        total
-    }
+// when a valve gets opened, counting minutes from the start. it releases
+// pressure for every minute after that
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+struct Opening<'a> {
+    minute: usize,
+    valve: &'a str,
 }
 
-fn shortest_paths<'a>(
-    readings: &[Reading<'a>],
-) -> HashMap<&'a str, HashMap<&'a str, (Vec<&'a str>, usize)>> {
-    let mut paths: HashMap<&'a str, HashMap<&'a str, (Vec<&'a str>, usize)>> = HashMap::new();
-    let map = readings
-        .iter()
-        .map(|r| (r.valve, r))
-        .collect::<HashMap<&str, _>>();
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct Plan<'a> {
+    pressure: i64,
+    // the valves each actor opens, in order
+    schedules: Vec<Vec<Opening<'a>>>,
+}
 
-    let endpoints = readings
-        .iter()
-        .filter(|r| r.valve == "AA" || r.rate > 0)
-        .map(|r| r.valve)
-        .collect::<Vec<_>>();
+// the valves worth opening, and the travel times between them. valves with
+// no flow are only ever passed through, so they're left out
+struct Network<'a> {
+    names: Vec<&'a str>,
+    rates: Vec<i64>,
+    // distances[i][j] between valves worth opening
+    distances: Vec<Vec<usize>>,
+    // from the start to each valve worth opening
+    from_start: Vec<usize>,
+}
 
-    for &start in &endpoints {
-        'pair: for &end in &endpoints {
-            if start == end {
-                continue;
-            }
-            if paths.get(&start).and_then(|m| m.get(&end)).is_some() {
-                continue;
-            }
-            let mut queue = VecDeque::from([(start, vec![], 0)]);
-            let mut seen = HashSet::new();
-            while let Some((next, passed, distance)) = queue.pop_front() {
-                let location = map.get(next).unwrap();
-                for &tunnel in &location.tunnels {
-                    if tunnel == end {
-                        paths
-                            .entry(start)
-                            .or_default()
-                            .insert(end, (passed.clone(), distance + 1));
-                        paths
-                            .entry(end)
-                            .or_default()
-                            .insert(start, (passed.clone(), distance + 1));
-                        continue 'pair;
-                    }
-                    if seen.contains(&tunnel) {
-                        continue;
-                    }
-                    seen.insert(tunnel);
-                    let mut passed = passed.clone();
-                    if map.get(&tunnel).unwrap().rate > 0 {
-                        passed.push(tunnel);
+impl<'a> Network<'a> {
+    fn new(readings: &[Reading<'a>]) -> Result<Self> {
+        let index: HashMap<&str, usize> = readings
+            .iter()
+            .enumerate()
+            .map(|(i, r)| (r.valve, i))
+            .collect();
+        let mut tunnels = vec![];
+        for reading in readings {
+            tunnels.push(
+                reading
+                    .tunnels
+                    .iter()
+                    .map(|t| {
+                        index
+                            .get(t)
+                            .copied()
+                            .with_context(|| format!("no valve {}", t))
+                    })
+                    .collect::<Result<Vec<_>>>()?,
+            );
+        }
+        // every valve by the number of steps to it
+        let bfs = |from: usize| {
+            let mut distances = vec![usize::MAX; readings.len()];
+            distances[from] = 0;
+            let mut queue = VecDeque::from([from]);
+            while let Some(current) = queue.pop_front() {
+                for &next in &tunnels[current] {
+                    if distances[next] == usize::MAX {
+                        distances[next] = distances[current] + 1;
+                        queue.push_back(next);
                     }
-                    queue.push_back((tunnel, passed, distance + 1));
                 }
             }
+            distances
+        };
+
+        let useful: Vec<usize> = (0..readings.len())
+            .filter(|&i| readings[i].rate > 0)
+            .collect();
+        if useful.len() > MAX_VALVES {
+            bail!(
+                "{} valves have a flow rate, can only handle {}",
+                useful.len(),
+                MAX_VALVES
+            );
         }
+        let start = *index.get(START).context("no start valve")?;
+        let start_distances = bfs(start);
+        let distances = useful
+            .iter()
+            .map(|&from| {
+                let all = bfs(from);
+                useful.iter().map(|&to| all[to]).collect()
+            })
+            .collect();
+        Ok(Network {
+            names: useful.iter().map(|&i| readings[i].valve).collect(),
+            rates: useful.iter().map(|&i| readings[i].rate).collect(),
+            distances,
+            from_start: useful.iter().map(|&i| start_distances[i]).collect(),
+        })
     }
-    paths
-}
-
-fn _shortest_direct_paths<'a>(
-    readings: &[Reading<'a>],
-) -> HashMap<&'a str, HashMap<&'a str, usize>> {
-    let mut paths: HashMap<&'a str, HashMap<&'a str, usize>> = HashMap::new();
-    let map = readings
-        .iter()
-        .map(|r| (r.valve, r))
-        .collect::<HashMap<&str, _>>();
 
-    let endpoints = readings
-        .iter()
-        .filter(|r| r.valve == "AA" || r.rate > 0)
-        .map(|r| r.valve)
-        .collect::<Vec<_>>();
+    // for every set of valves, the most pressure one actor can release by
+    // opening exactly those, and the order to open them in
+    fn best_by_set(&self, time: usize) -> Vec<(i64, Vec<(usize, usize)>)> {
+        let mut best = vec![(-1, vec![]); 1 << self.names.len()];
+        best[0].0 = 0;
+        let mut route = vec![];
+        self.explore(None, 0, 0, 0, time, &mut route, &mut best);
+        best
+    }
 
-    for &start in &endpoints {
-        'pair: for &end in &endpoints {
-            if start == end {
+    #[allow(clippy::too_many_arguments)]
+    fn explore(
+        &self,
+        at: Option<usize>,
+        minute: usize,
+        opened: u64,
+        pressure: i64,
+        time: usize,
+        route: &mut Vec<(usize, usize)>,
+        best: &mut Vec<(i64, Vec<(usize, usize)>)>,
+    ) {
+        for next in 0..self.names.len() {
+            if opened & (1 << next) != 0 {
                 continue;
             }
-            if paths.get(&start).and_then(|m| m.get(&end)).is_some() {
+            let distance = match at {
+                None => self.from_start[next],
+                Some(at) => self.distances[at][next],
+            };
+            let open_at = minute.saturating_add(distance).saturating_add(1);
+            if open_at >= time {
                 continue;
             }
-            let mut queue = VecDeque::from([(start, 0)]);
-            let mut seen = HashSet::new();
-            while let Some((next, distance)) = queue.pop_front() {
-                let location = map.get(next).unwrap();
-                for &tunnel in &location.tunnels {
-                    if tunnel == end {
-                        paths.entry(start).or_default().insert(end, distance + 1);
-                        paths.entry(end).or_default().insert(start, distance + 1);
-                        continue 'pair;
-                    }
-                    if seen.contains(&tunnel) {
-                        continue;
-                    }
-                    if endpoints.contains(&tunnel) {
-                        continue;
-                    }
-                    seen.insert(tunnel);
-                    if let Some(dst) = map.get(&tunnel) {
-                        if dst.rate > 0 {
-                            continue;
-                        }
-                    }
-                    queue.push_back((tunnel, distance + 1));
-                }
+            let opened = opened | 1 << next;
+            let pressure = pressure + self.rates[next] * (time - open_at) as i64;
+            route.push((next, open_at));
+            if pressure > best[opened as usize].0 {
+                best[opened as usize] = (pressure, route.clone());
             }
+            self.explore(Some(next), open_at, opened, pressure, time, route, best);
+            route.pop();
         }
     }
-    paths
-}
 
-fn part1(input: &str) -> Result<i64> {
-    let readings = input
-        .split('\n')
-        .map(|l| l.try_into())
-        .collect::<Result<Vec<Reading>>>()?;
-    let paths = shortest_paths(&readings);
-    let map = readings
-        .iter()
-        .map(|r| (r.valve, r))
-        .collect::<HashMap<&str, _>>();
-    let start = State {
-        pos: "AA",
-        steps: 0,
-        visited: HashSet::from(["AA"]),
-        open: HashSet::new(),
-        released: 0,
-        rates: vec![],
-        path: vec![],
-    };
-    let mut best = 0;
-    let mut queue = VecDeque::from([start]);
-    while let Some(state) = queue.pop_front() {
-        let location = map.get(state.pos).unwrap();
-        if state.total() > best {
-            best = best.max(state.total());
-        }
-        if state.path.len() > 30 {
-            continue;
-        };
-        if !state.open.contains(state.pos) && location.rate > 0 {
-            let mut new_state = state.clone();
-            new_state.steps += 1;
-            new_state.open.insert(state.pos);
-            new_state.released += state.flow_rate(&map);
-            new_state.path.push(state.pos);
-            new_state
-                .rates
-                .push((new_state.steps, new_state.flow_rate(&map)));
-            queue.push_back(new_state);
-            continue;
-        }
-        for (tunnel, (_, distance)) in paths.get(state.pos).unwrap() {
-            if state.visited.contains(tunnel) {
-                continue;
-            }
-            let mut new_state = state.clone();
-            new_state.pos = tunnel;
-            new_state.visited.insert(tunnel);
-            new_state.steps += distance;
-            if new_state.steps >= 30 {
-                continue;
+    // the most pressure `actors` working together can release in `time`
+    // minutes, all starting at the same time from the start valve
+    fn optimize(&self, actors: usize, time: usize) -> Plan<'a> {
+        let best = self.best_by_set(time);
+        let sets = best.len();
+        // for each set, the best of any of its subsets
+        let mut within: Vec<(i64, usize)> =
+            best.iter().enumerate().map(|(set, b)| (b.0, set)).collect();
+        for valve in 0..self.names.len() {
+            for set in 0..sets {
+                if set & (1 << valve) != 0 {
+                    let without = within[set ^ (1 << valve)];
+                    if without.0 > within[set].0 {
+                        within[set] = without;
+                    }
+                }
             }
-            new_state.released += state.flow_rate(&map);
-            new_state.path.push(tunnel);
-            new_state
-                .rates
-                .push((new_state.steps, new_state.flow_rate(&map)));
-            queue.push_back(new_state);
         }
-    }
-    Ok(best)
-}
 
-fn _add_opens<'a>(path: &'_ Vec<(&'a str, usize)>) -> Vec<Vec<(&'a str, usize)>> {
-    let mut counts: HashMap<&str, usize> = HashMap::new();
-    let mut paths: Vec<Vec<(&'a str, usize)>> = vec![vec![path[0]]];
-    for (n, _) in path {
-        if n == &"AA" {
-            continue;
+        let mut memo = HashMap::new();
+        let pressure = split(actors, sets - 1, &within, &mut memo);
+        let mut schedules = vec![];
+        let mut remaining = sets - 1;
+        for actors in (1..=actors).rev() {
+            let (_, own) = memo[&(actors, remaining)];
+            let (_, exact) = within[own];
+            schedules.push(
+                best[exact]
+                    .1
+                    .iter()
+                    .map(|&(valve, minute)| Opening {
+                        minute,
+                        valve: self.names[valve],
+                    })
+                    .collect(),
+            );
+            remaining &= !own;
         }
-        *counts.entry(n).or_default() += 1;
-    }
-    for entry in &path[1..] {
-        if *counts.get(&entry.0).unwrap_or(&0) > 1 {
-            let mut new = vec![];
-            for option1 in &mut paths {
-                let mut option2 = option1.clone();
-                option1.push(*entry);
-                option1.push((" ", 1));
-                option2.push(*entry);
-                // NB: no ""
-                new.push(option2);
-            }
-            paths.extend(new);
-        } else {
-            for option in &mut paths {
-                option.push(*entry);
-                if entry.0 != "AA" {
-                    option.push((" ", 1));
-                }
-            }
+        Plan {
+            pressure,
+            schedules,
         }
     }
-    paths
 }
 
-fn add_opens2<'a>(
-    path: &[(&'a str, usize)],
-    other_path: &'_ Vec<(&'a str, usize)>,
-) -> Vec<Vec<(&'a str, usize)>> {
-    let mut counts: HashMap<&str, usize> = HashMap::new();
-    let mut paths: Vec<Vec<(&'a str, usize)>> = vec![vec![path[0]]];
-    for (n, _) in path.iter().chain(other_path) {
-        if n == &"AA" {
-            continue;
-        }
-        *counts.entry(n).or_default() += 1;
+// the most pressure `actors` can release between them from valves in `set`,
+// each taking a disjoint subset, and none without any actors. memoizes the
+// best value and the subset the first actor takes
+fn split(
+    actors: usize,
+    set: usize,
+    within: &[(i64, usize)],
+    memo: &mut HashMap<(usize, usize), (i64, usize)>,
+) -> i64 {
+    if let Some(&(pressure, _)) = memo.get(&(actors, set)) {
+        return pressure;
     }
-    for entry in &path[1..] {
-        if *counts.get(&entry.0).unwrap_or(&0) > 1 {
-            let mut new = vec![];
-            for option1 in &mut paths {
-                let mut option2 = option1.clone();
-                option1.push(*entry);
-                option1.push((" ", 1));
-                option2.push(*entry);
-                // NB: no ""
-                new.push(option2);
+    let result = if actors == 0 {
+        (0, 0)
+    } else if actors == 1 {
+        (within[set].0, set)
+    } else {
+        // every subset of `set`, including itself and the empty one
+        let mut result = (-1, 0);
+        let mut own = set;
+        loop {
+            let pressure = within[own].0 + split(actors - 1, set & !own, within, memo);
+            if pressure > result.0 {
+                result = (pressure, own);
             }
-            paths.extend(new);
-        } else {
-            for option in &mut paths {
-                option.push(*entry);
-                if entry.0 != "AA" {
-                    option.push((" ", 1));
-                }
+            if own == 0 {
+                break;
             }
+            own = (own - 1) & set;
         }
-    }
-    paths
+        result
+    };
+    memo.insert((actors, set), result);
+    result.0
 }
 
-fn part2(input: &str) -> Result<i64> {
-    let readings = input
-        .split('\n')
+fn parse(input: &str) -> Result<Vec<Reading<'_>>> {
+    input
+        .lines()
+        .filter(|l| !l.is_empty())
         .map(|l| l.try_into())
-        .collect::<Result<Vec<Reading>>>()?;
-    let map = readings
-        .iter()
-        .map(|r| (r.valve, r))
-        .collect::<HashMap<&str, _>>();
-    let paths = _shortest_direct_paths(&readings);
+        .collect()
+}
 
-    // a1, a2, visited, path
-    let mut options = vec![];
-    let start = (
-        "AA",
-        "AA",
-        BTreeSet::<&str>::from(["AA"]),
-        vec![("AA", 0)],
-        vec![("AA", 0)],
-    );
-    let mut seen = HashSet::new();
-    let mut queue = VecDeque::from([start]);
-    let all = paths.keys().cloned().collect::<BTreeSet<_>>();
-    while let Some((a1, a2, visited, path1, path2)) = queue.pop_front() {
-        if visited == all {
-            options.push((path1, path2));
-            continue;
-        }
-        let location1 = paths.get(a1).unwrap();
-        let location2 = paths.get(a2).unwrap();
-        for (&tunnel1, &d1) in location1.iter() {
-            for (&tunnel2, &d2) in location2.iter() {
-                if seen.contains(&(visited.clone(), tunnel1, tunnel2))
-                    || seen.contains(&(visited.clone(), tunnel2, tunnel1))
-                {
-                    continue;
-                }
-                seen.insert((visited.clone(), tunnel1, tunnel2));
-                let mut path1 = path1.clone();
-                path1.push((tunnel1, d1));
-                let mut path2 = path2.clone();
-                path2.push((tunnel2, d2));
-                let mut visited = visited.clone();
-                visited.insert(tunnel1);
-                visited.insert(tunnel2);
-                queue.push_back((tunnel1, tunnel2, visited, path1, path2));
-            }
-        }
-    }
+fn optimize<'a>(readings: &[Reading<'a>], actors: usize, time: usize) -> Result<Plan<'a>> {
+    Ok(Network::new(readings)?.optimize(actors, time))
+}
 
-    let mut possible = vec![];
-    for (path1, path2) in options {
-        let paths1 = add_opens2(&path1, &path2);
-        let paths2 = add_opens2(&path2, &path1);
-        for option1 in &paths1 {
-            let _label1 = &option1.iter().map(|(v, _)| v).cloned().collect::<String>();
-            for option2 in &paths2 {
-                let _label2 = &option2.iter().map(|(v, _)| v).cloned().collect::<String>();
-                let mut rates = [0; 26];
-                let mut open = HashSet::new();
-                for player in [option1, option2] {
-                    let mut time = 1;
-                    let mut rate = 0;
-                    let mut prev_time = 0;
-                    let mut prev_pos = "AA";
-                    for (pos, dist) in player {
-                        time += dist;
-                        rate += if let Some(location1) = map.get(prev_pos) {
-                            if pos == &" " && !open.contains(&prev_pos) {
-                                open.insert(prev_pos);
-                                location1.rate
-                            } else {
-                                0
-                            }
-                        } else {
-                            0
-                        };
-                        if prev_time >= 26 {
-                            break;
-                        }
-                        for entry in rates.iter_mut().take(time.min(26)).skip(prev_time) {
-                            *entry += rate;
-                        }
-                        prev_time = time;
-                        prev_pos = pos;
-                    }
-                    for entry in rates.iter_mut().skip(prev_time) {
-                        *entry += rate;
-                    }
-                }
-                possible.push(rates.iter().sum());
-            }
-        }
-    }
+fn part1(input: &str) -> Result<i64> {
+    Ok(optimize(&parse(input)?, 1, 30)?.pressure)
+}
 
-    Ok(*possible.iter().max().unwrap())
+fn part2(input: &str) -> Result<i64> {
+    Ok(optimize(&parse(input)?, 2, 26)?.pressure)
 }
 
 #[cfg(test)]
@@ -433,4 +306,91 @@ Valve JJ has flow rate=21; tunnel leads to valve II";
         assert_eq!(part2(TEST_INPUT)?, 1707);
         Ok(())
     }
+
+    fn schedule(openings: &[(&'static str, usize)]) -> Vec<Opening<'static>> {
+        openings
+            .iter()
+            .map(|&(valve, minute)| Opening { minute, valve })
+            .collect()
+    }
+
+    #[test]
+    fn test_network() -> Result<()> {
+        let readings = parse(TEST_INPUT)?;
+        let network = Network::new(&readings)?;
+        assert_eq!(network.names, vec!["BB", "CC", "DD", "EE", "HH", "JJ"]);
+        assert_eq!(network.from_start, vec![1, 2, 1, 2, 5, 2]);
+        // DD to HH goes through EE, FF and GG
+        assert_eq!(network.distances[2][4], 4);
+
+        let mut readings = readings;
+        readings[1].tunnels = vec!["ZZ"];
+        assert_eq!(
+            Network::new(&readings).err().unwrap().to_string(),
+            "no valve ZZ"
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_schedules() -> Result<()> {
+        let readings = parse(TEST_INPUT)?;
+        let plan = optimize(&readings, 1, 30)?;
+        assert_eq!(
+            plan,
+            Plan {
+                pressure: 1651,
+                schedules: vec![schedule(&[
+                    ("DD", 2),
+                    ("BB", 5),
+                    ("JJ", 9),
+                    ("HH", 17),
+                    ("EE", 21),
+                    ("CC", 24)
+                ])]
+            }
+        );
+
+        let mut plan = optimize(&readings, 2, 26)?;
+        plan.schedules.sort();
+        assert_eq!(
+            plan,
+            Plan {
+                pressure: 1707,
+                schedules: vec![
+                    schedule(&[("DD", 2), ("HH", 7), ("EE", 11)]),
+                    schedule(&[("JJ", 3), ("BB", 7), ("CC", 9)]),
+                ]
+            }
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_actors_and_time() -> Result<()> {
+        let readings = parse(TEST_INPUT)?;
+        // no time to open anything, or no one to open it
+        assert_eq!(optimize(&readings, 1, 1)?.pressure, 0);
+        let plan = optimize(&readings, 0, 30)?;
+        assert_eq!(plan.pressure, 0);
+        assert!(plan.schedules.is_empty());
+        // DD, opened at minute 2, then flows for one minute
+        assert_eq!(optimize(&readings, 1, 3)?.pressure, 20);
+        // with more actors than valves, some have nothing to do, and every
+        // valve is opened as soon as someone can get there
+        let plan = optimize(&readings, 8, 30)?;
+        let everything: i64 = [(13, 1), (2, 2), (20, 1), (3, 2), (22, 5), (21, 2)]
+            .iter()
+            .map(|&(rate, distance): &(i64, i64)| rate * (30 - distance - 1))
+            .sum();
+        assert_eq!(plan.pressure, everything);
+        assert_eq!(plan.schedules.len(), 8);
+        assert_eq!(plan.schedules.iter().filter(|s| s.is_empty()).count(), 2);
+        // more actors never hurts
+        let pressures: Vec<i64> = (1..=4)
+            .map(|actors| optimize(&readings, actors, 26).map(|p| p.pressure))
+            .collect::<Result<_>>()?;
+        assert!(pressures.windows(2).all(|w| w[0] <= w[1]));
+        Ok(())
+    }
 }
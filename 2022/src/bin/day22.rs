@@ -1,6 +1,6 @@
-use anyhow::{bail, Error, Result};
+use anyhow::{bail, Context, Error, Result};
 use aoc2022::coor::Coor;
-use aoc2022::coor3::Coor3;
+use aoc2022::cube::CubeNet;
 use aoc2022::dispatch;
 use std::collections::HashMap;
 
@@ -28,67 +28,10 @@ impl TryFrom<char> for Tile {
     }
 }
 
-#[derive(Debug, PartialEq, Eq, Hash)]
-struct Local {
-    u: Coor3,
-    v: Coor3,
-}
-
-#[derive(Debug, PartialEq, Eq, Hash, Copy, Clone)]
-struct Side(i64, i64);
-
-impl Local {
-    fn new(u: Coor3, v: Coor3) -> Self {
-        Self { u, v }
-    }
-
-    fn axis(&self) -> Coor3 {
-        self.u.cross(self.v)
-    }
-
-    // ↑
-    // v
-    // u →
-    //
-    //    y
-    //    ↑
-    //  ↙︎  → x
-    // z
-    // (u, v)
-
-    fn left(&self) -> Self {
-        Self::new(-(-self.u).cross(self.v), self.v)
-    }
-
-    fn right(&self) -> Self {
-        Self::new(-self.u.cross(self.v), self.v)
-    }
-
-    fn down(&self) -> Self {
-        Self::new(self.u, (-self.v).cross(self.u))
-    }
-
-    fn up(&self) -> Self {
-        Self::new(self.u, self.v.cross(self.u))
-    }
-
-    fn mv(&self, direction: Coor) -> Self {
-        match (direction.x, direction.y) {
-            (1, 0) => self.right(),
-            (-1, 0) => self.left(),
-            (0, 1) => self.up(),
-            (0, -1) => self.down(),
-            _ => panic!("invalid mv direction"),
-        }
-    }
-
-    fn v_to_global(&self, direction: Coor) -> Coor3 {
-        direction.x * self.u + direction.y * self.v
-    }
-
-    fn v_to_local(&self, direction: Coor3) -> Coor {
-        Coor::new(direction.dot(self.u), direction.dot(self.v))
-    }
+// the map is stored with y pointing up (rows going negative), the cube net
+// with y pointing down
+fn flip(coor: Coor) -> Coor {
+    Coor::new(coor.x, -coor.y)
 }
 
 #[derive(Debug)]
@@ -101,52 +44,6 @@ struct Map {
 }
 
 impl Map {
-    fn side_coors(&self) -> (HashMap<Side, Local>, HashMap<Coor3, Side>) {
-        let start = self.start();
-
-        let start_side = self.coor_to_side(start);
-        let mut side_coors: HashMap<Side, Local> = HashMap::from([(
-            start_side,
-            Local::new(Coor3::new(1, 0, 0), Coor3::new(0, 1, 0)),
-        )]);
-        let right = |s: Side| Side(s.0 + 1, s.1);
-        let left = |s: Side| Side(s.0 - 1, s.1);
-        let up = |s: Side| Side(s.0, s.1 + 1);
-        for _ in 0..16 {
-            for side_y in 0..4 {
-                for side_x in 0..4 {
-                    let side = Side(side_x, -side_y);
-                    if side_coors.contains_key(&side) {
-                        continue;
-                    }
-
-                    match *self
-                        .tiles
-                        .get(&self.side_top_left(side))
-                        .unwrap_or(&Tile::Blank)
-                    {
-                        Tile::Blank => continue,
-                        Tile::Open | Tile::Wall => {}
-                    }
-                    if let Some(prev) = side_coors.get(&(right(side))) {
-                        side_coors.insert(side, prev.left());
-                    }
-                    if let Some(prev) = side_coors.get(&(left(side))) {
-                        side_coors.insert(side, prev.right());
-                    }
-                    if let Some(prev) = side_coors.get(&(up(side))) {
-                        side_coors.insert(side, prev.down());
-                    }
-                }
-            }
-        }
-        let coor_sides: HashMap<Coor3, Side> = side_coors
-            .iter()
-            .map(|(side, local)| (local.axis(), *side))
-            .collect();
-        (side_coors, coor_sides)
-    }
-
     fn start(&self) -> Coor {
         *self
             .tiles
@@ -157,6 +54,10 @@ impl Map {
             .0
     }
 
+    fn tile(&self, coor: Coor) -> Tile {
+        *self.tiles.get(&coor).unwrap_or(&Tile::Blank)
+    }
+
     fn wrap(&self, coor: Coor) -> Coor {
         let mut x = coor.x;
         let mut y = coor.y;
@@ -177,107 +78,26 @@ impl Map {
         Coor::new(x, y)
     }
 
-    fn wrap_cube(&self, coor: Coor, direction: Coor) -> (Coor, Coor) {
-        let side = self.coor_to_side(coor);
-        let (side_coors, coor_sides) = self.side_coors();
-        let side_local = side_coors.get(&side).unwrap();
-        let next = side_local.mv(direction);
-        let destination_side = *coor_sides.get(&next.axis()).unwrap();
-        let destination_local = side_coors.get(&destination_side).unwrap();
-        let destination_direction = destination_local.v_to_local(next.v_to_global(direction));
-        let global = self.c_to_global(coor);
-        let top_left = self.side_top_left(destination_side);
-
-        let find = |target: Coor3| -> Coor {
-            for x in [
-                0,
-                self.side_size() - 1,
-                target.x,
-                self.side_size() - 1 - target.x,
-                target.y,
-                self.side_size() - 1 - target.y,
-                target.z,
-                self.side_size() - 1 - target.z,
-            ] {
-                for y in [
-                    0,
-                    self.side_size() - 1,
-                    target.x,
-                    self.side_size() - 1 - target.x,
-                    target.y,
-                    self.side_size() - 1 - target.y,
-                    target.z,
-                    self.side_size() - 1 - target.z,
-                ] {
-                    let test = top_left + Coor::new(x, -y);
-                    if self.c_to_global(test) == target {
-                        return test;
-                    }
-                }
-            }
-            panic!("not found");
-        };
-        let destination_local = find(global);
-
-        (destination_local, destination_direction)
-    }
-
     fn find_opposite(&self, pos: Coor, dir: Coor) -> Coor {
         let mut next = self.wrap(pos + dir);
-        while *self.tiles.get(&next).unwrap_or(&Tile::Blank) == Tile::Blank {
+        while self.tile(next) == Tile::Blank {
             next = self.wrap(next + dir);
         }
         next
     }
 
-    fn c_to_global(&self, global_coor: Coor) -> Coor3 {
-        let coor = self.global_to_local(Coor::new(global_coor.x, -global_coor.y));
-        let side = self.coor_to_side(global_coor);
-        let (side_coors, _coor_sides) = self.side_coors();
-        let side_local = side_coors.get(&side).unwrap();
-        let axis = side_local.axis();
-
-        let side_size = self.side_size();
-        let _mul = |n: i64, vec: Coor3| {
-            // n * vec
-            if vec.x + vec.y + vec.z < 0 {
-                (side_size - 1 - n) * -vec
-            } else {
-                n * vec
-            }
-        };
-        _mul(coor.x, side_local.u) + _mul(coor.y, side_local.v) + _mul(side_size - 1, axis)
-    }
-
-    fn side_size(&self) -> i64 {
-        let x_size = self.max_x - self.min_x;
-        let y_size = self.max_y - self.min_y;
-        (x_size.max(y_size) + 1) / 4
+    fn cube(&self) -> Result<CubeNet> {
+        CubeNet::new(
+            self.tiles
+                .iter()
+                .filter(|&(_, t)| *t != Tile::Blank)
+                .map(|(c, _)| flip(*c)),
+        )
     }
 
-    fn coor_to_side(&self, coor: Coor) -> Side {
-        let side = coor / self.side_size();
-        Side(side.x, side.y)
-    }
-
-    fn global_to_local(&self, coor: Coor) -> Coor {
-        let side_size = self.side_size();
-        Coor::new(coor.x % side_size, side_size - 1 - (coor.y % side_size))
-    }
-
-    fn side_top_left(&self, side: Side) -> Coor {
-        let x_size = self.max_x - self.min_x;
-        let y_size = self.max_y - self.min_y;
-        let side_size = (x_size.max(y_size) + 1) / 4;
-        Coor::new(side.0, side.1) * side_size
-    }
-
-    fn _find_edge(&self, pos: Coor, dir: Coor) -> Coor {
-        let mut pos = pos;
-        while *self.tiles.get(&(pos + dir)).unwrap_or(&Tile::Blank) == Tile::Blank {
-            pos += dir;
-        }
-        pos
+    fn wrap_cube(&self, cube: &CubeNet, coor: Coor, direction: Coor) -> (Coor, Coor) {
+        let (coor, direction) = cube.step(flip(coor), flip(direction));
+        (flip(coor), flip(direction))
     }
 }
 
@@ -328,117 +148,41 @@ fn parse_moves(input: &str) -> (Vec<i64>, Vec<bool>) {
     (moves, turns)
 }
 
-fn part1(input: &str) -> Result<i64> {
-    let (raw_map, raw_moves) = input.split_once("\n\n").unwrap();
-    let map: Map = raw_map.parse()?;
-    let (moves, turns) = parse_moves(raw_moves);
-
-    let mut pos = map.start();
-
-    let mut dir = Coor::new(1, 0);
-    for (&mv, &turn) in moves.iter().zip(turns.iter()) {
-        for _ in 0..mv {
-            let next = pos + dir;
-            match *map.tiles.get(&next).unwrap_or(&Tile::Blank) {
-                Tile::Open => pos = next,
-                Tile::Wall => break,
-                Tile::Blank => {
-                    let next = map.find_opposite(pos, dir);
-                    if *map.tiles.get(&next).unwrap_or(&Tile::Blank) == Tile::Open {
-                        pos = next;
-                    }
-                }
-            }
-        }
-        if turn {
-            // R
-            // [0  -1]
-            // [1 0]
-            dir = Coor::new(dir.y, -dir.x);
-        } else {
-            // L
-            // [0 1]
-            // [-1  0]
-            dir = Coor::new(-dir.y, dir.x);
-        }
-    }
-
-    for _ in 0..moves[moves.len() - 1] {
-        let next = pos + dir;
-        match *map.tiles.get(&next).unwrap_or(&Tile::Blank) {
-            Tile::Open => pos = next,
-            Tile::Wall => break,
-            Tile::Blank => {
-                let next = map.find_opposite(pos, dir);
-                if *map.tiles.get(&next).unwrap_or(&Tile::Blank) == Tile::Open {
-                    pos = next;
-                }
-            }
-        }
+// with y pointing up, turning right is a clockwise quarter turn
+fn turn(dir: Coor, right: bool) -> Coor {
+    if right {
+        Coor::new(dir.y, -dir.x)
+    } else {
+        Coor::new(-dir.y, dir.x)
     }
-    let dir_score = match (dir.x, dir.y) {
-        (1, 0) => 0,
-        (0, -1) => 1,
-        (-1, 0) => 2,
-        (0, 1) => 3,
-        _ => unreachable!(),
-    };
-    Ok((-pos.y + 1) * 1000 + (pos.x + 1) * 4 + dir_score)
 }
 
-fn part2(input: &str) -> Result<i64> {
-    let (raw_map, raw_moves) = input.split_once("\n\n").unwrap();
+// follows the path, calling `wrap` with the position and direction whenever
+// the next step would leave the map, and returns the password
+fn walk(input: &str, wrap: impl Fn(&Map, Coor, Coor) -> (Coor, Coor)) -> Result<i64> {
+    let (raw_map, raw_moves) = input.split_once("\n\n").context("no moves")?;
     let map: Map = raw_map.parse()?;
     let (moves, turns) = parse_moves(raw_moves);
 
     let mut pos = map.start();
-
     let mut dir = Coor::new(1, 0);
-    for (&mv, &turn) in moves.iter().zip(turns.iter()) {
+    for (idx, &mv) in moves.iter().enumerate() {
         for _ in 0..mv {
-            let next = pos + dir;
-            match *map.tiles.get(&next).unwrap_or(&Tile::Blank) {
-                Tile::Open => pos = next,
-                Tile::Wall => break,
-                Tile::Blank => {
-                    let (next, next_dir) = map.wrap_cube(pos, dir);
-                    if *map.tiles.get(&next).unwrap_or(&Tile::Blank) == Tile::Open {
-                        pos = next;
-                        dir = next_dir;
-                    }
-                }
+            let (next, next_dir) = match map.tile(pos + dir) {
+                Tile::Blank => wrap(&map, pos, dir),
+                _ => (pos + dir, dir),
+            };
+            if map.tile(next) == Tile::Wall {
+                break;
             }
+            pos = next;
+            dir = next_dir;
         }
-        if turn {
-            // R
-            // [0  -1]
-            // [1 0]
-            dir = Coor::new(dir.y, -dir.x);
-            // dir =
-            // directions[(directions.iter().position(|d| d == &dir).unwrap() + 1).rem_euclid(4)];
-        } else {
-            // L
-            // [0 1]
-            // [-1  0]
-            dir = Coor::new(-dir.y, dir.x);
-            // dir =
-            // directions[(directions.iter().position(|d| d == &dir).unwrap() + 3).rem_euclid(4)];
+        if let Some(&right) = turns.get(idx) {
+            dir = turn(dir, right);
         }
     }
 
-    for _ in 0..moves[moves.len() - 1] {
-        let next = pos + dir;
-        match *map.tiles.get(&next).unwrap_or(&Tile::Blank) {
-            Tile::Open => pos = next,
-            Tile::Wall => break,
-            Tile::Blank => {
-                let next = map.find_opposite(pos, dir);
-                if *map.tiles.get(&next).unwrap_or(&Tile::Blank) == Tile::Open {
-                    pos = next;
-                }
-            }
-        }
-    }
     let dir_score = match (dir.x, dir.y) {
         (1, 0) => 0,
         (0, -1) => 1,
@@ -449,6 +193,16 @@ fn part2(input: &str) -> Result<i64> {
     Ok((-pos.y + 1) * 1000 + (pos.x + 1) * 4 + dir_score)
 }
 
+fn part1(input: &str) -> Result<i64> {
+    walk(input, |map, pos, dir| (map.find_opposite(pos, dir), dir))
+}
+
+fn part2(input: &str) -> Result<i64> {
+    let (raw_map, _) = input.split_once("\n\n").context("no moves")?;
+    let cube = raw_map.parse::<Map>()?.cube()?;
+    walk(input, |map, pos, dir| map.wrap_cube(&cube, pos, dir))
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -476,58 +230,27 @@ mod tests {
     // (map.find_opposite(Coor::new(5, 4), Coor::new(0, -1)));
     // (map.find_opposite(Coor::new(0, 6), Coor::new(-1, 0)));
 
-    #[test]
-    fn test_axes() {
-        let x = Coor3::new(1, 0, 0);
-        let y = Coor3::new(0, 1, 0);
-        let z = Coor3::new(0, 0, 1);
-        let front = Local { u: x, v: y };
-        let right = Local { u: -z, v: y };
-        let left = Local { u: z, v: y };
-        let down = Local { u: x, v: z };
-        let up = Local { u: x, v: -z };
-        assert_eq!(front.right(), right);
-        assert_eq!(front.left(), left);
-        assert_eq!(front.down(), down);
-        assert_eq!(front.up(), up);
-    }
-
     #[test]
     fn test_wrap_cube() -> Result<()> {
         let (raw_map, _raw_moves) = TEST_INPUT.split_once("\n\n").unwrap();
         let map: Map = raw_map.parse()?;
+        let cube = map.cube()?;
 
         assert_eq!(
-            map.wrap_cube(Coor::new(11, -5), Coor::new(1, 0)),
+            map.wrap_cube(&cube, Coor::new(11, -5), Coor::new(1, 0)),
             (Coor::new(14, -8), Coor::new(0, -1))
         );
         assert_eq!(
-            map.wrap_cube(Coor::new(10, -11), Coor::new(0, -1)),
+            map.wrap_cube(&cube, Coor::new(10, -11), Coor::new(0, -1)),
             (Coor::new(1, -7), Coor::new(0, 1))
         );
         assert_eq!(
-            map.wrap_cube(Coor::new(6, -4), Coor::new(0, 1)),
+            map.wrap_cube(&cube, Coor::new(6, -4), Coor::new(0, 1)),
             (Coor::new(8, -2), Coor::new(1, 0))
         );
         Ok(())
     }
 
-    #[test]
-    fn test_c_to_global() -> Result<()> {
-        let (raw_map, _raw_moves) = TEST_INPUT.split_once("\n\n").unwrap();
-        let map: Map = raw_map.parse()?;
-
-        assert_eq!(map.c_to_global(Coor::new(8, 0)), Coor3::new(0, 3, 3));
-        assert_eq!(map.c_to_global(Coor::new(11, -3)), Coor3::new(3, 0, 3));
-        assert_eq!(map.c_to_global(Coor::new(11, -4)), Coor3::new(3, 0, 3));
-        assert_eq!(map.c_to_global(Coor::new(11, -5)), Coor3::new(3, 0, 2));
-        assert_eq!(map.c_to_global(Coor::new(14, -8)), Coor3::new(3, 0, 2));
-        assert_eq!(map.c_to_global(Coor::new(10, -11)), Coor3::new(2, 3, 0));
-        assert_eq!(map.c_to_global(Coor::new(4, -5)), Coor3::new(0, 3, 2));
-        assert_eq!(map.c_to_global(Coor::new(3, -5)), Coor3::new(0, 3, 2));
-        Ok(())
-    }
-
     #[test]
     fn test_part1() -> Result<()> {
         assert_eq!(part1(TEST_INPUT)?, 6032);
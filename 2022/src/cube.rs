@@ -0,0 +1,375 @@
+// folds a flat cube net (any of the 11) into a cube and stitches its edges,
+// so a walk that steps off the net carries on from the matching cell of the
+// face it folds onto. coordinates are screen-like: x to the right, y down
+use crate::coor::Coor;
+use crate::coor3::Coor3;
+use anyhow::{bail, Result};
+use std::collections::{HashMap, HashSet, VecDeque};
+
+pub const RIGHT: Coor = Coor::new(1, 0);
+pub const DOWN: Coor = Coor::new(0, 1);
+pub const LEFT: Coor = Coor::new(-1, 0);
+pub const UP: Coor = Coor::new(0, -1);
+
+pub const DIRECTIONS: [Coor; 4] = [RIGHT, DOWN, LEFT, UP];
+
+// where a face's net directions point once folded, and which way it faces
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Frame {
+    pub right: Coor3,
+    pub down: Coor3,
+    pub normal: Coor3,
+}
+
+impl Frame {
+    // the frame of the face next to this one in the net, folded over the
+    // shared edge
+    pub fn roll(&self, dir: Coor) -> Frame {
+        let Frame {
+            right,
+            down,
+            normal,
+        } = *self;
+        match (dir.x, dir.y) {
+            (1, 0) => Frame {
+                right: -normal,
+                down,
+                normal: right,
+            },
+            (-1, 0) => Frame {
+                right: normal,
+                down,
+                normal: -right,
+            },
+            (0, 1) => Frame {
+                right,
+                down: -normal,
+                normal: down,
+            },
+            (0, -1) => Frame {
+                right,
+                down: normal,
+                normal: -down,
+            },
+            _ => panic!("invalid direction {:?}", dir),
+        }
+    }
+
+    fn to_space(self, dir: Coor) -> Coor3 {
+        dir.x * self.right + dir.y * self.down
+    }
+
+    fn to_net(self, dir: Coor3) -> Coor {
+        Coor::new(dir.dot(self.right), dir.dot(self.down))
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Face {
+    // the face's top left cell in the net
+    pub origin: Coor,
+    pub frame: Frame,
+}
+
+#[derive(Debug)]
+pub struct CubeNet {
+    size: i64,
+    min: Coor,
+    faces: Vec<Face>,
+    // face index by position in the grid of faces
+    grid: HashMap<Coor, usize>,
+    edges: HashMap<(Coor, Coor), (Coor, Coor)>,
+}
+
+fn grid_neighbours(grid_pos: Coor) -> impl Iterator<Item = (Coor, Coor)> {
+    DIRECTIONS.into_iter().map(move |dir| (dir, grid_pos + dir))
+}
+
+impl CubeNet {
+    // `cells` are the positions that are part of the net
+    pub fn new(cells: impl IntoIterator<Item = Coor>) -> Result<Self> {
+        let cells: HashSet<Coor> = cells.into_iter().collect();
+        let per_face = cells.len() as i64 / 6;
+        let size = (1..).find(|s| s * s >= per_face).unwrap_or(0);
+        if cells.is_empty() || size * size * 6 != cells.len() as i64 {
+            bail!("{} cells can't make six square faces", cells.len());
+        }
+        let min = Coor::new(
+            cells.iter().map(|c| c.x).min().unwrap(),
+            cells.iter().map(|c| c.y).min().unwrap(),
+        );
+        let grid_positions: HashSet<Coor> = cells
+            .iter()
+            .map(|&c| {
+                let offset = c - min;
+                Coor::new(offset.x.div_euclid(size), offset.y.div_euclid(size))
+            })
+            .collect();
+        if grid_positions.len() != 6 {
+            bail!(
+                "cells don't line up into six {}x{} faces (found {})",
+                size,
+                size,
+                grid_positions.len()
+            );
+        }
+
+        // fold outwards from the first face, in reading order
+        let first = *grid_positions.iter().min_by_key(|c| (c.y, c.x)).unwrap();
+        let mut frames = HashMap::from([(
+            first,
+            Frame {
+                right: Coor3::new(1, 0, 0),
+                down: Coor3::new(0, 1, 0),
+                normal: Coor3::new(0, 0, 1),
+            },
+        )]);
+        let mut queue = VecDeque::from([first]);
+        while let Some(grid_pos) = queue.pop_front() {
+            let frame = frames[&grid_pos];
+            for (dir, next) in grid_neighbours(grid_pos) {
+                if grid_positions.contains(&next) && !frames.contains_key(&next) {
+                    frames.insert(next, frame.roll(dir));
+                    queue.push_back(next);
+                }
+            }
+        }
+        if frames.len() != 6 {
+            bail!("faces are not all connected");
+        }
+        let normals: HashSet<Coor3> = frames.values().map(|f| f.normal).collect();
+        if normals.len() != 6 {
+            bail!("not a cube net: faces overlap when folded");
+        }
+
+        let mut grid_order: Vec<Coor> = grid_positions.into_iter().collect();
+        grid_order.sort_by_key(|c| (c.y, c.x));
+        let faces: Vec<Face> = grid_order
+            .iter()
+            .map(|g| Face {
+                origin: min + *g * size,
+                frame: frames[g],
+            })
+            .collect();
+        let grid = grid_order
+            .iter()
+            .enumerate()
+            .map(|(i, g)| (*g, i))
+            .collect();
+
+        let mut net = CubeNet {
+            size,
+            min,
+            faces,
+            grid,
+            edges: HashMap::new(),
+        };
+        net.edges = net.stitch();
+        Ok(net)
+    }
+
+    pub fn size(&self) -> i64 {
+        self.size
+    }
+
+    pub fn faces(&self) -> &[Face] {
+        &self.faces
+    }
+
+    pub fn face(&self, pos: Coor) -> Option<&Face> {
+        let offset = pos - self.min;
+        let grid_pos = Coor::new(
+            offset.x.div_euclid(self.size),
+            offset.y.div_euclid(self.size),
+        );
+        self.grid.get(&grid_pos).map(|&i| &self.faces[i])
+    }
+
+    // (position, direction) stepping off the net, to the (position, direction)
+    // it arrives at on the folded cube
+    pub fn edges(&self) -> &HashMap<(Coor, Coor), (Coor, Coor)> {
+        &self.edges
+    }
+
+    pub fn step(&self, pos: Coor, dir: Coor) -> (Coor, Coor) {
+        match self.edges.get(&(pos, dir)) {
+            Some(&destination) => destination,
+            None => (pos + dir, dir),
+        }
+    }
+
+    fn stitch(&self) -> HashMap<(Coor, Coor), (Coor, Coor)> {
+        let mut edges = HashMap::new();
+        for face in &self.faces {
+            for dir in DIRECTIONS {
+                for i in 0..self.size {
+                    let pos = face.origin + self.edge_cell(dir, i);
+                    if self.face(pos + dir).is_none() {
+                        edges.insert((pos, dir), self.fold_across(face, pos, dir));
+                    }
+                }
+            }
+        }
+        edges
+    }
+
+    // the i-th cell along a face's edge in direction `dir`, relative to its
+    // origin
+    fn edge_cell(&self, dir: Coor, i: i64) -> Coor {
+        let last = self.size - 1;
+        match (dir.x, dir.y) {
+            (1, 0) => Coor::new(last, i),
+            (-1, 0) => Coor::new(0, i),
+            (0, 1) => Coor::new(i, last),
+            _ => Coor::new(i, 0),
+        }
+    }
+
+    // cells are placed in space at their centres, doubled to stay integral:
+    // a face's cells sit at `size` along its normal
+    fn fold_across(&self, face: &Face, pos: Coor, dir: Coor) -> (Coor, Coor) {
+        let size = self.size;
+        let local = pos - face.origin;
+        let frame = face.frame;
+        let point = size * frame.normal
+            + (2 * local.x - size + 1) * frame.right
+            + (2 * local.y - size + 1) * frame.down;
+        let out = frame.to_space(dir);
+        let destination = self
+            .faces
+            .iter()
+            .find(|f| f.frame.normal == out)
+            .expect("every normal has a face");
+        let point = point + out - frame.normal;
+        let to = destination.frame;
+        let local = Coor::new(
+            (point.dot(to.right) + size - 1) / 2,
+            (point.dot(to.down) + size - 1) / 2,
+        );
+        (destination.origin + local, to.to_net(-frame.normal))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // the 11 nets, one cell per face
+    const NETS: [&str; 11] = [
+        "#...\n####\n#...",
+        "#...\n####\n.#..",
+        "#...\n####\n..#.",
+        "#...\n####\n...#",
+        ".#..\n####\n.#..",
+        ".#..\n####\n..#.",
+        "##..\n.###\n.#..",
+        "##..\n.###\n..#.",
+        "##..\n.###\n...#",
+        "##..\n.##.\n..##",
+        "###..\n..###",
+    ];
+
+    fn cells(net: &str, size: i64) -> Vec<Coor> {
+        let mut cells = vec![];
+        for (y, line) in net.lines().enumerate() {
+            for (x, c) in line.chars().enumerate() {
+                if c == '#' {
+                    for dy in 0..size {
+                        for dx in 0..size {
+                            cells.push(Coor::new(x as i64 * size + dx, y as i64 * size + dy));
+                        }
+                    }
+                }
+            }
+        }
+        cells
+    }
+
+    fn reverse(dir: Coor) -> Coor {
+        dir * -1
+    }
+
+    #[test]
+    fn test_roll() {
+        let x = Coor3::new(1, 0, 0);
+        let y = Coor3::new(0, 1, 0);
+        let z = Coor3::new(0, 0, 1);
+        let front = Frame {
+            right: x,
+            down: y,
+            normal: z,
+        };
+        let right = front.roll(RIGHT);
+        assert_eq!((right.right, right.down, right.normal), (-z, y, x));
+        assert_eq!(front.roll(LEFT).normal, -x);
+        assert_eq!(front.roll(DOWN).normal, y);
+        assert_eq!(front.roll(UP).normal, -y);
+        for dir in DIRECTIONS {
+            assert_eq!(front.roll(dir).roll(reverse(dir)), front);
+        }
+    }
+
+    #[test]
+    fn test_all_nets() -> Result<()> {
+        for net in NETS {
+            for size in [1, 3] {
+                let cube = CubeNet::new(cells(net, size))?;
+                assert_eq!(cube.size(), size);
+                // 24 face edges, of which 5 pairs are joined in the net
+                assert_eq!(cube.edges().len() as i64, 14 * size, "{}", net);
+                for (&(pos, dir), &(to, to_dir)) in cube.edges() {
+                    assert!(cube.face(to).is_some());
+                    assert_eq!(
+                        cube.step(to, reverse(to_dir)),
+                        (pos, reverse(dir)),
+                        "{}",
+                        net
+                    );
+                }
+                // going straight ahead goes round the cube and back
+                for pos in cells(net, size) {
+                    for dir in DIRECTIONS {
+                        let (mut p, mut d) = (pos, dir);
+                        for _ in 0..4 * size {
+                            (p, d) = cube.step(p, d);
+                        }
+                        assert_eq!((p, d), (pos, dir), "{} from {:?}", net, pos);
+                    }
+                }
+            }
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn test_example() -> Result<()> {
+        let cube = CubeNet::new(cells("..#.\n###.\n..##", 4))?;
+        assert_eq!(cube.step(Coor::new(11, 5), RIGHT), (Coor::new(14, 8), DOWN));
+        assert_eq!(cube.step(Coor::new(10, 11), DOWN), (Coor::new(1, 7), UP));
+        assert_eq!(cube.step(Coor::new(6, 4), UP), (Coor::new(8, 2), RIGHT));
+        Ok(())
+    }
+
+    #[test]
+    fn test_real_layout() -> Result<()> {
+        let cube = CubeNet::new(cells(".##\n.#.\n##.\n#..", 50))?;
+        assert_eq!(cube.step(Coor::new(50, 0), UP), (Coor::new(0, 150), RIGHT));
+        assert_eq!(
+            cube.step(Coor::new(149, 0), RIGHT),
+            (Coor::new(99, 149), LEFT)
+        );
+        assert_eq!(
+            cube.step(Coor::new(50, 60), LEFT),
+            (Coor::new(10, 100), DOWN)
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_invalid() {
+        let err = |net: &str| CubeNet::new(cells(net, 2)).unwrap_err().to_string();
+        assert_eq!(err("#####"), "20 cells can't make six square faces");
+        assert_eq!(err("###\n###"), "not a cube net: faces overlap when folded");
+        assert_eq!(err("###.#\n..#.#"), "faces are not all connected");
+    }
+}
@@ -1,4 +1,7 @@
+pub mod algebra;
 pub mod coor;
 pub mod coor3;
+pub mod cube;
+pub mod packet;
 pub mod shared;
 pub use crate::shared::dispatch;
@@ -0,0 +1,302 @@
+// day 13's distress signal packets: nested lists of integers, ordered by
+// comparing entries in turn and wrapping a lone integer in a list when it meets
+// one. they parse from and print as the puzzle text, and convert to and from
+// JSON values
+use anyhow::{bail, Context, Error, Result};
+use serde_json::Value;
+use std::cmp::Ordering;
+use std::fmt;
+use std::str::FromStr;
+
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub enum Packet {
+    List(Vec<Packet>),
+    Integer(i32),
+}
+
+struct Parser<'a> {
+    chars: &'a [u8],
+    pos: usize,
+}
+
+impl Parser<'_> {
+    fn peek(&self) -> Option<char> {
+        self.chars.get(self.pos).map(|&b| b as char)
+    }
+
+    fn skip_whitespace(&mut self) {
+        while self.peek().is_some_and(|c| c.is_ascii_whitespace()) {
+            self.pos += 1;
+        }
+    }
+
+    fn column(&self) -> usize {
+        self.pos + 1
+    }
+
+    fn found(&self) -> String {
+        match self.peek() {
+            Some(c) => format!("`{}`", c),
+            None => "end of input".to_string(),
+        }
+    }
+
+    fn parse_number(&mut self) -> Result<i32> {
+        let start = self.pos;
+        if self.peek() == Some('-') {
+            self.pos += 1;
+        }
+        while self.peek().is_some_and(|c| c.is_ascii_digit()) {
+            self.pos += 1;
+        }
+        let digits = std::str::from_utf8(&self.chars[start..self.pos])?;
+        digits
+            .parse()
+            .with_context(|| format!("column {}: invalid number `{}`", start + 1, digits))
+    }
+
+    fn parse(&mut self) -> Result<Packet> {
+        match self.peek() {
+            Some('[') => {}
+            Some('0'..='9' | '-') => return Ok(Packet::Integer(self.parse_number()?)),
+            _ => bail!(
+                "column {}: expected `[` or a number, found {}",
+                self.column(),
+                self.found()
+            ),
+        }
+        let open = self.column();
+        self.pos += 1;
+        let mut list = vec![];
+        if self.peek() == Some(']') {
+            self.pos += 1;
+            return Ok(Packet::List(list));
+        }
+        loop {
+            list.push(self.parse()?);
+            match self.peek() {
+                Some(',') => self.pos += 1,
+                Some(']') => {
+                    self.pos += 1;
+                    return Ok(Packet::List(list));
+                }
+                _ => bail!(
+                    "column {}: expected `,` or `]` to close column {}, found {}",
+                    self.column(),
+                    open,
+                    self.found()
+                ),
+            }
+        }
+    }
+}
+
+impl FromStr for Packet {
+    type Err = Error;
+    fn from_str(s: &str) -> Result<Self> {
+        // columns count from the start of `s`, surrounding whitespace and all
+        let mut parser = Parser {
+            chars: s.as_bytes(),
+            pos: 0,
+        };
+        parser.skip_whitespace();
+        let packet = parser.parse()?;
+        parser.skip_whitespace();
+        if parser.peek().is_some() {
+            bail!(
+                "column {}: unexpected {} after packet",
+                parser.column(),
+                parser.found()
+            );
+        }
+        Ok(packet)
+    }
+}
+
+impl fmt::Display for Packet {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Packet::Integer(n) => write!(f, "{}", n),
+            Packet::List(list) => {
+                write!(f, "[")?;
+                for (idx, entry) in list.iter().enumerate() {
+                    if idx > 0 {
+                        write!(f, ",")?;
+                    }
+                    write!(f, "{}", entry)?;
+                }
+                write!(f, "]")
+            }
+        }
+    }
+}
+
+impl From<&Packet> for Value {
+    fn from(packet: &Packet) -> Self {
+        match packet {
+            Packet::Integer(n) => Value::from(*n),
+            Packet::List(list) => Value::Array(list.iter().map(Value::from).collect()),
+        }
+    }
+}
+
+impl TryFrom<&Value> for Packet {
+    type Error = Error;
+    fn try_from(value: &Value) -> Result<Self> {
+        Ok(match value {
+            Value::Array(list) => Packet::List(
+                list.iter()
+                    .enumerate()
+                    .map(|(idx, entry)| {
+                        Packet::try_from(entry).with_context(|| format!("in entry {}", idx))
+                    })
+                    .collect::<Result<_>>()?,
+            ),
+            Value::Number(n) => Packet::Integer(
+                n.as_i64()
+                    .and_then(|n| i32::try_from(n).ok())
+                    .with_context(|| format!("`{}` is not a 32 bit integer", n))?,
+            ),
+            other => bail!("`{}` is neither a list nor an integer", other),
+        })
+    }
+}
+
+impl Ord for Packet {
+    fn cmp(&self, other: &Self) -> Ordering {
+        match (self, other) {
+            (Packet::List(self_list), Packet::List(other_list)) => {
+                for (self_entry, other_entry) in self_list.iter().zip(other_list) {
+                    match self_entry.cmp(other_entry) {
+                        Ordering::Equal => continue,
+                        o => return o,
+                    }
+                }
+                self_list.len().cmp(&other_list.len())
+            }
+
+            (Packet::List(_), Packet::Integer(_)) => self.cmp(&Packet::List(vec![other.clone()])),
+            (Packet::Integer(_), Packet::List(_)) => Packet::List(vec![self.clone()]).cmp(other),
+
+            (Packet::Integer(self_int), Packet::Integer(other_int)) => self_int.cmp(other_int),
+        }
+    }
+}
+
+impl PartialOrd for Packet {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const PACKETS: [&str; 6] = [
+        "[1,1,3,1,1]",
+        "[[1],[2,3,4]]",
+        "[[8,7,6]]",
+        "[]",
+        "[[[]]]",
+        "[1,[2,[3,[4,[5,6,0]]]],8,9]",
+    ];
+
+    #[test]
+    fn test_parse1() -> Result<()> {
+        assert_eq!(
+            "[1]".parse::<Packet>()?,
+            Packet::List(vec![Packet::Integer(1)])
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_parse2() -> Result<()> {
+        assert_eq!("[]".parse::<Packet>()?, Packet::List(vec![]));
+        Ok(())
+    }
+
+    #[test]
+    fn test_parse3() -> Result<()> {
+        assert_eq!(
+            "[1,2]".parse::<Packet>()?,
+            Packet::List(vec![Packet::Integer(1), Packet::Integer(2)])
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_parse4() -> Result<()> {
+        assert_eq!(
+            "[[1],[2,3,4]]".parse::<Packet>()?,
+            Packet::List(vec![
+                Packet::List(vec![Packet::Integer(1)]),
+                Packet::List(vec![
+                    Packet::Integer(2),
+                    Packet::Integer(3),
+                    Packet::Integer(4),
+                ])
+            ])
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_display() -> Result<()> {
+        for line in PACKETS {
+            assert_eq!(line.parse::<Packet>()?.to_string(), line);
+        }
+        assert_eq!("[-1,2]".parse::<Packet>()?.to_string(), "[-1,2]");
+        Ok(())
+    }
+
+    #[test]
+    fn test_parse_errors() {
+        let err = |s: &str| s.parse::<Packet>().unwrap_err().to_string();
+        assert_eq!(
+            err("[1,2"),
+            "column 5: expected `,` or `]` to close column 1, found end of input"
+        );
+        assert_eq!(
+            err("[[1],x]"),
+            "column 6: expected `[` or a number, found `x`"
+        );
+        assert_eq!(err("[1]]"), "column 4: unexpected `]` after packet");
+        assert_eq!(err("[1,]"), "column 4: expected `[` or a number, found `]`");
+        assert_eq!(
+            err("[1;2]"),
+            "column 3: expected `,` or `]` to close column 1, found `;`"
+        );
+        assert_eq!(
+            err("[99999999999]"),
+            "column 2: invalid number `99999999999`"
+        );
+        // columns are positions in the untrimmed string
+        assert_eq!(
+            err("  [1,"),
+            "column 6: expected `[` or a number, found end of input"
+        );
+        assert_eq!(err(" [1] 2 "), "column 6: unexpected `2` after packet");
+    }
+
+    #[test]
+    fn test_json() -> Result<()> {
+        for line in PACKETS {
+            let packet: Packet = line.parse()?;
+            let value: Value = serde_json::from_str(line)?;
+            assert_eq!(Value::from(&packet), value);
+            assert_eq!(Packet::try_from(&value)?, packet);
+        }
+        let err = |s: &str| {
+            let value: Value = serde_json::from_str(s).unwrap();
+            format!("{:#}", Packet::try_from(&value).unwrap_err())
+        };
+        assert_eq!(
+            err(r#"[1,[2,"x"]]"#),
+            r#"in entry 1: in entry 1: `"x"` is neither a list nor an integer"#
+        );
+        assert_eq!(err("[1.5]"), "in entry 0: `1.5` is not a 32 bit integer");
+        Ok(())
+    }
+}
@@ -0,0 +1,368 @@
+// a small framework for the assembly-style puzzles: a day declares its
+// instruction set by implementing `Instruction`, and the `Machine` takes care
+// of the program counter, cycle counting, loop detection and step hooks
+//
+// it has no dependencies, so that every year can use it whichever error
+// handling crate it's built on: `ParseError` converts into either
+use std::borrow::Borrow;
+use std::collections::{BTreeMap, HashSet};
+use std::error::Error;
+use std::fmt::{self, Display};
+use std::str::FromStr;
+
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum Flow {
+    Next,
+    // relative to the current instruction
+    Jump(i64),
+    Halt,
+}
+
+pub trait Instruction {
+    type State;
+
+    fn execute(&self, state: &mut Self::State) -> Flow;
+
+    // how many cycles the instruction takes. the state only changes at the
+    // end of the last one
+    fn cycles(&self) -> usize {
+        1
+    }
+}
+
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum Outcome {
+    // ran off either end of the program, or executed a `Flow::Halt`
+    Halted,
+    // was about to run the instruction at `pc` for a second time
+    Repeated(usize),
+}
+
+// passed to step hooks once per cycle, with the state as it is *during* the
+// cycle
+pub struct Tick<'a, S> {
+    // starts at 1
+    pub cycle: usize,
+    pub pc: usize,
+    pub state: &'a S,
+}
+
+pub struct Machine<I: Instruction> {
+    pub program: Vec<I>,
+    pub state: I::State,
+    pub pc: i64,
+    pub cycles: usize,
+}
+
+impl<I: Instruction> Machine<I> {
+    pub fn new(program: Vec<I>, state: I::State) -> Self {
+        Machine {
+            program,
+            state,
+            pc: 0,
+            cycles: 0,
+        }
+    }
+
+    fn current(&self) -> Option<usize> {
+        if self.pc >= 0 && (self.pc as usize) < self.program.len() {
+            Some(self.pc as usize)
+        } else {
+            None
+        }
+    }
+
+    // executes a single instruction, returning whether the machine is still
+    // running
+    pub fn step_with(&mut self, mut hook: impl FnMut(Tick<I::State>)) -> bool {
+        let pc = match self.current() {
+            Some(pc) => pc,
+            None => return false,
+        };
+        let instruction = &self.program[pc];
+        for _ in 0..instruction.cycles() {
+            self.cycles += 1;
+            hook(Tick {
+                cycle: self.cycles,
+                pc,
+                state: &self.state,
+            });
+        }
+        match instruction.execute(&mut self.state) {
+            Flow::Next => self.pc += 1,
+            Flow::Jump(offset) => self.pc += offset,
+            Flow::Halt => {
+                self.pc = self.program.len() as i64;
+                return false;
+            }
+        }
+        self.current().is_some()
+    }
+
+    pub fn step(&mut self) -> bool {
+        self.step_with(|_| {})
+    }
+
+    pub fn run_with(&mut self, mut hook: impl FnMut(Tick<I::State>)) -> Outcome {
+        while self.step_with(&mut hook) {}
+        Outcome::Halted
+    }
+
+    pub fn run(&mut self) -> Outcome {
+        self.run_with(|_| {})
+    }
+
+    // like `run`, but stops instead of running any instruction twice. only
+    // a sure sign of an infinite loop for programs without conditional jumps
+    pub fn run_until_repeat(&mut self) -> Outcome {
+        let mut seen = HashSet::new();
+        while let Some(pc) = self.current() {
+            if !seen.insert(pc) {
+                return Outcome::Repeated(pc);
+            }
+            if !self.step() {
+                break;
+            }
+        }
+        Outcome::Halted
+    }
+}
+
+impl<I: Instruction> Machine<I>
+where
+    I::State: Display,
+{
+    pub fn dump(&self) -> String {
+        format!("pc={} cycles={} {}", self.pc, self.cycles, self.state)
+    }
+}
+
+// named registers, all starting at 0
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Registers<K: Ord> {
+    values: BTreeMap<K, i64>,
+}
+
+impl<K: Ord> Default for Registers<K> {
+    fn default() -> Self {
+        Registers {
+            values: BTreeMap::new(),
+        }
+    }
+}
+
+// takes registers by reference, so they can be anything that can be looked
+// up in a map, e.g. `char`s or `String`s
+impl<K: Ord> Registers<K> {
+    pub fn get<Q>(&self, register: &Q) -> i64
+    where
+        K: Borrow<Q>,
+        Q: Ord + ?Sized,
+    {
+        self.values.get(register).copied().unwrap_or(0)
+    }
+
+    pub fn set<Q>(&mut self, register: &Q, value: i64)
+    where
+        K: Borrow<Q>,
+        Q: Ord + ToOwned<Owned = K> + ?Sized,
+    {
+        self.update(register, |_| value);
+    }
+
+    pub fn update<Q>(&mut self, register: &Q, f: impl FnOnce(i64) -> i64)
+    where
+        K: Borrow<Q>,
+        Q: Ord + ToOwned<Owned = K> + ?Sized,
+    {
+        match self.values.get_mut(register) {
+            Some(value) => *value = f(*value),
+            None => {
+                self.values.insert(register.to_owned(), f(0));
+            }
+        }
+    }
+
+    pub fn max(&self) -> Option<i64> {
+        self.values.values().copied().max()
+    }
+}
+
+impl<K: Ord + Display> Display for Registers<K> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let mut first = true;
+        for (register, value) in &self.values {
+            if !first {
+                write!(f, " ")?;
+            }
+            first = false;
+            write!(f, "{}={}", register, value)?;
+        }
+        Ok(())
+    }
+}
+
+// splits `jio a, +2` into `("jio", ["a", "+2"])`
+pub fn split(line: &str) -> (&str, Vec<&str>) {
+    let mut parts = line
+        .split(|c: char| c.is_whitespace() || c == ',')
+        .filter(|part| !part.is_empty());
+    let mnemonic = parts.next().unwrap_or("");
+    (mnemonic, parts.collect())
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ParseError {
+    // starts at 1
+    pub line: usize,
+    pub text: String,
+    pub message: String,
+}
+
+impl Display for ParseError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "line {}: `{}`: {}", self.line, self.text, self.message)
+    }
+}
+
+impl Error for ParseError {}
+
+pub fn parse_program<I>(input: &str) -> Result<Vec<I>, ParseError>
+where
+    I: FromStr,
+    I::Err: Display,
+{
+    input
+        .lines()
+        .enumerate()
+        .filter(|(_, line)| !line.trim().is_empty())
+        .map(|(index, line)| {
+            line.trim().parse::<I>().map_err(|err| ParseError {
+                line: index + 1,
+                text: line.trim().to_string(),
+                message: err.to_string(),
+            })
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[derive(Debug)]
+    enum Test {
+        Inc(char),
+        Jnz(char, i64),
+        Slow,
+        Stop,
+    }
+
+    impl FromStr for Test {
+        type Err = String;
+
+        fn from_str(s: &str) -> Result<Self, String> {
+            let register = |arg: &str| arg.chars().next().ok_or("no register");
+            let offset = |arg: &str| arg.parse().map_err(|_| "invalid offset");
+            Ok(match split(s) {
+                ("inc", args) if args.len() == 1 => Test::Inc(register(args[0])?),
+                ("jnz", args) if args.len() == 2 => Test::Jnz(register(args[0])?, offset(args[1])?),
+                ("slow", _) => Test::Slow,
+                ("stop", _) => Test::Stop,
+                _ => return Err("invalid instruction".to_string()),
+            })
+        }
+    }
+
+    impl Instruction for Test {
+        type State = Registers<char>;
+
+        fn execute(&self, state: &mut Self::State) -> Flow {
+            match *self {
+                Test::Inc(register) => state.update(&register, |v| v + 1),
+                Test::Jnz(register, offset) if state.get(&register) != 0 => {
+                    return Flow::Jump(offset)
+                }
+                Test::Jnz(..) | Test::Slow => {}
+                Test::Stop => return Flow::Halt,
+            }
+            Flow::Next
+        }
+
+        fn cycles(&self) -> usize {
+            match self {
+                Test::Slow => 3,
+                _ => 1,
+            }
+        }
+    }
+
+    #[test]
+    fn test_split() {
+        assert_eq!(split("jio a, +2"), ("jio", vec!["a", "+2"]));
+        assert_eq!(split("noop"), ("noop", vec![]));
+    }
+
+    #[test]
+    fn test_parse_error() {
+        let err = parse_program::<Test>("inc a\nfoo").unwrap_err();
+        assert_eq!(err.to_string(), "line 2: `foo`: invalid instruction");
+        let err = parse_program::<Test>("jnz a x").unwrap_err();
+        assert_eq!(
+            err,
+            ParseError {
+                line: 1,
+                text: "jnz a x".to_string(),
+                message: "invalid offset".to_string(),
+            }
+        );
+    }
+
+    #[test]
+    fn test_run() -> Result<(), ParseError> {
+        let mut machine = Machine::new(
+            parse_program::<Test>("inc a\nslow\ninc b\nstop\ninc a")?,
+            Registers::default(),
+        );
+        let mut ticks = vec![];
+        assert_eq!(
+            machine.run_with(|tick| ticks.push((tick.cycle, tick.pc, tick.state.get(&'b')))),
+            Outcome::Halted
+        );
+        assert_eq!(
+            ticks,
+            vec![
+                (1, 0, 0),
+                (2, 1, 0),
+                (3, 1, 0),
+                (4, 1, 0),
+                (5, 2, 0),
+                (6, 3, 1)
+            ]
+        );
+        assert_eq!(machine.dump(), "pc=5 cycles=6 a=1 b=1");
+        Ok(())
+    }
+
+    #[test]
+    fn test_run_until_repeat() -> Result<(), ParseError> {
+        let mut machine = Machine::new(
+            parse_program::<Test>("inc a\ninc b\njnz a -1")?,
+            Registers::default(),
+        );
+        assert_eq!(machine.run_until_repeat(), Outcome::Repeated(1));
+        assert_eq!(machine.state.get(&'b'), 1);
+        Ok(())
+    }
+
+    #[test]
+    fn test_registers() {
+        let mut registers: Registers<String> = Registers::default();
+        registers.update("x", |v| v + 3);
+        registers.set("y", -1);
+        assert_eq!(registers.get("x"), 3);
+        assert_eq!(registers.get("z"), 0);
+        assert_eq!(registers.max(), Some(3));
+        assert_eq!(registers.to_string(), "x=3 y=-1");
+    }
+}
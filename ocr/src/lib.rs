@@ -0,0 +1,238 @@
+// reads the block letters that some puzzles draw as their answer. glyphs are
+// separated by at least one unlit column, and the font is picked from the
+// height of the image: 6 rows for the small font, 10 for the large one
+//
+// errors are a plain `OcrError`, which `?` turns into whatever error type the
+// calling year uses
+use std::collections::HashSet;
+use std::error::Error;
+use std::fmt;
+
+// glyphs with their rows separated by spaces
+const SMALL: &[(char, &str)] = &[
+    ('A', ".##. #..# #..# #### #..# #..#"),
+    ('B', "###. #..# ###. #..# #..# ###."),
+    ('C', ".##. #..# #... #... #..# .##."),
+    ('E', "#### #... ###. #... #... ####"),
+    ('F', "#### #... ###. #... #... #..."),
+    ('G', ".##. #..# #... #.## #..# .###"),
+    ('H', "#..# #..# #### #..# #..# #..#"),
+    ('I', "### .#. .#. .#. .#. ###"),
+    ('J', "..## ...# ...# ...# #..# .##."),
+    ('K', "#..# #.#. ##.. #.#. #.#. #..#"),
+    ('L', "#... #... #... #... #... ####"),
+    ('O', ".##. #..# #..# #..# #..# .##."),
+    ('P', "###. #..# #..# ###. #... #..."),
+    ('R', "###. #..# #..# ###. #.#. #..#"),
+    ('S', ".### #... #... .##. ...# ###."),
+    ('U', "#..# #..# #..# #..# #..# .##."),
+    ('Y', "#...# #...# .#.#. ..#.. ..#.. ..#.."),
+    ('Z', "#### ...# ..#. .#.. #... ####"),
+];
+
+#[rustfmt::skip]
+const LARGE: &[(char, &str)] = &[
+    ('A', "..##.. .#..#. #....# #....# #....# ###### #....# #....# #....# #....#"),
+    ('B', "#####. #....# #....# #....# #####. #....# #....# #....# #....# #####."),
+    ('C', ".####. #....# #..... #..... #..... #..... #..... #..... #....# .####."),
+    ('E', "###### #..... #..... #..... #####. #..... #..... #..... #..... ######"),
+    ('F', "###### #..... #..... #..... #####. #..... #..... #..... #..... #....."),
+    ('G', ".####. #....# #..... #..... #..... #..### #....# #....# #...## .###.#"),
+    ('H', "#....# #....# #....# #....# ###### #....# #....# #....# #....# #....#"),
+    ('J', "...### ....#. ....#. ....#. ....#. ....#. ....#. #...#. #...#. .###.."),
+    ('K', "#....# #...#. #..#.. #.#... ##.... ##.... #.#... #..#.. #...#. #....#"),
+    ('L', "#..... #..... #..... #..... #..... #..... #..... #..... #..... ######"),
+    ('N', "#....# ##...# ##...# #.#..# #.#..# #..#.# #..#.# #...## #...## #....#"),
+    ('P', "#####. #....# #....# #....# #####. #..... #..... #..... #..... #....."),
+    ('R', "#####. #....# #....# #....# #####. #..#.. #...#. #...#. #....# #....#"),
+    ('X', "#....# #....# .#..#. .#..#. ..##.. ..##.. .#..#. .#..#. #....# #....#"),
+    ('Z', "###### .....# .....# ....#. ...#.. ..#... .#.... #..... #..... ######"),
+];
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum OcrError {
+    Empty,
+    // no font is this many rows high
+    Height(i64),
+    // columns where a glyph starts, counting from the leftmost lit one, and
+    // the text with `?` for each of them
+    Unrecognized { columns: Vec<i64>, text: String },
+}
+
+impl fmt::Display for OcrError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            OcrError::Empty => write!(f, "nothing to read"),
+            OcrError::Height(height) => write!(f, "no font is {} rows high", height),
+            OcrError::Unrecognized { columns, text } => write!(
+                f,
+                "unrecognized glyphs at columns {} (read `{}`)",
+                columns
+                    .iter()
+                    .map(|c| c.to_string())
+                    .collect::<Vec<_>>()
+                    .join(", "),
+                text
+            ),
+        }
+    }
+}
+
+impl Error for OcrError {}
+
+type Result<T> = std::result::Result<T, OcrError>;
+
+// an image where `#` is lit and anything else (`.` or ` `) is not
+pub fn read(image: &str) -> Result<String> {
+    let lit: HashSet<(i64, i64)> = image
+        .lines()
+        .enumerate()
+        .flat_map(|(y, line)| {
+            line.chars()
+                .enumerate()
+                .filter(|&(_, c)| c == '#')
+                .map(move |(x, _)| (x as i64, y as i64))
+        })
+        .collect();
+    read_points(lit)
+}
+
+// any set of lit (x, y) points
+pub fn read_points(lit: impl IntoIterator<Item = (i64, i64)>) -> Result<String> {
+    let lit: HashSet<(i64, i64)> = lit.into_iter().collect();
+    if lit.is_empty() {
+        return Err(OcrError::Empty);
+    }
+    let minx = lit.iter().map(|&(x, _)| x).min().unwrap();
+    let maxx = lit.iter().map(|&(x, _)| x).max().unwrap();
+    let miny = lit.iter().map(|&(_, y)| y).min().unwrap();
+    let maxy = lit.iter().map(|&(_, y)| y).max().unwrap();
+    let font = match maxy - miny + 1 {
+        6 => SMALL,
+        10 => LARGE,
+        height => return Err(OcrError::Height(height)),
+    };
+
+    let column_lit = |x: i64| (miny..=maxy).any(|y| lit.contains(&(x, y)));
+    let mut text = String::new();
+    let mut unknown = vec![];
+    let mut x = minx;
+    while x <= maxx {
+        if !column_lit(x) {
+            x += 1;
+            continue;
+        }
+        let start = x;
+        while x <= maxx && column_lit(x) {
+            x += 1;
+        }
+        let glyph = (miny..=maxy)
+            .map(|y| {
+                (start..x)
+                    .map(|x| if lit.contains(&(x, y)) { '#' } else { '.' })
+                    .collect::<String>()
+            })
+            .collect::<Vec<_>>()
+            .join(" ");
+        match font.iter().find(|(_, rows)| *rows == glyph) {
+            Some((c, _)) => text.push(*c),
+            None => {
+                text.push('?');
+                unknown.push(start - minx);
+            }
+        }
+    }
+    if !unknown.is_empty() {
+        return Err(OcrError::Unrecognized {
+            columns: unknown,
+            text,
+        });
+    }
+    Ok(text)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn render(font: &[(char, &str)], text: &str) -> String {
+        let glyphs: Vec<Vec<&str>> = text
+            .chars()
+            .map(|c| font.iter().find(|(g, _)| *g == c).unwrap().1)
+            .map(|rows| rows.split(' ').collect())
+            .collect();
+        (0..glyphs[0].len())
+            .map(|y| {
+                glyphs
+                    .iter()
+                    .map(|rows| rows[y])
+                    .collect::<Vec<_>>()
+                    .join("..")
+            })
+            .collect::<Vec<_>>()
+            .join("\n")
+    }
+
+    #[test]
+    fn test_small() -> Result<()> {
+        let alphabet: String = SMALL.iter().map(|(c, _)| c).collect();
+        assert_eq!(read(&render(SMALL, &alphabet))?, alphabet);
+        // a real screen, with padding around and between the letters
+        let screen = "
+###..####.#..#.####..##..#....
+#..#....#.#..#....#.#..#.#....
+#..#...#..####...#..#....#....
+###...#...#..#..#...#.##.#....
+#....#....#..#.#....#..#.#....
+#....####.#..#.####..###.####.";
+        assert_eq!(read(screen)?, "PZHZGL");
+        Ok(())
+    }
+
+    #[test]
+    fn test_large() -> Result<()> {
+        let alphabet: String = LARGE.iter().map(|(c, _)| c).collect();
+        assert_eq!(read(&render(LARGE, &alphabet))?, alphabet);
+        Ok(())
+    }
+
+    #[test]
+    fn test_points() -> Result<()> {
+        let image = render(SMALL, "HI");
+        let lit: Vec<(i64, i64)> = image
+            .lines()
+            .enumerate()
+            .flat_map(|(y, line)| {
+                line.chars()
+                    .enumerate()
+                    .filter(|&(_, c)| c == '#')
+                    .map(move |(x, _)| (x as i64 - 20, y as i64 + 7))
+            })
+            .collect();
+        assert_eq!(read_points(lit)?, "HI");
+        Ok(())
+    }
+
+    #[test]
+    fn test_errors() {
+        let err = |image: &str| read(image).unwrap_err().to_string();
+        assert_eq!(err(""), "nothing to read");
+        assert_eq!(err("#\n#\n#"), "no font is 3 rows high");
+        // a bar between an A and a C, and a second one after
+        let image: Vec<String> = render(SMALL, "AC")
+            .lines()
+            .map(|row| format!("{}..#..{}..#", &row[..4], &row[6..]))
+            .collect();
+        assert_eq!(
+            read(&image.join("\n")),
+            Err(OcrError::Unrecognized {
+                columns: vec![6, 15],
+                text: "A?C?".to_string()
+            })
+        );
+        assert_eq!(
+            err(&image.join("\n")),
+            "unrecognized glyphs at columns 6, 15 (read `A?C?`)"
+        );
+    }
+}